@@ -0,0 +1,392 @@
+// Addresses of P2PKH, P2SH and every witness program. P2PKH and P2SH are Base58Check
+// encoded, witness programs are Bech32 for version 0 (BIP173) and Bech32m above (BIP350)
+
+use std::fmt;
+
+use crate::{
+    hash_utils::double_hash256,
+    script::{Script, ScriptBuf, ScriptTemplate},
+};
+
+#[path = "./test/address_tests.rs"]
+#[cfg(test)]
+mod address_test;
+
+const BASE58_CHARS: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+const BECH32_CHARS: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+
+// the checksum constants the polymod of a valid string ends with
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc830a3;
+
+// longest bech32 string BIP173 allows
+const BECH32_MAX_LENGTH: usize = 90;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Bitcoin,
+    Testnet,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    fn p2pkh_prefix(self) -> u8 {
+        match self {
+            Network::Bitcoin => 0x00,
+            _ => 0x6f,
+        }
+    }
+
+    fn p2sh_prefix(self) -> u8 {
+        match self {
+            Network::Bitcoin => 0x05,
+            _ => 0xc4,
+        }
+    }
+
+    /// Human readable part of the segwit addresses, testnet and signet share theirs
+    pub fn hrp(self) -> &'static str {
+        match self {
+            Network::Bitcoin => "bc",
+            Network::Testnet | Network::Signet => "tb",
+            Network::Regtest => "bcrt",
+        }
+    }
+}
+
+/// What an address pays to, the scriptpubkey follows from it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    PubkeyHash([u8; 20]),
+    ScriptHash([u8; 20]),
+    // only P2WPKH, P2WSH and P2TR programs are accepted
+    WitnessProgram { version: u8, program: Vec<u8> },
+}
+
+/// Reasons a string is not an address of the expected network, positions are
+/// character offsets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    InvalidBase58Char(usize),
+    InvalidBech32Char(usize),
+    InvalidChecksum,
+    // the Base58Check payload is not a prefix byte and a 20 byte hash
+    InvalidLength(usize),
+    UnknownPrefix(u8),
+    MixedCase,
+    // a witness version of Bech32m in Bech32, or the other way around
+    WrongChecksumVariant { version: u8 },
+    InvalidPadding,
+    InvalidWitnessProgram { version: u8, len: usize },
+    WrongNetwork { expected: Network },
+}
+
+impl fmt::Display for AddressError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AddressError::InvalidBase58Char(pos) => {
+                write!(f, "invalid base58 character at {}", pos)
+            }
+            AddressError::InvalidBech32Char(pos) => {
+                write!(f, "invalid bech32 character at {}", pos)
+            }
+            AddressError::InvalidChecksum => write!(f, "invalid checksum"),
+            AddressError::InvalidLength(len) => {
+                write!(f, "base58check payload of {} bytes", len)
+            }
+            AddressError::UnknownPrefix(prefix) => {
+                write!(f, "unknown address prefix {:#04x}", prefix)
+            }
+            AddressError::MixedCase => write!(f, "bech32 string of mixed case"),
+            AddressError::WrongChecksumVariant { version } => {
+                write!(f, "wrong checksum variant for witness version {}", version)
+            }
+            AddressError::InvalidPadding => write!(f, "invalid bech32 padding"),
+            AddressError::InvalidWitnessProgram { version, len } => write!(
+                f,
+                "invalid witness program, version {} of {} bytes",
+                version, len
+            ),
+            AddressError::WrongNetwork { expected } => {
+                write!(f, "not an address of {:?}", expected)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Address {
+    pub network: Network,
+    pub payload: Payload,
+}
+
+impl Address {
+    /// Address of `script`, None for bare keys and multisig, OP_RETURN and the other
+    /// non standard scripts
+    pub fn from_script(script: Script, network: Network) -> Option<Address> {
+        let bytes = script.as_bytes();
+        let payload = match script.classify() {
+            ScriptTemplate::P2PKH => Payload::PubkeyHash(bytes[3..23].try_into().ok()?),
+            ScriptTemplate::P2SH => Payload::ScriptHash(bytes[2..22].try_into().ok()?),
+            // version 0 programs of another length can not be spent and have no address
+            ScriptTemplate::P2WPKH
+            | ScriptTemplate::P2WSH
+            | ScriptTemplate::P2TR
+            | ScriptTemplate::Anchor
+            | ScriptTemplate::WitnessUnknown { version: 1..=16, .. } => {
+                let (version, program) = script.witness_program()?;
+                Payload::WitnessProgram {
+                    version,
+                    program: program.to_vec(),
+                }
+            }
+            _ => return None,
+        };
+        Some(Address { network, payload })
+    }
+
+    /// Parses an address of `network`. Bech32 addresses are accepted in upper case,
+    /// Base58 prefixes are the same for every test network
+    pub fn parse(address: &str, network: Network) -> Result<Address, AddressError> {
+        let has_prefix = |prefix: &str| {
+            address
+                .get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        };
+
+        let payload = if has_prefix(&format!("{}1", network.hrp())) {
+            decode_segwit(address, network.hrp())?
+        } else if ["bc1", "tb1", "bcrt1"].into_iter().any(has_prefix) {
+            return Err(AddressError::WrongNetwork { expected: network });
+        } else {
+            decode_base58check_payload(address, network)?
+        };
+        Ok(Address { network, payload })
+    }
+
+    pub fn script_pubkey(&self) -> ScriptBuf {
+        let bytes = match &self.payload {
+            Payload::PubkeyHash(hash) => {
+                [&[0x76, 0xa9, 0x14], hash.as_slice(), &[0x88, 0xac]].concat()
+            }
+            Payload::ScriptHash(hash) => [&[0xa9, 0x14], hash.as_slice(), &[0x87]].concat(),
+            Payload::WitnessProgram { version, program } => {
+                // OP_0, or OP_1 to OP_16
+                let version_op = if *version == 0 { 0x00 } else { 0x50 + version };
+                [&[version_op, program.len() as u8], program.as_slice()].concat()
+            }
+        };
+        ScriptBuf::from_bytes(bytes)
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.payload {
+            Payload::PubkeyHash(hash) => {
+                write!(f, "{}", encode_base58check(self.network.p2pkh_prefix(), hash))
+            }
+            Payload::ScriptHash(hash) => {
+                write!(f, "{}", encode_base58check(self.network.p2sh_prefix(), hash))
+            }
+            Payload::WitnessProgram { version, program } => {
+                let mut data = vec![*version];
+                data.extend(convert_bits(program, 8, 5, true).expect("bytes always convert"));
+                let checksum = if *version == 0 { BECH32_CONST } else { BECH32M_CONST };
+                write!(f, "{}", encode_bech32(self.network.hrp(), &data, checksum))
+            }
+        }
+    }
+}
+
+fn encode_base58check(prefix: u8, hash: &[u8]) -> String {
+    let mut bytes = [&[prefix], hash].concat();
+    let checksum = double_hash256(&bytes);
+    bytes.extend_from_slice(&checksum[..4]);
+
+    // base 256 to base 58, digits are kept least significant first
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in bytes.iter() {
+        let mut carry = *byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    // every leading zero byte is a leading '1'
+    let zeros = bytes.iter().take_while(|byte| **byte == 0).count();
+    std::iter::repeat_n(BASE58_CHARS[0], zeros)
+        .chain(digits.iter().rev().map(|digit| BASE58_CHARS[*digit as usize]))
+        .map(char::from)
+        .collect()
+}
+
+// the prefix byte and the hash of a Base58Check address
+fn decode_base58check(address: &str) -> Result<(u8, [u8; 20]), AddressError> {
+    // base 58 to base 256, bytes are kept least significant first
+    let mut bytes: Vec<u8> = Vec::new();
+    for (pos, char) in address.bytes().enumerate() {
+        let mut carry = BASE58_CHARS
+            .iter()
+            .position(|c| *c == char)
+            .ok_or(AddressError::InvalidBase58Char(pos))? as u32;
+        for byte in bytes.iter_mut() {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = address.bytes().take_while(|char| *char == BASE58_CHARS[0]).count();
+    bytes.extend(std::iter::repeat_n(0, zeros));
+    bytes.reverse();
+
+    if bytes.len() != 25 {
+        return Err(AddressError::InvalidLength(bytes.len().saturating_sub(4)));
+    }
+    let (payload, checksum) = bytes.split_at(21);
+    if double_hash256(&payload.to_vec())[..4] != *checksum {
+        return Err(AddressError::InvalidChecksum);
+    }
+    Ok((payload[0], payload[1..].try_into().expect("21 byte payload")))
+}
+
+fn decode_base58check_payload(address: &str, network: Network) -> Result<Payload, AddressError> {
+    let (prefix, hash) = decode_base58check(address)?;
+    if prefix == network.p2pkh_prefix() {
+        Ok(Payload::PubkeyHash(hash))
+    } else if prefix == network.p2sh_prefix() {
+        Ok(Payload::ScriptHash(hash))
+    } else if [0x00, 0x05, 0x6f, 0xc4].contains(&prefix) {
+        Err(AddressError::WrongNetwork { expected: network })
+    } else {
+        Err(AddressError::UnknownPrefix(prefix))
+    }
+}
+
+fn bech32_polymod(values: impl Iterator<Item = u8>) -> u32 {
+    const GENERATORS: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    values.fold(1, |chk, value| {
+        let top = chk >> 25;
+        let chk = (chk & 0x1ffffff) << 5 ^ value as u32;
+        (0..5)
+            .filter(|bit| (top >> bit) & 1 == 1)
+            .fold(chk, |chk, bit| chk ^ GENERATORS[bit])
+    })
+}
+
+// the high bits of every hrp character, a zero, then the low bits
+fn hrp_expand(hrp: &str) -> impl Iterator<Item = u8> + '_ {
+    let bytes = hrp.as_bytes();
+    bytes
+        .iter()
+        .map(|byte| byte >> 5)
+        .chain([0])
+        .chain(bytes.iter().map(|byte| byte & 0x1f))
+}
+
+fn encode_bech32(hrp: &str, data: &[u8], checksum_const: u32) -> String {
+    let polymod = bech32_polymod(
+        hrp_expand(hrp)
+            .chain(data.iter().copied())
+            .chain([0; 6]),
+    ) ^ checksum_const;
+    let checksum = (0..6).map(|idx| (polymod >> (5 * (5 - idx))) as u8 & 0x1f);
+
+    let mut encoded = format!("{}1", hrp);
+    encoded.extend(
+        data.iter()
+            .copied()
+            .chain(checksum)
+            .map(|value| BECH32_CHARS[value as usize] as char),
+    );
+    encoded
+}
+
+// the 5 bit values between the separator and the checksum, and the checksum constant
+// they verify with
+fn decode_bech32(address: &str, hrp: &str) -> Result<(Vec<u8>, u32), AddressError> {
+    if address.bytes().any(|c| c.is_ascii_lowercase())
+        && address.bytes().any(|c| c.is_ascii_uppercase())
+    {
+        return Err(AddressError::MixedCase);
+    }
+    let address = address.to_ascii_lowercase();
+    // the hrp and the separator are checked by the caller
+    let data_part = &address[hrp.len() + 1..];
+    if address.len() > BECH32_MAX_LENGTH || data_part.len() < 6 {
+        return Err(AddressError::InvalidChecksum);
+    }
+
+    let values = data_part
+        .bytes()
+        .enumerate()
+        .map(|(pos, char)| {
+            BECH32_CHARS
+                .iter()
+                .position(|c| *c == char)
+                .map(|value| value as u8)
+                .ok_or(AddressError::InvalidBech32Char(hrp.len() + 1 + pos))
+        })
+        .collect::<Result<Vec<u8>, AddressError>>()?;
+
+    let checksum_const = bech32_polymod(hrp_expand(hrp).chain(values.iter().copied()));
+    if checksum_const != BECH32_CONST && checksum_const != BECH32M_CONST {
+        return Err(AddressError::InvalidChecksum);
+    }
+    Ok((values[..values.len() - 6].to_vec(), checksum_const))
+}
+
+fn decode_segwit(address: &str, hrp: &str) -> Result<Payload, AddressError> {
+    let (values, checksum_const) = decode_bech32(address, hrp)?;
+    let (&version, data) = values
+        .split_first()
+        .ok_or(AddressError::InvalidWitnessProgram { version: 0, len: 0 })?;
+    let program = convert_bits(data, 5, 8, false).ok_or(AddressError::InvalidPadding)?;
+
+    let expected_const = if version == 0 { BECH32_CONST } else { BECH32M_CONST };
+    if checksum_const != expected_const {
+        return Err(AddressError::WrongChecksumVariant { version });
+    }
+    match (version, program.len()) {
+        // BIP173 leaves the programs of later versions to their soft forks
+        (0, 20) | (0, 32) | (1..=16, 2..=40) => Ok(Payload::WitnessProgram { version, program }),
+        (version, len) => Err(AddressError::InvalidWitnessProgram { version, len }),
+    }
+}
+
+// regroups `from` bit values into `to` bit values, without padding the leftover bits
+// have to be fewer than `from` and all zero
+fn convert_bits(data: &[u8], from: u32, to: u32, pad: bool) -> Option<Vec<u8>> {
+    let max_value = (1u32 << to) - 1;
+    let mut acc: u32 = 0;
+    let mut bits: u32 = 0;
+    let mut converted = Vec::new();
+    for value in data.iter() {
+        acc = (acc << from) | *value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            converted.push(((acc >> bits) & max_value) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            converted.push(((acc << (to - bits)) & max_value) as u8);
+        }
+    } else if bits >= from || (acc << (to - bits)) & max_value != 0 {
+        return None;
+    }
+    Some(converted)
+}
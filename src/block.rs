@@ -0,0 +1,158 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use crate::{
+    encode::{Decodable, DecodeError, Encodable},
+    hash_types::BlockHash,
+    hash_utils::double_hash256,
+};
+
+#[path = "./test/block_tests.rs"]
+#[cfg(test)]
+mod block_test;
+
+pub const HEADER_SIZE: usize = 80;
+
+// the highest locktime in the mempool snapshot is 834637, so its tip is just below
+pub const DEFAULT_BLOCK_HEIGHT: u32 = 834_638;
+
+// block 840000, the fourth halving, times of other heights are estimated from it
+const HALVING_HEIGHT: u32 = 840_000;
+const HALVING_TIME: u32 = 1_713_571_767;
+const TARGET_SPACING: i64 = 600;
+
+/// Where the block being built goes in the chain, locktimes are checked against it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockContext {
+    pub height: u32,
+    // median of the timestamps of the previous 11 blocks, BIP113 checks time locks
+    // against it instead of the block's own timestamp
+    pub median_time_past: u32,
+}
+
+impl Default for BlockContext {
+    fn default() -> Self {
+        BlockContext {
+            height: DEFAULT_BLOCK_HEIGHT,
+            median_time_past: estimated_median_time_past(DEFAULT_BLOCK_HEIGHT),
+        }
+    }
+}
+
+impl BlockContext {
+    /// Reads `--height <height>` and `--median-time-past <unix time>` from the command
+    /// line arguments, without the program name. The height falls back to
+    /// DEFAULT_BLOCK_HEIGHT and the median time past to `estimated_median_time_past`
+    /// of the height
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Self, ContextArgError> {
+        let mut height = None;
+        let mut median_time_past = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let target = match arg.as_str() {
+                "--height" => &mut height,
+                "--median-time-past" => &mut median_time_past,
+                _ => return Err(ContextArgError::UnknownArgument(arg)),
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| ContextArgError::MissingValue(arg.clone()))?;
+            *target = Some(
+                value
+                    .parse::<u32>()
+                    .map_err(|_| ContextArgError::InvalidValue { arg, value })?,
+            );
+        }
+
+        let height = height.unwrap_or(DEFAULT_BLOCK_HEIGHT);
+        Ok(BlockContext {
+            height,
+            median_time_past: median_time_past
+                .unwrap_or_else(|| estimated_median_time_past(height)),
+        })
+    }
+}
+
+/// Median time past of the block at `height` on a chain which kept the 600 second
+/// target around the fourth halving. The median of the previous 11 blocks is the time
+/// of the block 6 below `height`
+pub fn estimated_median_time_past(height: u32) -> u32 {
+    let blocks = height as i64 - 6 - HALVING_HEIGHT as i64;
+    (HALVING_TIME as i64 + blocks * TARGET_SPACING).clamp(0, u32::MAX as i64) as u32
+}
+
+/// Command line arguments `BlockContext::from_args` could not read
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ContextArgError {
+    UnknownArgument(String),
+    MissingValue(String),
+    InvalidValue { arg: String, value: String },
+}
+
+impl fmt::Display for ContextArgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContextArgError::UnknownArgument(arg) => write!(f, "unknown argument {}", arg),
+            ContextArgError::MissingValue(arg) => write!(f, "{} needs a value", arg),
+            ContextArgError::InvalidValue { arg, value } => {
+                write!(f, "invalid value {} for {}", value, arg)
+            }
+        }
+    }
+}
+
+/// The 80 byte block header, the merkle root is in the internal byte order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockHeader {
+    pub version: u32,
+    pub prev_blockhash: BlockHash,
+    pub merkle_root: [u8; 32],
+    pub time: u32,
+    // compact encoding of the target
+    pub bits: u32,
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    /// Serialized header, written into a fixed buffer so mining does not allocate per nonce
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0u8; HEADER_SIZE];
+        self.consensus_encode(&mut &mut bytes[..])
+            .expect("a header is exactly 80 bytes");
+        bytes
+    }
+
+    pub fn block_hash(&self) -> BlockHash {
+        BlockHash::from_byte_array(
+            double_hash256(&self.to_bytes().to_vec())
+                .try_into()
+                .expect("sha256 hashes are 32 bytes"),
+        )
+    }
+}
+
+impl Encodable for BlockHeader {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.version.consensus_encode(writer)?
+            + self.prev_blockhash.consensus_encode(writer)?
+            + self.merkle_root.consensus_encode(writer)?
+            + self.time.consensus_encode(writer)?
+            + self.bits.consensus_encode(writer)?
+            + self.nonce.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for BlockHeader {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        Ok(BlockHeader {
+            version: Decodable::consensus_decode(reader)?,
+            prev_blockhash: Decodable::consensus_decode(reader)?,
+            merkle_root: Decodable::consensus_decode(reader)?,
+            time: Decodable::consensus_decode(reader)?,
+            bits: Decodable::consensus_decode(reader)?,
+            nonce: Decodable::consensus_decode(reader)?,
+        })
+    }
+}
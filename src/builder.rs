@@ -0,0 +1,322 @@
+// Builds and signs transactions in the schema of the mempool json, so test mempools
+// can be made of transactions with valid signatures instead of hand written files
+
+use std::fmt;
+
+use libsecp256k1::{sign, Message, PublicKey, SecretKey};
+
+use crate::{
+    hash_utils::pk_hash,
+    schnorr::{sign_schnorr, tweak_secret_key, xonly_public_key},
+    script::{ScriptBuf, ScriptTemplate},
+    sighash::{
+        legacy_sighash, segwit_v0_sighash, taproot_sighash, PrecomputedTransactionData,
+        SighashError, SIGHASH_DEFAULT, SIGHASH_SINGLE,
+    },
+    transaction::{OutPoint, Pubkey, Transaction, Vin, Witness},
+};
+
+#[path = "./test/builder_tests.rs"]
+#[cfg(test)]
+mod builder_test;
+
+// replaceable and still bound by the locktime, what most wallets of the feed use
+pub const DEFAULT_SEQUENCE: u32 = 0xfffffffd;
+
+/// Reasons an input could not be signed, the transaction is left as it was
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignError {
+    Sighash(SighashError),
+    // only P2PKH, P2WPKH and P2TR key path spends can be signed
+    UnsupportedScript { vin: usize, template: ScriptTemplate },
+    // the key does not belong to the spent output
+    KeyMismatch { vin: usize },
+}
+
+impl fmt::Display for SignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignError::Sighash(SighashError::InputOutOfRange { idx, inputs }) => {
+                write!(f, "input {} out of range, {} inputs", idx, inputs)
+            }
+            SignError::Sighash(SighashError::InvalidSighashType(sighash_type)) => {
+                write!(f, "invalid sighash type {:#04x}", sighash_type)
+            }
+            SignError::Sighash(SighashError::SingleWithoutOutput { idx }) => {
+                write!(f, "SIGHASH_SINGLE without an output for input {}", idx)
+            }
+            SignError::UnsupportedScript { vin, template } => {
+                write!(f, "vin {} spends an unsupported {:?} output", vin, template)
+            }
+            SignError::KeyMismatch { vin } => {
+                write!(f, "key does not match the output spent by vin {}", vin)
+            }
+        }
+    }
+}
+
+impl From<SighashError> for SignError {
+    fn from(err: SighashError) -> Self {
+        SignError::Sighash(err)
+    }
+}
+
+/// Unsigned transaction under construction, `Transaction::builder` starts one
+pub struct TransactionBuilder {
+    version: u32,
+    locktime: u32,
+    vin: Vec<Vin>,
+    vout: Vec<Pubkey>,
+}
+
+impl Default for TransactionBuilder {
+    fn default() -> Self {
+        TransactionBuilder {
+            version: 2,
+            locktime: 0,
+            vin: Vec::new(),
+            vout: Vec::new(),
+        }
+    }
+}
+
+impl TransactionBuilder {
+    pub fn version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn locktime(mut self, locktime: u32) -> Self {
+        self.locktime = locktime;
+        self
+    }
+
+    /// Spends `outpoint`, the prevout is what every signature hash commits to
+    pub fn add_input(mut self, outpoint: OutPoint, prevout: Pubkey) -> Self {
+        self.vin.push(Vin {
+            txid: outpoint.txid,
+            vout: outpoint.vout,
+            prevout,
+            scriptsig: ScriptBuf::new(),
+            scriptsig_asm: String::new(),
+            witness: None,
+            is_coinbase: false,
+            sequence: DEFAULT_SEQUENCE,
+            inner_redeemscript_asm: None,
+            inner_witnessscript_asm: None,
+        });
+        self
+    }
+
+    /// Sequence of the last input added
+    pub fn sequence(mut self, sequence: u32) -> Self {
+        let vin = self.vin.last_mut().expect("sequence is set after add_input");
+        vin.sequence = sequence;
+        self
+    }
+
+    pub fn add_output(mut self, scriptpubkey: ScriptBuf, value: u64) -> Self {
+        self.vout.push(Pubkey::new(scriptpubkey, value));
+        self
+    }
+
+    pub fn build(self) -> Transaction {
+        let mut tx = Transaction {
+            sanity_hash: None,
+            version: self.version,
+            locktime: self.locktime,
+            vin: self.vin,
+            vout: self.vout,
+            weight: None,
+            sigop_cost: None,
+            tx_fee: None,
+        };
+        tx.assign_weight();
+        tx.assign_sigop_cost();
+        tx.assign_tx_fee();
+        tx.sanity_hash = Some(tx.compute_sanity_hash());
+        tx
+    }
+}
+
+impl Transaction {
+    pub fn builder() -> TransactionBuilder {
+        TransactionBuilder::default()
+    }
+
+    /// Signs input `idx` with `secret_key`, which has to own the spent output. P2PKH
+    /// and P2WPKH inputs take a low-s DER signature, P2TR outputs are spent by the key
+    /// path with the key tweaked for no script tree. Inputs signed before with
+    /// SIGHASH_ALL have to be signed again when outputs change afterwards
+    pub fn sign_input(
+        &mut self,
+        idx: usize,
+        secret_key: &SecretKey,
+        sighash_type: u32,
+    ) -> Result<(), SignError> {
+        let (scriptsig, witness) = match input_signature(self, idx, secret_key, sighash_type)? {
+            InputSignature::Ecdsa { pubkey, signature }
+                if self.vin[idx].prevout.classify() == ScriptTemplate::P2PKH =>
+            {
+                (p2pkh_scriptsig(&signature, &pubkey), None)
+            }
+            InputSignature::Ecdsa { pubkey, signature } => {
+                (ScriptBuf::new(), Some(Witness(vec![signature, pubkey])))
+            }
+            InputSignature::Schnorr(signature) => {
+                (ScriptBuf::new(), Some(Witness(vec![signature])))
+            }
+        };
+
+        let vin = &mut self.vin[idx];
+        vin.scriptsig_asm = scriptsig.as_script().to_asm();
+        vin.scriptsig = scriptsig;
+        vin.witness = witness;
+
+        // the signatures change the weight and the witness sigops
+        self.assign_weight();
+        self.assign_sigop_cost();
+        self.sanity_hash = Some(self.compute_sanity_hash());
+        Ok(())
+    }
+
+    /// Signs every input with the key at the same position
+    pub fn sign(&mut self, secret_keys: &[SecretKey], sighash_type: u32) -> Result<(), SignError> {
+        for (idx, secret_key) in secret_keys.iter().enumerate() {
+            self.sign_input(idx, secret_key, sighash_type)?;
+        }
+        Ok(())
+    }
+
+    /// Outpoint of output `vout` of this transaction, to spend it from a child
+    pub fn outpoint(&self, vout: u32) -> OutPoint {
+        OutPoint {
+            txid: self.txid(),
+            vout,
+        }
+    }
+}
+
+/// Signature of input `idx` and the key it verifies with, before it is placed in the
+/// scriptsig or the witness
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputSignature {
+    // DER encoded with the sighash type byte, the pubkey is serialized as it was hashed
+    Ecdsa { pubkey: Vec<u8>, signature: Vec<u8> },
+    // key path signature, the sighash type byte only follows when not SIGHASH_DEFAULT
+    Schnorr(Vec<u8>),
+}
+
+/// Signs input `idx` of `tx` with `secret_key`, the prevouts on the transaction are
+/// the outputs being spent. See `Transaction::sign_input` for the supported outputs
+pub fn input_signature(
+    tx: &Transaction,
+    idx: usize,
+    secret_key: &SecretKey,
+    sighash_type: u32,
+) -> Result<InputSignature, SignError> {
+    let vin = tx.vin.get(idx).ok_or(SighashError::InputOutOfRange {
+        idx,
+        inputs: tx.vin.len(),
+    })?;
+    let scriptpubkey = vin.prevout.scriptpubkey.as_bytes();
+
+    match vin.prevout.classify() {
+        ScriptTemplate::P2PKH => {
+            check_ecdsa_sighash_type(tx, idx, sighash_type)?;
+            let pubkey = matching_pubkey(secret_key, &scriptpubkey[3..23])
+                .ok_or(SignError::KeyMismatch { vin: idx })?;
+            // the spent script is the script code, there is no OP_CODESEPARATOR in it
+            let sighash = legacy_sighash(tx, idx, scriptpubkey, sighash_type);
+            Ok(InputSignature::Ecdsa {
+                pubkey,
+                signature: ecdsa_signature(&sighash, secret_key, sighash_type),
+            })
+        }
+        ScriptTemplate::P2WPKH => {
+            check_ecdsa_sighash_type(tx, idx, sighash_type)?;
+            let pubkey = PublicKey::from_secret_key(secret_key)
+                .serialize_compressed()
+                .to_vec();
+            // only compressed keys are standard in witnesses
+            if pk_hash(&pubkey) != scriptpubkey[2..] {
+                return Err(SignError::KeyMismatch { vin: idx });
+            }
+            let script_code = [&[0x76, 0xa9, 0x14], &scriptpubkey[2..], &[0x88, 0xac]].concat();
+            let sighash = segwit_v0_sighash(
+                tx,
+                idx,
+                &script_code,
+                vin.prevout.value,
+                sighash_type,
+                &PrecomputedTransactionData::new(tx),
+            );
+            Ok(InputSignature::Ecdsa {
+                pubkey,
+                signature: ecdsa_signature(&sighash, secret_key, sighash_type),
+            })
+        }
+        ScriptTemplate::P2TR => {
+            let tweaked_key = tweak_secret_key(secret_key, None)
+                .filter(|key| xonly_public_key(key) == scriptpubkey[2..])
+                .ok_or(SignError::KeyMismatch { vin: idx })?;
+            let precomputed = PrecomputedTransactionData::new(tx);
+            let sighash: [u8; 32] =
+                taproot_sighash(tx, idx, sighash_type, None, None, &precomputed)?
+                    .try_into()
+                    .expect("sha256 hashes are 32 bytes");
+            let mut signature =
+                sign_schnorr(&sighash, &tweaked_key, &rand::random::<[u8; 32]>()).to_vec();
+            // SIGHASH_DEFAULT is implied by a 64 byte signature
+            if sighash_type != SIGHASH_DEFAULT {
+                signature.push(sighash_type as u8);
+            }
+            Ok(InputSignature::Schnorr(signature))
+        }
+        template => Err(SignError::UnsupportedScript { vin: idx, template }),
+    }
+}
+
+/// Scriptsig spending a P2PKH output: the signature and the public key
+pub fn p2pkh_scriptsig(signature: &[u8], pubkey: &[u8]) -> ScriptBuf {
+    ScriptBuf::from_bytes([push(signature), push(pubkey)].concat())
+}
+
+// ECDSA signatures only commit to the defined types, and SIGHASH_SINGLE without its
+// output would sign the constant 1 of the legacy sighash
+fn check_ecdsa_sighash_type(
+    tx: &Transaction,
+    idx: usize,
+    sighash_type: u32,
+) -> Result<(), SighashError> {
+    if !matches!(sighash_type, 0x01..=0x03 | 0x81..=0x83) {
+        return Err(SighashError::InvalidSighashType(sighash_type));
+    }
+    if sighash_type & 0x1f == SIGHASH_SINGLE && idx >= tx.vout.len() {
+        return Err(SighashError::SingleWithoutOutput { idx });
+    }
+    Ok(())
+}
+
+// the serialization of the key whose hash160 is `pubkeyhash`, P2PKH outputs can be
+// locked to an uncompressed key as well
+fn matching_pubkey(secret_key: &SecretKey, pubkeyhash: &[u8]) -> Option<Vec<u8>> {
+    let pubkey = PublicKey::from_secret_key(secret_key);
+    [pubkey.serialize_compressed().to_vec(), pubkey.serialize().to_vec()]
+        .into_iter()
+        .find(|serialized| pk_hash(serialized) == pubkeyhash)
+}
+
+// DER signature followed by the sighash type byte
+fn ecdsa_signature(sighash: &[u8], secret_key: &SecretKey, sighash_type: u32) -> Vec<u8> {
+    let msg = Message::parse_slice(sighash).expect("sighashes are 32 bytes");
+    let (signature, _) = sign(&msg, secret_key);
+    let mut signature = signature.serialize_der().as_ref().to_vec();
+    signature.push(sighash_type as u8);
+    signature
+}
+
+// minimal push of a signature or a public key, both are below 76 bytes
+fn push(data: &[u8]) -> Vec<u8> {
+    [&[data.len() as u8], data].concat()
+}
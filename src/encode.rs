@@ -0,0 +1,193 @@
+use std::io::{self, Read, Write};
+
+#[path = "./test/encode_tests.rs"]
+#[cfg(test)]
+mod encode_test;
+
+// compact sizes above this are never valid, same limit as MAX_SIZE in bitcoin core
+pub const MAX_COMPACT_SIZE: u64 = 0x02000000;
+
+/// Types with a consensus encoding, integers are little endian and lengths are
+/// compact sizes, the same rules as serialize.h in bitcoin core
+pub trait Encodable {
+    /// Writes the encoding to `writer` and returns the number of bytes written
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize>;
+}
+
+pub trait Decodable: Sized {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError>;
+}
+
+/// Reasons an encoding can not be decoded. Running out of bytes shows up as
+/// Io(UnexpectedEof)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    Io(io::ErrorKind),
+    NonCanonicalCompactSize,
+    OversizedCompactSize(u64),
+    // the segwit marker is set but every witness is empty
+    SuperfluousWitness,
+    UnknownOptionalData(u8),
+}
+
+impl From<io::Error> for DecodeError {
+    fn from(err: io::Error) -> Self {
+        DecodeError::Io(err.kind())
+    }
+}
+
+/// Encoding of `value` as bytes
+pub fn serialize<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+    let mut bytes: Vec<u8> = Vec::new();
+    value
+        .consensus_encode(&mut bytes)
+        .expect("writing to a Vec never fails");
+    bytes
+}
+
+macro_rules! impl_int_encodable {
+    ($($int: ty), +) => {
+        $(
+            impl Encodable for $int {
+                fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+                    writer.write_all(&self.to_le_bytes())?;
+                    Ok(std::mem::size_of::<$int>())
+                }
+            }
+
+            impl Decodable for $int {
+                fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+                    let mut bytes = [0u8; std::mem::size_of::<$int>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$int>::from_le_bytes(bytes))
+                }
+            }
+        )+
+    };
+}
+
+impl_int_encodable!(u8, u16, u32, u64, i32, i64);
+
+/// Length prefix of lists and byte strings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactSize(pub u64);
+
+impl Encodable for CompactSize {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        match self.0 {
+            0..=0xfc => (self.0 as u8).consensus_encode(writer),
+            0xfd..=0xffff => {
+                writer.write_all(&[0xfd])?;
+                Ok(1 + (self.0 as u16).consensus_encode(writer)?)
+            }
+            0x10000..=0xffffffff => {
+                writer.write_all(&[0xfe])?;
+                Ok(1 + (self.0 as u32).consensus_encode(writer)?)
+            }
+            _ => {
+                writer.write_all(&[0xff])?;
+                Ok(1 + self.0.consensus_encode(writer)?)
+            }
+        }
+    }
+}
+
+impl Decodable for CompactSize {
+    // compact sizes have to use the shortest encoding of their value
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        let (size, min) = match u8::consensus_decode(reader)? {
+            0xfd => (u16::consensus_decode(reader)? as u64, 0xfd),
+            0xfe => (u32::consensus_decode(reader)? as u64, 0x10000),
+            0xff => (u64::consensus_decode(reader)?, 0x100000000),
+            val => (val as u64, 0),
+        };
+        if size < min {
+            return Err(DecodeError::NonCanonicalCompactSize);
+        }
+        if size > MAX_COMPACT_SIZE {
+            return Err(DecodeError::OversizedCompactSize(size));
+        }
+        Ok(CompactSize(size))
+    }
+}
+
+// fixed size byte strings like hashes go without a length
+impl<const N: usize> Encodable for [u8; N] {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(self)?;
+        Ok(N)
+    }
+}
+
+impl<const N: usize> Decodable for [u8; N] {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        let mut bytes = [0u8; N];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+// variable length byte strings, e.g. scripts, are prefixed with their length
+impl Encodable for [u8] {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let len = CompactSize(self.len() as u64).consensus_encode(writer)?;
+        writer.write_all(self)?;
+        Ok(len + self.len())
+    }
+}
+
+impl Encodable for Vec<u8> {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.as_slice().consensus_encode(writer)
+    }
+}
+
+impl Decodable for Vec<u8> {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        let len = CompactSize::consensus_decode(reader)?.0 as usize;
+        // the length is not trusted for the allocation, a short input runs out first
+        let mut bytes: Vec<u8> = Vec::new();
+        reader.take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(DecodeError::Io(io::ErrorKind::UnexpectedEof));
+        }
+        Ok(bytes)
+    }
+}
+
+/// Reader over a byte slice which knows how many bytes were consumed, a failed read
+/// consumes nothing so the position points at the value which could not be read
+pub struct SliceReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> SliceReader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        SliceReader { bytes, position: 0 }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+}
+
+impl Read for SliceReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let count = buf.len().min(self.remaining());
+        buf[..count].copy_from_slice(&self.bytes[self.position..self.position + count]);
+        self.position += count;
+        Ok(count)
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        if buf.len() > self.remaining() {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof));
+        }
+        self.read(buf).map(|_| ())
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    encode::{Decodable, DecodeError, Encodable},
+    str_utils::get_hex_bytes,
+};
+
+#[path = "./test/hash_types_tests.rs"]
+#[cfg(test)]
+mod hash_types_test;
+
+/// Reasons a hash can not be parsed from its hex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HashParseError {
+    InvalidHex,
+    // number of hex characters found, a hash has 64
+    InvalidLength(usize),
+}
+
+impl fmt::Display for HashParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashParseError::InvalidHex => write!(f, "invalid hex in hash"),
+            HashParseError::InvalidLength(len) => {
+                write!(f, "hash has {} hex characters instead of 64", len)
+            }
+        }
+    }
+}
+
+// double sha256 hashes are kept in the order they come out of the hash function and
+// the order they are serialized in. Bitcoin shows them reversed, which is what
+// Display and FromStr use
+macro_rules! hash_newtype {
+    ($($(#[$doc: meta])* $name: ident), +) => {
+        $(
+            $(#[$doc])*
+            #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+            pub struct $name([u8; 32]);
+
+            impl $name {
+                pub const fn from_byte_array(bytes: [u8; 32]) -> Self {
+                    $name(bytes)
+                }
+
+                pub const fn all_zeros() -> Self {
+                    $name([0u8; 32])
+                }
+
+                pub fn as_byte_array(&self) -> &[u8; 32] {
+                    &self.0
+                }
+
+                pub fn to_byte_array(self) -> [u8; 32] {
+                    self.0
+                }
+            }
+
+            impl fmt::Display for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    for val in self.0.iter().rev() {
+                        write!(f, "{:02x}", val)?;
+                    }
+                    Ok(())
+                }
+            }
+
+            impl fmt::Debug for $name {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    write!(f, "{}({})", stringify!($name), self)
+                }
+            }
+
+            impl FromStr for $name {
+                type Err = HashParseError;
+
+                fn from_str(s: &str) -> Result<Self, Self::Err> {
+                    if s.len() != 64 {
+                        return Err(HashParseError::InvalidLength(s.len()));
+                    }
+                    if !s.is_ascii() {
+                        return Err(HashParseError::InvalidHex);
+                    }
+                    let mut bytes: [u8; 32] = get_hex_bytes(s)
+                        .map_err(|_| HashParseError::InvalidHex)?
+                        .try_into()
+                        .map_err(|_| HashParseError::InvalidHex)?;
+                    bytes.reverse();
+                    Ok($name(bytes))
+                }
+            }
+
+            // the mempool json has them in display order
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let hex = String::deserialize(deserializer)?;
+                    hex.parse().map_err(de::Error::custom)
+                }
+            }
+
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl Encodable for $name {
+                fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+                    self.0.consensus_encode(writer)
+                }
+            }
+
+            impl Decodable for $name {
+                fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+                    Ok($name(Decodable::consensus_decode(reader)?))
+                }
+            }
+        )+
+    };
+}
+
+hash_newtype!(
+    /// Double sha256 of a transaction without its witness
+    Txid,
+    /// Double sha256 of a transaction with its witness, the txid for legacy ones
+    Wtxid,
+    /// Double sha256 of a block header
+    BlockHash
+);
@@ -12,6 +12,16 @@ pub fn double_hash256 (data: &Vec<u8>) -> Vec<u8> {
     hash256(&hash256(data)).to_vec()
 }
 
+// BIP340 tagged hash, sha256(sha256(tag) || sha256(tag) || data)
+pub fn tagged_hash (tag: &str, data: &[u8]) -> Vec<u8> {
+    let tag_hash = hash256(&tag.as_bytes().to_vec());
+    let mut hasher = Sha256::new();
+    hasher.update(&tag_hash);
+    hasher.update(&tag_hash);
+    hasher.update(data);
+    hasher.finalize().to_vec()
+}
+
 pub fn hash_ripemd (data: &Vec<u8>) -> Vec<u8> {
     let mut hasher = Ripemd160::new();
     hasher.update(data);
@@ -1,221 +1,568 @@
-use std::{usize, vec};
-
 use crate::{
     opcodes::{
         all_opcodes::{
-            OP_CHECKSIG, OP_DUP, OP_EQUAL, OP_HASH160, OP_PUSHBYTES,
-            OP_PUSHDATA1, OP_PUSHDATA2, OP_PUSHDATA4, OP_RETURN,
+            OP_0, OP_1, OP_CHECKMULTISIG, OP_CHECKSIG, OP_DUP, OP_EQUAL, OP_HASH160,
+            OP_PUSHDATA1, OP_PUSHDATA2, OP_PUSHNUM, OP_RETURN,
         },
         Opcode,
     },
+    script::{Instruction, Instructions, Script, ScriptError},
+    sigcache::signature_cache,
+    sighash::{legacy_sighash, segwit_v0_sighash, PrecomputedTransactionData},
     stack::Stack,
     transaction::Transaction,
 };
 
-use libsecp256k1::{verify, Message, PublicKey, PublicKeyFormat, Signature};
 use ripemd::{Digest as Ripemd160Digest, Ripemd160};
 use sha2::Sha256;
 
 use super::opcodes::all_opcodes::OP_EQUALVERIFY;
 
+#[path = "./test/interpreter_tests.rs"]
+#[cfg(test)]
+mod interpreter_test;
+
+// maximum number of elements on the stack during execution
+pub const MAX_STACK_SIZE: usize = 1000;
+
+/// Script verification flags, same meaning as the SCRIPT_VERIFY_* flags of bitcoin core.
+/// Consensus rules are always enforced, the flags add policy on top of them. Core's
+/// MINIMALIF and CONST_SCRIPTCODE are left out, their rules are about OP_IF and
+/// OP_CODESEPARATOR which the interpreter does not run. The lock time, upgradable NOP
+/// and taproot flags only ever see scripts the interpreter rejects or reports as
+/// unsupported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyFlags(pub u32);
+
+impl VerifyFlags {
+    pub const NONE: VerifyFlags = VerifyFlags(0);
+    pub const P2SH: VerifyFlags = VerifyFlags(1 << 0);
+    pub const STRICTENC: VerifyFlags = VerifyFlags(1 << 1);
+    pub const DERSIG: VerifyFlags = VerifyFlags(1 << 2);
+    pub const LOW_S: VerifyFlags = VerifyFlags(1 << 3);
+    pub const NULLDUMMY: VerifyFlags = VerifyFlags(1 << 4);
+    pub const SIGPUSHONLY: VerifyFlags = VerifyFlags(1 << 5);
+    pub const MINIMALDATA: VerifyFlags = VerifyFlags(1 << 6);
+    pub const DISCOURAGE_UPGRADABLE_NOPS: VerifyFlags = VerifyFlags(1 << 7);
+    pub const CLEANSTACK: VerifyFlags = VerifyFlags(1 << 8);
+    pub const CHECKLOCKTIMEVERIFY: VerifyFlags = VerifyFlags(1 << 9);
+    pub const CHECKSEQUENCEVERIFY: VerifyFlags = VerifyFlags(1 << 10);
+    pub const WITNESS: VerifyFlags = VerifyFlags(1 << 11);
+    pub const DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM: VerifyFlags = VerifyFlags(1 << 12);
+    pub const NULLFAIL: VerifyFlags = VerifyFlags(1 << 14);
+    pub const WITNESS_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 15);
+    pub const TAPROOT: VerifyFlags = VerifyFlags(1 << 17);
+    pub const DISCOURAGE_UPGRADABLE_TAPROOT_VERSION: VerifyFlags = VerifyFlags(1 << 18);
+    pub const DISCOURAGE_OP_SUCCESS: VerifyFlags = VerifyFlags(1 << 19);
+    pub const DISCOURAGE_UPGRADABLE_PUBKEYTYPE: VerifyFlags = VerifyFlags(1 << 20);
+
+    // rules every block has to follow
+    pub const CONSENSUS: VerifyFlags = VerifyFlags(
+        Self::P2SH.0
+            | Self::DERSIG.0
+            | Self::NULLDUMMY.0
+            | Self::CHECKLOCKTIMEVERIFY.0
+            | Self::CHECKSEQUENCEVERIFY.0
+            | Self::WITNESS.0
+            | Self::TAPROOT.0,
+    );
+    // rules a transaction has to follow to be relayed
+    pub const STANDARD: VerifyFlags = VerifyFlags(
+        Self::CONSENSUS.0
+            | Self::STRICTENC.0
+            | Self::LOW_S.0
+            | Self::MINIMALDATA.0
+            | Self::DISCOURAGE_UPGRADABLE_NOPS.0
+            | Self::CLEANSTACK.0
+            | Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM.0
+            | Self::NULLFAIL.0
+            | Self::WITNESS_PUBKEYTYPE.0
+            | Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION.0
+            | Self::DISCOURAGE_OP_SUCCESS.0
+            | Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE.0,
+    );
+
+    pub fn contains(&self, other: VerifyFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn from_name(name: &str) -> Option<VerifyFlags> {
+        let flag = match name {
+            "NONE" => Self::NONE,
+            "P2SH" => Self::P2SH,
+            "STRICTENC" => Self::STRICTENC,
+            "DERSIG" => Self::DERSIG,
+            "LOW_S" => Self::LOW_S,
+            "NULLDUMMY" => Self::NULLDUMMY,
+            "SIGPUSHONLY" => Self::SIGPUSHONLY,
+            "MINIMALDATA" => Self::MINIMALDATA,
+            "DISCOURAGE_UPGRADABLE_NOPS" => Self::DISCOURAGE_UPGRADABLE_NOPS,
+            "CLEANSTACK" => Self::CLEANSTACK,
+            "CHECKLOCKTIMEVERIFY" => Self::CHECKLOCKTIMEVERIFY,
+            "CHECKSEQUENCEVERIFY" => Self::CHECKSEQUENCEVERIFY,
+            "WITNESS" => Self::WITNESS,
+            "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM" => Self::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM,
+            "NULLFAIL" => Self::NULLFAIL,
+            "WITNESS_PUBKEYTYPE" => Self::WITNESS_PUBKEYTYPE,
+            "TAPROOT" => Self::TAPROOT,
+            "DISCOURAGE_UPGRADABLE_TAPROOT_VERSION" => Self::DISCOURAGE_UPGRADABLE_TAPROOT_VERSION,
+            "DISCOURAGE_OP_SUCCESS" => Self::DISCOURAGE_OP_SUCCESS,
+            "DISCOURAGE_UPGRADABLE_PUBKEYTYPE" => Self::DISCOURAGE_UPGRADABLE_PUBKEYTYPE,
+            // including the flags which are not enforced, see above
+            _ => return None,
+        };
+        Some(flag)
+    }
+
+    /// Parses a comma separated list such as "P2SH,STRICTENC", the format used by the
+    /// bitcoin core test vectors
+    pub fn from_names(names: &str) -> Option<VerifyFlags> {
+        names
+            .split(',')
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .try_fold(Self::NONE, |acc, name| Self::from_name(name).map(|flag| acc | flag))
+    }
+}
+
+impl std::ops::BitOr for VerifyFlags {
+    type Output = VerifyFlags;
+
+    fn bitor(self, rhs: VerifyFlags) -> VerifyFlags {
+        VerifyFlags(self.0 | rhs.0)
+    }
+}
+
+/// Rules a signature is checked under, same as SigVersion in bitcoin core
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigVersion {
+    // pre segwit scripts, P2SH included
+    Base,
+    // BIP143 scripts, P2WPKH and P2WSH
+    WitnessV0,
+}
+
+/// Checks the signatures of OP_CHECKSIG and OP_CHECKMULTISIG, the interpreter itself
+/// does not know what a signature commits to
+pub trait SignatureChecker {
+    /// `signature` still carries its sighash byte, `script_code` is the script being
+    /// executed
+    fn check_ecdsa_signature(
+        &self,
+        signature: &[u8],
+        pubkey: &[u8],
+        script_code: &[u8],
+        sig_version: SigVersion,
+    ) -> bool;
+}
+
+/// Checks signatures against input `vin_idx` of a transaction spending `amount`, with
+/// the sighash data computed for this validation of the transaction
+pub struct TransactionSignatureChecker<'a> {
+    tx: &'a Transaction,
+    vin_idx: usize,
+    amount: u64,
+    precomputed: &'a PrecomputedTransactionData,
+}
+
+impl<'a> TransactionSignatureChecker<'a> {
+    pub fn new(
+        tx: &'a Transaction,
+        vin_idx: usize,
+        amount: u64,
+        precomputed: &'a PrecomputedTransactionData,
+    ) -> Self {
+        TransactionSignatureChecker {
+            tx,
+            vin_idx,
+            amount,
+            precomputed,
+        }
+    }
+}
+
+impl SignatureChecker for TransactionSignatureChecker<'_> {
+    fn check_ecdsa_signature(
+        &self,
+        signature: &[u8],
+        pubkey: &[u8],
+        script_code: &[u8],
+        sig_version: SigVersion,
+    ) -> bool {
+        let (sighash_type, der_signature) = match signature.split_last() {
+            Some((sighash_type, der_signature)) => (*sighash_type as u32, der_signature),
+            None => return false,
+        };
+
+        let sighash = match sig_version {
+            SigVersion::Base => legacy_sighash(self.tx, self.vin_idx, script_code, sighash_type),
+            SigVersion::WitnessV0 => segwit_v0_sighash(
+                self.tx,
+                self.vin_idx,
+                script_code,
+                self.amount,
+                sighash_type,
+                self.precomputed,
+            ),
+        };
+
+        // the cache is shared across transactions, a hit skips the ecdsa math
+        signature_cache().verify_ecdsa(&sighash, pubkey, der_signature)
+    }
+}
+
+/// Fails every signature, for evaluating scripts which are not part of a transaction
+pub struct NoSignatureChecker;
+
+impl SignatureChecker for NoSignatureChecker {
+    fn check_ecdsa_signature(&self, _: &[u8], _: &[u8], _: &[u8], _: SigVersion) -> bool {
+        false
+    }
+}
+
 /// This assembler handles the following types of unlock scripts
 /// 1. P2PKH
 /// 2. P2SH
 /// 3. P2WKH
 /// 4. P2WSH
 /// 5. P2TR
+/// 6. P2PK
+/// 7. Bare multisig
 pub struct Interpreter<'a> {
-    pub instructions: &'a [u8],
+    pub instructions: Instructions<'a>,
     pub stack: Stack<Vec<u8>>,
-    exec_ctx: &'a u8,
-    instructions_executed: usize,
-    is_halted: bool,
-    vin_idx: u32,
-    tx: &'a Transaction,
+    // script being executed, signatures commit to it
+    script_code: &'a [u8],
+    checker: &'a dyn SignatureChecker,
+    sig_version: SigVersion,
+    flags: VerifyFlags,
 }
 
 impl<'a> Interpreter<'a> {
-    pub fn new(instructions: &'a [u8], vin_idx: u32, tx: &'a Transaction) -> Self {
+    pub fn new(
+        script: Script<'a>,
+        checker: &'a dyn SignatureChecker,
+        sig_version: SigVersion,
+        flags: VerifyFlags,
+    ) -> Self {
         Interpreter {
-            instructions,
-            stack: Stack::new(),
-            exec_ctx: &instructions[0],
-            instructions_executed: 0,
-            is_halted: false,
-            vin_idx,
-            tx,
+            instructions: script.instructions(),
+            stack: Stack::with_limit(MAX_STACK_SIZE),
+            script_code: script.as_bytes(),
+            checker,
+            sig_version,
+            flags,
         }
     }
 
-    fn jump_next(&mut self) {
-        if self.is_halted {
-            return;
+    fn push(&mut self, data: Vec<u8>) -> Result<(), ScriptError> {
+        self.stack
+            .push(data)
+            .map_err(|_| ScriptError::StackOverflow)
+    }
+
+    fn pop(&mut self) -> Result<Vec<u8>, ScriptError> {
+        self.stack.pop().ok_or(ScriptError::StackUnderflow)
+    }
+
+    // pops a small non negative number such as the key and signature counts of multisig
+    fn pop_count(&mut self, err: ScriptError) -> Result<usize, ScriptError> {
+        let top = self.pop()?;
+        match top.as_slice() {
+            [] => Ok(0),
+            [val] if *val <= 20 => Ok(*val as usize),
+            _ => Err(err),
         }
+    }
 
-        let opcode: Opcode = Opcode {
-            code: self.get_ctx_val(),
-            max_range: None,
+    // the encoding rules of the flags come first, a badly encoded signature or key
+    // fails the script instead of just the signature
+    fn check_signature(&self, signature: &[u8], pubkey: &[u8]) -> Result<bool, ScriptError> {
+        check_signature_encoding(signature, self.flags)?;
+        check_pubkey_encoding(pubkey, self.flags, self.sig_version)?;
+        Ok(self
+            .checker
+            .check_ecdsa_signature(signature, pubkey, self.script_code, self.sig_version))
+    }
+
+    // under MINIMALDATA every push has to use the shortest opcode for its data,
+    // `offset` is where the instruction starts in the script being executed
+    fn check_minimal_push(&self, offset: usize, instruction: &Instruction) -> Result<(), ScriptError> {
+        match instruction {
+            Instruction::PushBytes(data)
+                if self.flags.contains(VerifyFlags::MINIMALDATA)
+                    && !is_minimal_push(self.script_code[offset], data) =>
+            {
+                Err(ScriptError::MinimalData)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn run(&mut self, instructions: &mut Instructions<'a>) -> Result<(), ScriptError> {
+        loop {
+            let offset = instructions.offset();
+            let instruction = match instructions.next() {
+                Some(instruction) => instruction?,
+                None => return Ok(()),
+            };
+            self.check_minimal_push(offset, &instruction)?;
+            self.execute(instruction)?;
+        }
+    }
+
+    fn execute(&mut self, instruction: Instruction<'a>) -> Result<(), ScriptError> {
+        let opcode: Opcode = match instruction {
+            Instruction::PushBytes(data) => {
+                self.push(data.to_vec())?;
+                return Ok(());
+            }
+            Instruction::Op(opcode) => opcode,
         };
 
         // TODO: use Match
         if OP_HASH160 == opcode {
             // Take the top element of the stack hash it using sha256 then use ripemd160 ->
             // push the 20 byte output into the stack
-            let top = self.stack.pop();
-            match top {
-                Some(val) => {
-                    let mut hasher = Sha256::new();
-                    hasher.update(val);
-                    let digest = hasher.finalize();
-                    let mut hasher = Ripemd160::new();
-                    hasher.update(digest);
-                    let pkh = hasher.finalize();
-                    self.stack.push(pkh.to_vec());
-                }
-                None => {
-                    // crash the program
-                }
-            }
+            let top = self.pop()?;
+            let mut hasher = Sha256::new();
+            hasher.update(top);
+            let digest = hasher.finalize();
+            let mut hasher = Ripemd160::new();
+            hasher.update(digest);
+            let pkh = hasher.finalize();
+            self.push(pkh.to_vec())?;
         } else if OP_EQUAL == opcode {
             // Take the top two element from the stack and compare if they are equal
-            let arg1 = self.stack.pop();
-            let arg2 = self.stack.pop();
-            if let (arg1, arg2) = (Some(arg1), Some(arg2)) {
-                match arg1
-                    .unwrap()
-                    .iter()
-                    .zip(&arg2.unwrap())
-                    .filter(|&(a, b)| *a != *b)
-                    .count()
-                {
-                    0 => self.stack.push(vec![0x01]),
-                    _ => self.stack.push(vec![0x00]),
-                }
+            let arg1 = self.pop()?;
+            let arg2 = self.pop()?;
+            if arg1 == arg2 {
+                self.push(vec![0x01])?;
             } else {
-                self.stack.push(vec![0x00]);
+                self.push(vec![0x00])?;
             }
         } else if OP_EQUALVERIFY == opcode {
-            let arg1 = self.stack.pop();
-            let arg2 = self.stack.pop();
-            if arg1.is_some() && arg2.is_some() {
-                match arg1
-                    .unwrap()
-                    .iter()
-                    .zip(&arg2.unwrap())
-                    .filter(|&(a, b)| *a != *b)
-                    .count()
-                {
-                    0 => self.is_halted = false,
-                    _ => self.is_halted = true,
-                }
-            } else {
-                self.is_halted = true;
+            let arg1 = self.pop()?;
+            let arg2 = self.pop()?;
+            if arg1 != arg2 {
+                return Err(ScriptError::EqualVerify);
             }
         } else if OP_DUP == opcode {
             // Take the top element duplicate it and push into the stack
-            let top = self.stack.pop().unwrap();
-            let mut copy = Vec::new();
-            for num in top.iter() {
-                copy.push(*num);
-            }
-            self.stack.push(top);
-            self.stack.push(copy);
+            let copy = self.stack.peek().cloned().ok_or(ScriptError::StackUnderflow)?;
+            self.push(copy)?;
         } else if OP_RETURN == opcode {
-            // Stop the program and stack top is the result
-            self.is_halted = true;
-            return;
-        } else if OP_PUSHBYTES == opcode {
-            let len = opcode.code - OP_PUSHBYTES.code + 1;
-            let mut data: Vec<u8> = Vec::new();
-            for _ in 0..len {
-                data.push(self.get_ctx_val());
+            // Stop the program, the output is unspendable
+            return Err(ScriptError::OpReturn);
+        } else if OP_PUSHNUM == opcode {
+            // OP_1 to OP_16 push the number they represent
+            self.push(vec![opcode.code - OP_1.code + 1])?;
+        } else if OP_CHECKSIG == opcode {
+            let pubkey = self.pop()?;
+            let signature = self.pop()?;
+            let is_valid = self.check_signature(&signature, &pubkey)?;
+            // NULLFAIL, the only signature allowed to fail is the empty one
+            if !is_valid && self.flags.contains(VerifyFlags::NULLFAIL) && !signature.is_empty() {
+                return Err(ScriptError::SigNullFail);
             }
-            self.stack.push(data);
-        } else if OP_PUSHDATA1 == opcode {
-            let len = self.get_ctx_val();
-            let mut data: Vec<u8> = Vec::new();
-            for _ in 0..len {
-                data.push(self.get_ctx_val());
+            if is_valid {
+                self.push(vec![0x01])?;
+            } else {
+                self.push(vec![0x00])?;
             }
-            self.stack.push(data);
-        } else if OP_PUSHDATA2 == opcode {
-            let arg1 = self.get_ctx_val();
-            let len: u16 = 0x0100 * self.get_ctx_val() as u16 + arg1 as u16;
-            let mut data: Vec<u8> = Vec::new();
-            for _ in 0..len {
-                data.push(self.get_ctx_val());
+        } else if OP_CHECKMULTISIG == opcode {
+            // stack: <dummy> <sig 1> .. <sig m> m <pubkey 1> .. <pubkey n> n
+            let keys_count = self.pop_count(ScriptError::PubkeyCount)?;
+            let mut pubkeys: Vec<Vec<u8>> = Vec::new();
+            for _ in 0..keys_count {
+                pubkeys.push(self.pop()?);
             }
-            self.stack.push(data);
-        } else if OP_PUSHDATA4 == opcode {
-            let arg1 = self.get_ctx_val();
-            let arg2 = self.get_ctx_val();
-            let len: u32 =
-                0x010000 * self.get_ctx_val() as u32 + 0x0100 * arg2 as u32 + arg1 as u32;
-            let mut data: Vec<u8> = Vec::new();
-            for _ in 0..len {
-                data.push(self.get_ctx_val());
+            let sigs_count = self.pop_count(ScriptError::SigCount)?;
+            if sigs_count > keys_count {
+                return Err(ScriptError::SigCount);
             }
-            self.stack.push(data);
-        } else if OP_CHECKSIG == opcode {
-            if let (Some(pubkey), Some(signature)) = (self.stack.pop(), self.stack.pop()) {
-                let mut serialized_tx = self.tx.get_raw_tx_for_vin(self.vin_idx);
-                let sighash_type = *signature.last().unwrap() as u32;
-                sighash_type
-                    .to_le_bytes()
-                    .iter()
-                    .for_each(|val| serialized_tx.push(*val));
-
-                let mut hasher = Sha256::new();
-                hasher.update(serialized_tx);
-                let result = hasher.finalize();
-
-                let mut hasher = Sha256::new();
-                hasher.update(result);
-                let serialized_hash = hasher.finalize();
-
-                if let (Ok(msg), Ok(sig), Ok(pk)) = (
-                    Message::parse_slice(serialized_hash.as_slice()),
-                    Signature::parse_der_lax(signature.as_slice()),
-                    PublicKey::parse_slice(pubkey.as_slice(), Some(PublicKeyFormat::Compressed)),
-                ) {
-                    let is_valid = verify(&msg, &sig, &pk);
-                    if is_valid {
-                        self.stack.push(vec![0x01]);
-                    } else {
-                        self.stack.push(vec![0x00]);
-                    }
-                } else {
-                    self.is_halted = true;
-                    self.stack.push(vec![0]);
+            let mut signatures: Vec<Vec<u8>> = Vec::new();
+            for _ in 0..sigs_count {
+                signatures.push(self.pop()?);
+            }
+            // the famous off by one, an extra element is consumed
+            let dummy = self.pop()?;
+
+            // popped in reverse, put them back in script order
+            pubkeys.reverse();
+            signatures.reverse();
+
+            // signatures have to appear in the same order as their public keys, the
+            // loop stops once the keys left can not cover the signatures left. Like
+            // core only the pairs actually compared have their encoding checked
+            let mut is_valid = true;
+            let (mut sig_idx, mut key_idx) = (0, 0);
+            while is_valid && sig_idx < signatures.len() {
+                if self.check_signature(&signatures[sig_idx], &pubkeys[key_idx])? {
+                    sig_idx += 1;
                 }
+                key_idx += 1;
+                is_valid = signatures.len() - sig_idx <= pubkeys.len() - key_idx;
+            }
+
+            if !is_valid
+                && self.flags.contains(VerifyFlags::NULLFAIL)
+                && signatures.iter().any(|signature| !signature.is_empty())
+            {
+                return Err(ScriptError::SigNullFail);
+            }
+            // since BIP147 the extra element must be empty
+            if self.flags.contains(VerifyFlags::NULLDUMMY) && !dummy.is_empty() {
+                return Err(ScriptError::NullDummy);
+            }
+
+            if is_valid {
+                self.push(vec![0x01])?;
             } else {
-                self.is_halted = true;
-                self.stack.push(vec![0]);
+                self.push(vec![0x00])?;
             }
-        }  else {
-            // should we crash :) ?
+        } else {
+            return Err(ScriptError::UnknownOpcode(opcode.code));
         }
+        Ok(())
     }
 
-    fn inc_ctx(&mut self) {
-        self.instructions_executed += 1;
-        if self.instructions_executed < self.instructions.len() {
-            self.exec_ctx = &self.instructions[self.instructions_executed];
+    /// Runs another script on top of the current stack, e.g. the scriptpubkey after
+    /// the scriptsig has pushed its data
+    pub fn exec_script(&mut self, script: Script<'a>) -> Result<(), ScriptError> {
+        let script_code = std::mem::replace(&mut self.script_code, script.as_bytes());
+        let result = self.run(&mut script.instructions());
+        self.script_code = script_code;
+        result
+    }
+
+    /// Runs every instruction of the script and returns the top of the stack, a script
+    /// leaving nothing behind evaluates to false
+    pub fn exec_all(&mut self) -> Result<Vec<u8>, ScriptError> {
+        let mut instructions =
+            std::mem::replace(&mut self.instructions, Script::new(&[]).instructions());
+        let result = self.run(&mut instructions);
+        self.instructions = instructions;
+        result?;
+        self.stack.pop().ok_or(ScriptError::EvalFalse)
+    }
+}
+
+/// Interprets a stack element as a boolean, any non zero byte is true except for the
+/// sign bit of the last byte alone (negative zero)
+pub fn cast_to_bool(data: &[u8]) -> bool {
+    data.iter().enumerate().any(|(idx, val)| {
+        if idx == data.len() - 1 {
+            *val & 0x7f != 0
         } else {
-            self.exec_ctx = &self.instructions[0];
+            *val != 0
         }
+    })
+}
+
+/// Strict DER encoding of BIP66 with the sighash byte at the end, same as
+/// IsValidSignatureEncoding in bitcoin core
+pub fn is_valid_signature_encoding(signature: &[u8]) -> bool {
+    // 0x30 <len> 0x02 <len R> <R> 0x02 <len S> <S> <sighash>
+    let len = signature.len();
+    if !(9..=73).contains(&len) || signature[0] != 0x30 || signature[1] as usize != len - 3 {
+        return false;
+    }
+    let len_r = signature[3] as usize;
+    if 5 + len_r >= len {
+        return false;
+    }
+    let len_s = signature[5 + len_r] as usize;
+    if len_r + len_s + 7 != len {
+        return false;
     }
 
-    pub fn exec_all(&mut self) -> Option<Vec<u8>> {
-        while self.instructions_executed < self.instructions.len() && !self.is_halted {
-            self.jump_next();
-        }
-        self.stack.pop()
+    // both integers are positive and without needless leading zeros
+    let is_valid_integer = |marker: usize, len: usize| {
+        let value = &signature[marker + 2..marker + 2 + len];
+        signature[marker] == 0x02
+            && len != 0
+            && value[0] & 0x80 == 0
+            && !(len > 1 && value[0] == 0x00 && value[1] & 0x80 == 0)
+    };
+    is_valid_integer(2, len_r) && is_valid_integer(4 + len_r, len_s)
+}
+
+// half the order of the secp256k1 group, BIP62 low S values are at most this
+const HALF_CURVE_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// Whether the S value of a strictly DER encoded signature is in the lower half
+pub fn is_low_der_signature(signature: &[u8]) -> bool {
+    let len_r = signature[3] as usize;
+    let len_s = signature[5 + len_r] as usize;
+    let s = &signature[6 + len_r..6 + len_r + len_s];
+    let s = &s[s.iter().take_while(|val| **val == 0).count()..];
+    if s.len() != HALF_CURVE_ORDER.len() {
+        return s.len() < HALF_CURVE_ORDER.len();
     }
+    s <= HALF_CURVE_ORDER.as_slice()
+}
+
+/// SIGHASH_ALL, SIGHASH_NONE or SIGHASH_SINGLE, with or without ANYONECANPAY
+pub fn is_defined_hashtype_signature(signature: &[u8]) -> bool {
+    signature
+        .last()
+        .is_some_and(|sighash_type| (0x01..=0x03).contains(&(sighash_type & !0x80)))
+}
+
+// an empty signature is always well formed, it is how a signature check is made to
+// fail on purpose
+fn check_signature_encoding(signature: &[u8], flags: VerifyFlags) -> Result<(), ScriptError> {
+    if signature.is_empty() {
+        return Ok(());
+    }
+    let der_flags = VerifyFlags::DERSIG | VerifyFlags::LOW_S | VerifyFlags::STRICTENC;
+    if flags.0 & der_flags.0 != 0 && !is_valid_signature_encoding(signature) {
+        return Err(ScriptError::SigDer);
+    }
+    if flags.contains(VerifyFlags::LOW_S) && !is_low_der_signature(signature) {
+        return Err(ScriptError::SigHighS);
+    }
+    if flags.contains(VerifyFlags::STRICTENC) && !is_defined_hashtype_signature(signature) {
+        return Err(ScriptError::SigHashType);
+    }
+    Ok(())
+}
+
+fn check_pubkey_encoding(
+    pubkey: &[u8],
+    flags: VerifyFlags,
+    sig_version: SigVersion,
+) -> Result<(), ScriptError> {
+    let is_compressed = pubkey.len() == 33 && (pubkey[0] == 0x02 || pubkey[0] == 0x03);
+    let is_uncompressed = pubkey.len() == 65 && pubkey[0] == 0x04;
+    if flags.contains(VerifyFlags::STRICTENC) && !is_compressed && !is_uncompressed {
+        return Err(ScriptError::PubkeyType);
+    }
+    // BIP143 policy, segwit keys are compressed
+    if flags.contains(VerifyFlags::WITNESS_PUBKEYTYPE)
+        && sig_version == SigVersion::WitnessV0
+        && !is_compressed
+    {
+        return Err(ScriptError::WitnessPubkeyType);
+    }
+    Ok(())
+}
 
-    fn get_ctx_val(&mut self) -> u8 {
-        let val = *self.exec_ctx;
-        self.inc_ctx();
-        val
+/// Whether `data` is pushed with the shortest opcode for it, same as CheckMinimalPush
+/// in bitcoin core
+pub fn is_minimal_push(opcode: u8, data: &[u8]) -> bool {
+    match data {
+        [] => opcode == OP_0.code,
+        // OP_1 to OP_16 and OP_1NEGATE
+        [val] if (1..=16).contains(val) => false,
+        [0x81] => false,
+        _ if data.len() <= 75 => opcode as usize == data.len(),
+        _ if data.len() <= 0xff => opcode == OP_PUSHDATA1.code,
+        _ if data.len() <= 0xffff => opcode == OP_PUSHDATA2.code,
+        _ => true,
     }
 }
@@ -0,0 +1,34 @@
+pub mod address;
+pub mod block;
+pub mod builder;
+pub mod encode;
+pub mod hash_types;
+pub mod hash_utils;
+pub mod interpreter;
+pub mod macro_utils;
+pub mod merkle;
+pub mod opcodes;
+pub mod policy;
+pub mod psbt;
+pub mod schnorr;
+pub mod script;
+pub mod sighash;
+pub mod sigcache;
+pub mod stack;
+pub mod str_utils;
+pub mod transaction;
+pub mod utils;
+
+#[path = "./test/core_vectors.rs"]
+#[cfg(test)]
+mod core_vectors;
+
+pub const MAX_WEIGHT_ALLOWED : u32 = 4_000_000;
+
+// legacy and P2SH sigops are charged this much more than witness ones, like weight
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+
+pub const MAX_BLOCK_SIGOPS_COST: u32 = 80_000;
+
+// 21 million bitcoin in satoshis, no amount or sum of amounts can be above it
+pub const MAX_MONEY: u64 = 21_000_000 * 100_000_000;
@@ -2,64 +2,53 @@ use std::{
     fs::File,
     io::Write
 };
-use transaction::Transaction;
 
-use crate::{
-    hash_utils::{double_hash256, hash256},
+use mining::{
+    block::BlockContext,
+    hex_str,
     merkle::reorder_txs,
-    utils::{get_txs, prepare_blockheader, prepare_coinbase_tx, remove_double_spending_tx, pick_best_transactions},
+    transaction::Transaction,
+    utils::{get_txs, mempool_parents, prepare_blockheader, prepare_coinbase_tx, remove_double_spending_tx, remove_non_final_txs, pick_best_transactions},
 };
 
-mod hash_utils;
-mod interpreter;
-mod macro_utils;
-mod merkle;
-mod opcodes;
-mod stack;
-mod str_utils;
-mod transaction;
-mod utils;
-
-const MAX_WEIGHT_ALLOWED : u32 = 4_000_000;
-
+// `--height <height>` and `--median-time-past <unix time>` set the block the template
+// is built for, see BlockContext::from_args for what they default to
 fn main() {
-    let mut txs: Vec<Transaction> = get_txs();
-
-    // cosnider mutable iterator because, transactino id is computed and set accordingly
-    txs.iter_mut().for_each(|tx| {
-        let raw_tx: Vec<u8> = tx.get_raw_bytes(false);
-
-        let mut result = double_hash256(&raw_tx);
-        result.reverse();
-
-        // after this step, result will have the actual txid
-        let txid: String = result.iter().fold(String::new(), |acc, val| format!("{}{:02x}", acc, val));
-
-        tx.txid = Some(txid.clone());
-
-        // this is just a sanity check whether, the serialzed data is correct or not
-        let result = hash256(&result);
+    let context = match BlockContext::from_args(std::env::args().skip(1)) {
+        Ok(context) => context,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(2);
+        }
+    };
+    println!(
+        "Building on height {} with median time past {}",
+        context.height, context.median_time_past
+    );
 
-        let hash_txid: String = result.iter().fold(String::new(), |acc, val| format!("{}{:02x}", acc, val));
-        assert_eq!(hash_txid, *tx.sanity_hash.as_ref().unwrap());
+    let mut txs: Vec<Transaction> = get_txs();
 
-        if tx.is_segwit.unwrap() {
-            let raw_tx_witness = tx.get_raw_bytes(true);
-            let wtxid: String = double_hash256(&raw_tx_witness)
-                .iter()
-                .rev()
-                .fold(String::new(), |acc, val| format!("{}{:02x}", acc, *val));
-            tx.wtxid = Some(wtxid);
-        } else {
-            tx.wtxid = Some(txid.clone());
-        }
+    // this is just a sanity check whether, the serialzed data is correct or not, the
+    // file name is the sha256 of the txid (reverse order, again just a bitcoin thing)
+    txs.iter().for_each(|tx| {
+        assert_eq!(tx.compute_sanity_hash(), *tx.sanity_hash.as_ref().unwrap());
     });
 
+    // parents in the mempool confirm in the block being built at the earliest, the
+    // ones already confirmed are not in the snapshot
+    let parents = mempool_parents(&txs, &context);
+
     // this is the filtered txs of double spending
     let txs = remove_double_spending_tx(&mut txs);
 
     println!("Number of txs after removing double spending {}", txs.len());
 
+    // only transactions whose locktime and relative locks have passed can go in the
+    // block
+    let txs = remove_non_final_txs(&txs, &context, &parents);
+
+    println!("Number of final txs {}", txs.len());
+
     let mut verified_txs: Vec<&Transaction> = Vec::new();
     // verify each trannscations vin
     for tx in txs.iter() {
@@ -69,7 +58,7 @@ fn main() {
     }
     println!("Verified {}", verified_txs.len());
 
-    let transactions_to_consider: Vec<&Transaction> = pick_best_transactions(verified_txs.as_slice(), 8000_000);
+    let transactions_to_consider: Vec<&Transaction> = pick_best_transactions(verified_txs.as_slice(), 8_000_000);
 
     // order the transactions topologically
     let mut ordered_txs: Vec<&Transaction> = reorder_txs(&transactions_to_consider);
@@ -100,7 +89,7 @@ fn main() {
     let _ = out_file.write("\n".as_bytes());
 
     ordered_txs.iter().for_each(|tx| {
-        let _ = out_file.write(tx.txid.as_ref().unwrap().as_bytes());
+        let _ = out_file.write(tx.txid().to_string().as_bytes());
         let _ = out_file.write("\n".as_bytes());
     });
 }
@@ -1,6 +1,6 @@
-use std::{collections::HashMap, usize};
+use std::{collections::HashMap, hash::Hash};
 
-use crate::{hash_utils::double_hash256, stack::Stack, str_utils::get_hex_bytes, transaction::Transaction};
+use crate::{hash_types::Txid, hash_utils::double_hash256, stack::Stack, transaction::Transaction};
 
 #[path = "./test/merkle_test.rs"]
 #[cfg(test)]
@@ -37,14 +37,11 @@ pub fn prepare_merkle_root(txs: &[&Transaction], is_wtxid: bool) -> Vec<u8> {
     let txids: Vec<Vec<u8>> = txs
         .iter()
         .map(|tx| {
-            let input = if is_wtxid {
-                tx.wtxid.as_ref()
+            if is_wtxid {
+                tx.wtxid().as_byte_array().to_vec()
             } else {
-                tx.txid.as_ref()
-            };
-            let mut result = get_hex_bytes(input.unwrap()).unwrap().clone();
-            result.reverse();
-            result
+                tx.txid().as_byte_array().to_vec()
+            }
         })
         .collect();
 
@@ -54,51 +51,43 @@ pub fn prepare_merkle_root(txs: &[&Transaction], is_wtxid: bool) -> Vec<u8> {
 // this function will reorder the tx, such that parent tx appears first before the child txs appear
 pub fn reorder_txs<'a>(txs: &'a [&'a Transaction]) -> Vec<&'a Transaction> {
     // adjacency list in the form of txid(parent) -> txid(child1), txid(child2).. and so on
-    let mut adj_list: HashMap<String, Vec<String>> = HashMap::new();
-    let mut incoming_edges: HashMap<String, u32> = HashMap::new();
-    let mut tx_rev_map: HashMap<String, usize> = HashMap::new();
-    let mut visited: HashMap<String, bool> = HashMap::new();
+    let mut adj_list: HashMap<Txid, Vec<Txid>> = HashMap::new();
+    let mut incoming_edges: HashMap<Txid, u32> = HashMap::new();
+    let mut tx_rev_map: HashMap<Txid, usize> = HashMap::new();
+    let mut visited: HashMap<Txid, bool> = HashMap::new();
 
     for (idx, tx) in txs.iter().enumerate() {
         // edge from tx.txid to vins.txid, does vout matter here ?, I guess not
-        // Safely txid because we know that when we reach here it is obvious that
-        // txid will be present
-        let txid = tx.txid.as_ref().unwrap();
-        let mut neighbours: Vec<String> = Vec::new();
+        let txid = tx.txid();
+        let mut neighbours: Vec<Txid> = Vec::new();
 
         tx.vin.iter().for_each(|child_tx| {
-            neighbours.push(child_tx.txid.clone());
-            if let Some(val) = incoming_edges.get_mut(&child_tx.txid.clone()) {
-                *val += 1;
-            } else {
-                incoming_edges.insert(child_tx.txid.clone(), 1);
-            }
+            neighbours.push(child_tx.txid);
+            *incoming_edges.entry(child_tx.txid).or_insert(0) += 1;
         });
 
-        // I hate strings, .clone.clone.clone.clone.clone
-        adj_list.insert(txid.clone(), neighbours);
-        tx_rev_map.insert(txid.clone(), idx);
-        visited.insert(txid.clone(), false);
+        adj_list.insert(txid, neighbours);
+        tx_rev_map.insert(txid, idx);
+        visited.insert(txid, false);
     }
 
     let mut stack: Stack<usize> = Stack::new();
 
     // topological order transactions
     for tx in txs.iter() {
-        let txid = tx.txid.as_ref().unwrap();
+        let txid = tx.txid();
 
-        // TODO: revisit this, this looks way wrong, clone and sending reference :)
-        if let Some(incoming_edge) = incoming_edges.get(&txid.clone()) {
+        if let Some(incoming_edge) = incoming_edges.get(&txid) {
             if *incoming_edge != 0 {
                 continue;
             }
         }
 
         // if not visited earlier do dfs
-        if let Some(is_visited) = visited.get(txid) {
+        if let Some(is_visited) = visited.get(&txid) {
             if !*is_visited {
                 do_dfs(
-                    txid.clone(),
+                    txid,
                     &adj_list,
                     &tx_rev_map,
                     &mut visited,
@@ -119,11 +108,11 @@ pub fn reorder_txs<'a>(txs: &'a [&'a Transaction]) -> Vec<&'a Transaction> {
 }
 
 // this dfs function is specifically designed for topological ordering
-fn do_dfs(
-    txid: String,
-    adj_list: &HashMap<String, Vec<String>>,
-    tx_rev_idx: &HashMap<String, usize>,
-    visited: &mut HashMap<String, bool>,
+fn do_dfs<K: Eq + Hash + Clone>(
+    txid: K,
+    adj_list: &HashMap<K, Vec<K>>,
+    tx_rev_idx: &HashMap<K, usize>,
+    visited: &mut HashMap<K, bool>,
     stack: &mut Stack<usize>,
 ) {
     if let Some(val) = visited.get_mut(&txid) {
@@ -145,6 +134,6 @@ fn do_dfs(
     }
 
     if let Some(idx) = tx_rev_idx.get(&txid) {
-        stack.push(*idx);
+        stack.push(*idx).expect("stack without a limit");
     }
 }
@@ -0,0 +1,240 @@
+use std::fmt;
+
+use crate::{
+    encode::serialize,
+    opcodes::all_opcodes::OP_RETURN,
+    script::{Script, ScriptTemplate},
+    transaction::{Pubkey, Transaction, Vin},
+    WITNESS_SCALE_FACTOR,
+};
+
+#[path = "./test/policy_tests.rs"]
+#[cfg(test)]
+mod policy_test;
+
+pub const MIN_STANDARD_VERSION: u32 = 1;
+pub const MAX_STANDARD_VERSION: u32 = 3;
+pub const MAX_STANDARD_TX_WEIGHT: usize = 400_000;
+// large enough for a 15 of 15 multisig redeem script with its signatures
+pub const MAX_STANDARD_SCRIPTSIG_SIZE: usize = 1650;
+// the whole output script, OP_RETURN included
+pub const MAX_OP_RETURN_RELAY: usize = 83;
+// satoshis per 1000 virtual bytes
+pub const DUST_RELAY_TX_FEE: u64 = 3000;
+pub const MAX_STANDARD_P2WSH_SCRIPT_SIZE: usize = 3600;
+pub const MAX_STANDARD_P2WSH_STACK_ITEMS: usize = 100;
+pub const MAX_STANDARD_P2WSH_STACK_ITEM_SIZE: usize = 80;
+pub const MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE: usize = 80;
+// scripts above this size can never be spent
+const MAX_SCRIPT_SIZE: usize = 10_000;
+const ANNEX_TAG: u8 = 0x50;
+const TAPROOT_LEAF_MASK: u8 = 0xfe;
+const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+
+/// Relay settings, the defaults are the ones of bitcoin core
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Policy {
+    // None when OP_RETURN outputs are not relayed at all
+    pub max_datacarrier_bytes: Option<usize>,
+    pub permit_bare_multisig: bool,
+    pub dust_relay_fee: u64,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Policy {
+            max_datacarrier_bytes: Some(MAX_OP_RETURN_RELAY),
+            permit_bare_multisig: true,
+            dust_relay_fee: DUST_RELAY_TX_FEE,
+        }
+    }
+}
+
+/// Reasons a transaction is valid but would not be relayed, Display gives the reject
+/// reason of bitcoin core
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PolicyError {
+    Version(u32),
+    TxSize(usize),
+    ScriptSigSize { vin: usize, size: usize },
+    ScriptSigNotPushOnly { vin: usize },
+    ScriptPubKey { vout: usize },
+    Datacarrier { vout: usize, size: usize },
+    BareMultisig { vout: usize },
+    MultiOpReturn,
+    Dust { vout: usize, threshold: u64 },
+    WitnessNonStandard { vin: usize },
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::Version(version) => write!(f, "version ({})", version),
+            PolicyError::TxSize(weight) => write!(f, "tx-size (weight {})", weight),
+            PolicyError::ScriptSigSize { vin, size } => {
+                write!(f, "scriptsig-size (vin {}, {} bytes)", vin, size)
+            }
+            PolicyError::ScriptSigNotPushOnly { vin } => {
+                write!(f, "scriptsig-not-pushonly (vin {})", vin)
+            }
+            PolicyError::ScriptPubKey { vout } => write!(f, "scriptpubkey (vout {})", vout),
+            PolicyError::Datacarrier { vout, size } => {
+                write!(f, "scriptpubkey (vout {}, {} bytes of OP_RETURN)", vout, size)
+            }
+            PolicyError::BareMultisig { vout } => write!(f, "bare-multisig (vout {})", vout),
+            PolicyError::MultiOpReturn => write!(f, "multi-op-return"),
+            PolicyError::Dust { vout, threshold } => {
+                write!(f, "dust (vout {} below {} sats)", vout, threshold)
+            }
+            PolicyError::WitnessNonStandard { vin } => {
+                write!(f, "bad-witness-nonstandard (vin {})", vin)
+            }
+        }
+    }
+}
+
+impl Policy {
+    /// Checks the transaction against the relay policy, same as IsStandardTx followed
+    /// by IsWitnessStandard in bitcoin core. The first rule broken is reported
+    pub fn check_standard(&self, tx: &Transaction) -> Result<(), PolicyError> {
+        if !(MIN_STANDARD_VERSION..=MAX_STANDARD_VERSION).contains(&tx.version) {
+            return Err(PolicyError::Version(tx.version));
+        }
+
+        let weight = tx.weight.unwrap_or_else(|| tx.get_weight());
+        if weight > MAX_STANDARD_TX_WEIGHT {
+            return Err(PolicyError::TxSize(weight));
+        }
+
+        for (idx, vin) in tx.vin.iter().enumerate() {
+            if vin.scriptsig.len() > MAX_STANDARD_SCRIPTSIG_SIZE {
+                return Err(PolicyError::ScriptSigSize {
+                    vin: idx,
+                    size: vin.scriptsig.len(),
+                });
+            }
+            if !vin.scriptsig.as_script().is_push_only() {
+                return Err(PolicyError::ScriptSigNotPushOnly { vin: idx });
+            }
+        }
+
+        let mut data_outputs = 0;
+        for (idx, vout) in tx.vout.iter().enumerate() {
+            match vout.classify() {
+                ScriptTemplate::NonStandard => {
+                    return Err(PolicyError::ScriptPubKey { vout: idx });
+                }
+                ScriptTemplate::Multisig { keys, .. } if keys > 3 => {
+                    return Err(PolicyError::ScriptPubKey { vout: idx });
+                }
+                ScriptTemplate::Multisig { .. } if !self.permit_bare_multisig => {
+                    return Err(PolicyError::BareMultisig { vout: idx });
+                }
+                ScriptTemplate::NullData => {
+                    let size = vout.scriptpubkey.len();
+                    if self.max_datacarrier_bytes.is_none_or(|max| size > max) {
+                        return Err(PolicyError::Datacarrier { vout: idx, size });
+                    }
+                    data_outputs += 1;
+                }
+                _ => {}
+            }
+        }
+        if data_outputs > 1 {
+            return Err(PolicyError::MultiOpReturn);
+        }
+
+        for (idx, vout) in tx.vout.iter().enumerate() {
+            let threshold = self.dust_threshold(vout);
+            if vout.value < threshold {
+                return Err(PolicyError::Dust {
+                    vout: idx,
+                    threshold,
+                });
+            }
+        }
+
+        if !tx.is_coinbase() {
+            if let Some(idx) = tx.vin.iter().position(|vin| !is_witness_standard(vin)) {
+                return Err(PolicyError::WitnessNonStandard { vin: idx });
+            }
+        }
+        Ok(())
+    }
+
+    /// Smallest value worth relaying for the output: less than what spending it would
+    /// cost at the dust relay fee, with a P2PKH sized input for legacy outputs and a
+    /// P2WPKH sized one for witness programs
+    pub fn dust_threshold(&self, vout: &Pubkey) -> u64 {
+        let script = vout.scriptpubkey.as_script();
+        if is_unspendable(&script) {
+            return 0;
+        }
+        // outpoint, scriptSig length and sequence with a signature and a pubkey
+        let input_size = if script.witness_program().is_some() {
+            32 + 4 + 1 + (107 / WITNESS_SCALE_FACTOR) + 4
+        } else {
+            32 + 4 + 1 + 107 + 4
+        };
+        let size = serialize(vout).len() + input_size;
+        size as u64 * self.dust_relay_fee / 1000
+    }
+}
+
+fn is_unspendable(script: &Script) -> bool {
+    script.as_bytes().first() == Some(&OP_RETURN.code) || script.len() > MAX_SCRIPT_SIZE
+}
+
+// limits on the witness of a single input, only witness v0 and taproot have them
+fn is_witness_standard(vin: &Vin) -> bool {
+    let stack: &[Vec<u8>] = match vin.witness.as_ref() {
+        Some(witness) if !witness.is_empty() => &witness.0,
+        _ => return true,
+    };
+
+    let mut script = vin.prevout.scriptpubkey.as_script();
+    let is_p2sh = script.classify() == ScriptTemplate::P2SH;
+    if is_p2sh {
+        script = match vin.scriptsig.as_script().last_push() {
+            Some(redeem_script) => Script::new(redeem_script),
+            None => return false,
+        };
+    }
+
+    // witness data on an input which is not a witness program
+    let (version, program) = match script.witness_program() {
+        Some(program) => program,
+        None => return false,
+    };
+
+    match (version, program.len()) {
+        (0, 32) => {
+            let (witness_script, items) = stack.split_last().expect("stack is not empty");
+            witness_script.len() <= MAX_STANDARD_P2WSH_SCRIPT_SIZE
+                && items.len() <= MAX_STANDARD_P2WSH_STACK_ITEMS
+                && items
+                    .iter()
+                    .all(|item| item.len() <= MAX_STANDARD_P2WSH_STACK_ITEM_SIZE)
+        }
+        (1, 32) if !is_p2sh => {
+            // the annex is reserved for future soft forks
+            if stack.len() >= 2 && stack[stack.len() - 1].first() == Some(&ANNEX_TAG) {
+                return false;
+            }
+            match stack {
+                // script path: the inputs, the tapscript and the control block
+                [items @ .., _, control_block] => match control_block.first() {
+                    None => false,
+                    // only tapscript leaves have limits, other versions are upgradable
+                    Some(leaf) if leaf & TAPROOT_LEAF_MASK == TAPROOT_LEAF_TAPSCRIPT => items
+                        .iter()
+                        .all(|item| item.len() <= MAX_STANDARD_TAPSCRIPT_STACK_ITEM_SIZE),
+                    Some(_) => true,
+                },
+                // key path
+                _ => true,
+            }
+        }
+        _ => true,
+    }
+}
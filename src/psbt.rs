@@ -0,0 +1,972 @@
+// Partially signed transactions of BIP174 (version 0) and BIP370 (version 2). Both
+// versions are parsed into the same maps, version 0 keeps the unsigned transaction in
+// the global map and version 2 spreads its fields over the input and output maps
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+
+use libsecp256k1::{PublicKey, SecretKey};
+
+use crate::{
+    builder::{input_signature, p2pkh_scriptsig, InputSignature, SignError},
+    encode::{serialize, CompactSize, Decodable, DecodeError, SliceReader},
+    hash_types::Txid,
+    hash_utils::pk_hash,
+    hex_str,
+    interpreter::VerifyFlags,
+    schnorr::verify_schnorr,
+    script::{ScriptBuf, ScriptTemplate},
+    sighash::{taproot_sighash, PrecomputedTransactionData, SIGHASH_ALL, SIGHASH_DEFAULT},
+    str_utils::{decode_base64, encode_base64, Base64Error},
+    transaction::{
+        InputVerdict, Pubkey, Transaction, TxCheckError, Vin, Witness, LOCKTIME_THRESHOLD,
+        SEQUENCE_FINAL,
+    },
+};
+
+#[path = "./test/psbt_tests.rs"]
+#[cfg(test)]
+mod psbt_test;
+
+pub const PSBT_MAGIC: [u8; 5] = *b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u64 = 0x00;
+const PSBT_GLOBAL_TX_VERSION: u64 = 0x02;
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u64 = 0x03;
+const PSBT_GLOBAL_INPUT_COUNT: u64 = 0x04;
+const PSBT_GLOBAL_OUTPUT_COUNT: u64 = 0x05;
+const PSBT_GLOBAL_TX_MODIFIABLE: u64 = 0x06;
+const PSBT_GLOBAL_VERSION: u64 = 0xfb;
+
+const PSBT_IN_NON_WITNESS_UTXO: u64 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u64 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u64 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u64 = 0x03;
+const PSBT_IN_REDEEM_SCRIPT: u64 = 0x04;
+const PSBT_IN_WITNESS_SCRIPT: u64 = 0x05;
+const PSBT_IN_FINAL_SCRIPTSIG: u64 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u64 = 0x08;
+const PSBT_IN_PREVIOUS_TXID: u64 = 0x0e;
+const PSBT_IN_OUTPUT_INDEX: u64 = 0x0f;
+const PSBT_IN_SEQUENCE: u64 = 0x10;
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u64 = 0x11;
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u64 = 0x12;
+const PSBT_IN_TAP_KEY_SIG: u64 = 0x13;
+
+const PSBT_OUT_REDEEM_SCRIPT: u64 = 0x00;
+const PSBT_OUT_WITNESS_SCRIPT: u64 = 0x01;
+const PSBT_OUT_AMOUNT: u64 = 0x03;
+const PSBT_OUT_SCRIPT: u64 = 0x04;
+
+// fields which only exist in one of the versions, per map
+const GLOBAL_V0_ONLY: &[u64] = &[PSBT_GLOBAL_UNSIGNED_TX];
+const GLOBAL_V2_ONLY: &[u64] = &[
+    PSBT_GLOBAL_TX_VERSION,
+    PSBT_GLOBAL_FALLBACK_LOCKTIME,
+    PSBT_GLOBAL_INPUT_COUNT,
+    PSBT_GLOBAL_OUTPUT_COUNT,
+    PSBT_GLOBAL_TX_MODIFIABLE,
+];
+const INPUT_V2_ONLY: &[u64] = &[
+    PSBT_IN_PREVIOUS_TXID,
+    PSBT_IN_OUTPUT_INDEX,
+    PSBT_IN_SEQUENCE,
+    PSBT_IN_REQUIRED_TIME_LOCKTIME,
+    PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+];
+const OUTPUT_V2_ONLY: &[u64] = &[PSBT_OUT_AMOUNT, PSBT_OUT_SCRIPT];
+
+/// Reasons a PSBT can not be parsed, signed or finalized
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PsbtError {
+    Base64(Base64Error),
+    BadMagic,
+    Decode(DecodeError),
+    TrailingData { remaining: usize },
+    DuplicateKey(Vec<u8>),
+    // key data on a key type which takes none, or key data which is not valid
+    InvalidKey(Vec<u8>),
+    InvalidValue(Vec<u8>),
+    UnsupportedVersion(u32),
+    FieldNotAllowed { version: u32, key: Vec<u8> },
+    MissingField(&'static str),
+    // the unsigned transaction of version 0 has scriptsigs or witnesses
+    UnsignedTxWithScripts,
+    // the non witness utxo is not the transaction the input spends from
+    UtxoMismatch { vin: usize },
+    // inputs ask for both a height and a time locktime
+    LocktimeConflict,
+    InputOutOfRange { idx: usize, inputs: usize },
+    MissingUtxo { vin: usize },
+    Sign(SignError),
+    AlreadyFinalized { vin: usize },
+    MissingSignature { vin: usize },
+    CannotFinalize { vin: usize, template: ScriptTemplate },
+    NotFinalized { vin: usize },
+    Check(TxCheckError),
+    InvalidInput { vin: usize, verdict: InputVerdict },
+}
+
+impl fmt::Display for PsbtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PsbtError::Base64(err) => write!(f, "{}", err),
+            PsbtError::BadMagic => write!(f, "missing psbt magic bytes"),
+            PsbtError::Decode(err) => write!(f, "malformed key value map ({:?})", err),
+            PsbtError::TrailingData { remaining } => {
+                write!(f, "{} bytes left after the output maps", remaining)
+            }
+            PsbtError::DuplicateKey(key) => write!(f, "duplicate key {}", hex_str!(key)),
+            PsbtError::InvalidKey(key) => write!(f, "invalid key {}", hex_str!(key)),
+            PsbtError::InvalidValue(key) => write!(f, "invalid value of key {}", hex_str!(key)),
+            PsbtError::UnsupportedVersion(version) => {
+                write!(f, "unsupported psbt version {}", version)
+            }
+            PsbtError::FieldNotAllowed { version, key } => {
+                write!(f, "key {} is not allowed in psbt version {}", hex_str!(key), version)
+            }
+            PsbtError::MissingField(field) => write!(f, "missing {}", field),
+            PsbtError::UnsignedTxWithScripts => {
+                write!(f, "unsigned tx does not have empty scriptsigs and witnesses")
+            }
+            PsbtError::UtxoMismatch { vin } => {
+                write!(f, "non witness utxo of vin {} does not match its prevout", vin)
+            }
+            PsbtError::LocktimeConflict => {
+                write!(f, "inputs require both a height and a time locktime")
+            }
+            PsbtError::InputOutOfRange { idx, inputs } => {
+                write!(f, "input {} out of range, {} inputs", idx, inputs)
+            }
+            PsbtError::MissingUtxo { vin } => write!(f, "missing utxo of vin {}", vin),
+            PsbtError::Sign(err) => write!(f, "{}", err),
+            PsbtError::AlreadyFinalized { vin } => write!(f, "vin {} is already finalized", vin),
+            PsbtError::MissingSignature { vin } => write!(f, "missing signature of vin {}", vin),
+            PsbtError::CannotFinalize { vin, template } => {
+                write!(f, "vin {} spends a {:?} output which can not be finalized", vin, template)
+            }
+            PsbtError::NotFinalized { vin } => write!(f, "vin {} is not finalized", vin),
+            PsbtError::Check(err) => write!(f, "{}", err),
+            PsbtError::InvalidInput { vin, verdict } => {
+                write!(f, "vin {} does not validate: {:?}", vin, verdict)
+            }
+        }
+    }
+}
+
+impl From<DecodeError> for PsbtError {
+    fn from(err: DecodeError) -> Self {
+        PsbtError::Decode(err)
+    }
+}
+
+/// A partially signed transaction. The fields of both versions live in the input and
+/// output maps, the unsigned transaction of version 0 is built from them
+#[derive(Clone)]
+pub struct Psbt {
+    pub version: u32,
+    pub tx_version: u32,
+    // the locktime of version 0, in version 2 inputs can require a later one
+    pub fallback_locktime: Option<u32>,
+    pub tx_modifiable: Option<u8>,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PsbtOutput>,
+    // xpubs, proprietary and unknown keys, serialized back as they came
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(Clone)]
+pub struct PsbtInput {
+    pub previous_txid: Txid,
+    pub output_index: u32,
+    // a missing sequence is a final one
+    pub sequence: Option<u32>,
+    pub required_time_locktime: Option<u32>,
+    pub required_height_locktime: Option<u32>,
+    pub non_witness_utxo: Option<Transaction>,
+    pub witness_utxo: Option<Pubkey>,
+    // DER signatures with the sighash byte, by serialized public key
+    pub partial_sigs: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub sighash_type: Option<u32>,
+    pub redeem_script: Option<ScriptBuf>,
+    pub witness_script: Option<ScriptBuf>,
+    pub final_script_sig: Option<ScriptBuf>,
+    pub final_script_witness: Option<Witness>,
+    pub tap_key_sig: Option<Vec<u8>>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(Clone)]
+pub struct PsbtOutput {
+    pub amount: u64,
+    pub script: ScriptBuf,
+    pub redeem_script: Option<ScriptBuf>,
+    pub witness_script: Option<ScriptBuf>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+// a key value pair of a map, the key starts with its type as a compact size
+struct Pair {
+    key_type: u64,
+    key: Vec<u8>,
+    keydata: Vec<u8>,
+    value: Vec<u8>,
+}
+
+impl Pair {
+    fn decode<T: Decodable>(&self) -> Result<T, PsbtError> {
+        let mut reader = SliceReader::new(&self.value);
+        match T::consensus_decode(&mut reader) {
+            Ok(value) if reader.remaining() == 0 => Ok(value),
+            _ => Err(PsbtError::InvalidValue(self.key.clone())),
+        }
+    }
+
+    // most key types are the whole key, without any key data
+    fn no_keydata(&self) -> Result<&Self, PsbtError> {
+        if !self.keydata.is_empty() {
+            return Err(PsbtError::InvalidKey(self.key.clone()));
+        }
+        Ok(self)
+    }
+
+    // scripts are the whole value, without a length of their own
+    fn script(&self) -> Result<ScriptBuf, PsbtError> {
+        Ok(ScriptBuf::from_bytes(self.no_keydata()?.value.clone()))
+    }
+
+    fn check_version(
+        &self,
+        version: u32,
+        v0_only: &[u64],
+        v2_only: &[u64],
+    ) -> Result<(), PsbtError> {
+        let only = if version == 0 { v2_only } else { v0_only };
+        if only.contains(&self.key_type) {
+            return Err(PsbtError::FieldNotAllowed {
+                version,
+                key: self.key.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+// the pairs of one map up to its 0x00 separator
+fn read_map(reader: &mut SliceReader) -> Result<Vec<Pair>, PsbtError> {
+    let mut pairs: Vec<Pair> = Vec::new();
+    let mut keys: HashSet<Vec<u8>> = HashSet::new();
+    loop {
+        let key = Vec::<u8>::consensus_decode(reader)?;
+        if key.is_empty() {
+            return Ok(pairs);
+        }
+        let value = Vec::<u8>::consensus_decode(reader)?;
+        if !keys.insert(key.clone()) {
+            return Err(PsbtError::DuplicateKey(key));
+        }
+
+        let mut key_reader = SliceReader::new(&key);
+        let key_type = CompactSize::consensus_decode(&mut key_reader)?.0;
+        let keydata = key[key_reader.position()..].to_vec();
+        pairs.push(Pair {
+            key_type,
+            key,
+            keydata,
+            value,
+        });
+    }
+}
+
+fn write_pair(bytes: &mut Vec<u8>, key_type: u64, keydata: &[u8], value: &[u8]) {
+    let key = [serialize(&CompactSize(key_type)).as_slice(), keydata].concat();
+    bytes.extend(serialize(&key));
+    bytes.extend(serialize(value));
+}
+
+fn write_unknown(bytes: &mut Vec<u8>, unknown: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    for (key, value) in unknown.iter() {
+        bytes.extend(serialize(key));
+        bytes.extend(serialize(value));
+    }
+}
+
+impl Psbt {
+    /// PSBT of an unsigned transaction, e.g. one from `Transaction::builder`. Spent
+    /// outputs which are witness programs become witness utxos, the others need the
+    /// whole transaction they come from, see `update_with_utxo`
+    pub fn from_unsigned_tx(tx: &Transaction, version: u32) -> Result<Psbt, PsbtError> {
+        if version != 0 && version != 2 {
+            return Err(PsbtError::UnsupportedVersion(version));
+        }
+        if tx.vin.iter().any(|vin| !vin.scriptsig.is_empty()) || tx.has_witness() {
+            return Err(PsbtError::UnsignedTxWithScripts);
+        }
+
+        let inputs = tx
+            .vin
+            .iter()
+            .map(|vin| {
+                let mut input = PsbtInput::new(vin.txid, vin.vout, Some(vin.sequence));
+                if vin.prevout.scriptpubkey.as_script().witness_program().is_some() {
+                    input.witness_utxo = Some(vin.prevout.clone());
+                }
+                input
+            })
+            .collect();
+        let outputs = tx
+            .vout
+            .iter()
+            .map(|vout| PsbtOutput::new(vout.value, vout.scriptpubkey.clone()))
+            .collect();
+
+        Ok(Psbt {
+            version,
+            tx_version: tx.version,
+            fallback_locktime: Some(tx.locktime),
+            tx_modifiable: None,
+            inputs,
+            outputs,
+            unknown: BTreeMap::new(),
+        })
+    }
+
+    pub fn from_base64(encoded: &str) -> Result<Psbt, PsbtError> {
+        let bytes = decode_base64(encoded).map_err(PsbtError::Base64)?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn to_base64(&self) -> String {
+        encode_base64(&self.to_bytes())
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Psbt, PsbtError> {
+        if !bytes.starts_with(&PSBT_MAGIC) {
+            return Err(PsbtError::BadMagic);
+        }
+        let mut reader = SliceReader::new(&bytes[PSBT_MAGIC.len()..]);
+
+        let globals = read_map(&mut reader)?;
+        // the version decides which fields are allowed, it comes late in the map
+        let version = match globals.iter().find(|pair| pair.key_type == PSBT_GLOBAL_VERSION) {
+            Some(pair) => pair.no_keydata()?.decode::<u32>()?,
+            None => 0,
+        };
+        if version != 0 && version != 2 {
+            return Err(PsbtError::UnsupportedVersion(version));
+        }
+
+        let mut psbt = Psbt {
+            version,
+            tx_version: 0,
+            fallback_locktime: None,
+            tx_modifiable: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            unknown: BTreeMap::new(),
+        };
+        let mut unsigned_tx: Option<Transaction> = None;
+        let (mut tx_version, mut input_count, mut output_count) = (None, None, None);
+
+        for pair in globals.iter() {
+            pair.check_version(version, GLOBAL_V0_ONLY, GLOBAL_V2_ONLY)?;
+            match pair.key_type {
+                PSBT_GLOBAL_UNSIGNED_TX => {
+                    let tx = Transaction::from_bytes(&pair.no_keydata()?.value)
+                        .map_err(|_| PsbtError::InvalidValue(pair.key.clone()))?;
+                    unsigned_tx = Some(tx);
+                }
+                PSBT_GLOBAL_TX_VERSION => tx_version = Some(pair.no_keydata()?.decode::<u32>()?),
+                PSBT_GLOBAL_FALLBACK_LOCKTIME => {
+                    psbt.fallback_locktime = Some(pair.no_keydata()?.decode::<u32>()?)
+                }
+                PSBT_GLOBAL_INPUT_COUNT => {
+                    input_count = Some(pair.no_keydata()?.decode::<CompactSize>()?.0)
+                }
+                PSBT_GLOBAL_OUTPUT_COUNT => {
+                    output_count = Some(pair.no_keydata()?.decode::<CompactSize>()?.0)
+                }
+                PSBT_GLOBAL_TX_MODIFIABLE => {
+                    psbt.tx_modifiable = Some(pair.no_keydata()?.decode::<u8>()?)
+                }
+                PSBT_GLOBAL_VERSION => {}
+                _ => {
+                    psbt.unknown.insert(pair.key.clone(), pair.value.clone());
+                }
+            }
+        }
+
+        if version == 0 {
+            let tx = unsigned_tx.ok_or(PsbtError::MissingField("unsigned tx"))?;
+            if tx.vin.iter().any(|vin| !vin.scriptsig.is_empty()) || tx.has_witness() {
+                return Err(PsbtError::UnsignedTxWithScripts);
+            }
+            psbt.tx_version = tx.version;
+            psbt.fallback_locktime = Some(tx.locktime);
+            for (idx, vin) in tx.vin.iter().enumerate() {
+                let input = PsbtInput::new(vin.txid, vin.vout, Some(vin.sequence));
+                psbt.inputs.push(input.parse(&read_map(&mut reader)?, idx, version)?);
+            }
+            for vout in tx.vout.iter() {
+                let output = PsbtOutput::new(vout.value, vout.scriptpubkey.clone());
+                psbt.outputs.push(output.parse(&read_map(&mut reader)?, version)?);
+            }
+        } else {
+            psbt.tx_version = tx_version.ok_or(PsbtError::MissingField("tx version"))?;
+            let input_count = input_count.ok_or(PsbtError::MissingField("input count"))?;
+            let output_count = output_count.ok_or(PsbtError::MissingField("output count"))?;
+            // a short psbt runs out of maps before a large count allocates anything
+            for idx in 0..input_count as usize {
+                let input = PsbtInput::new(Txid::all_zeros(), 0, None);
+                psbt.inputs.push(input.parse(&read_map(&mut reader)?, idx, version)?);
+            }
+            for _ in 0..output_count {
+                let output = PsbtOutput::new(0, ScriptBuf::new());
+                psbt.outputs.push(output.parse(&read_map(&mut reader)?, version)?);
+            }
+        }
+
+        if reader.remaining() != 0 {
+            return Err(PsbtError::TrailingData {
+                remaining: reader.remaining(),
+            });
+        }
+        Ok(psbt)
+    }
+
+    /// Serialization with the known keys of each map in key type order, followed by
+    /// the unknown ones
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = PSBT_MAGIC.to_vec();
+
+        if self.version == 0 {
+            let locktime = self.fallback_locktime.unwrap_or(0);
+            let unsigned_tx = self.transaction(locktime, false).get_raw_bytes(false);
+            write_pair(&mut bytes, PSBT_GLOBAL_UNSIGNED_TX, &[], &unsigned_tx);
+        } else {
+            write_pair(&mut bytes, PSBT_GLOBAL_TX_VERSION, &[], &serialize(&self.tx_version));
+            if let Some(locktime) = self.fallback_locktime {
+                write_pair(&mut bytes, PSBT_GLOBAL_FALLBACK_LOCKTIME, &[], &serialize(&locktime));
+            }
+            let input_count = serialize(&CompactSize(self.inputs.len() as u64));
+            write_pair(&mut bytes, PSBT_GLOBAL_INPUT_COUNT, &[], &input_count);
+            let output_count = serialize(&CompactSize(self.outputs.len() as u64));
+            write_pair(&mut bytes, PSBT_GLOBAL_OUTPUT_COUNT, &[], &output_count);
+            if let Some(modifiable) = self.tx_modifiable {
+                write_pair(&mut bytes, PSBT_GLOBAL_TX_MODIFIABLE, &[], &[modifiable]);
+            }
+            write_pair(&mut bytes, PSBT_GLOBAL_VERSION, &[], &serialize(&self.version));
+        }
+        write_unknown(&mut bytes, &self.unknown);
+        bytes.push(0x00);
+
+        for input in self.inputs.iter() {
+            input.write(&mut bytes, self.version);
+        }
+        for output in self.outputs.iter() {
+            output.write(&mut bytes, self.version);
+        }
+        bytes
+    }
+
+    /// Locktime of the transaction. Version 2 inputs can require a height or a time,
+    /// the latest one wins and height is picked when every input allows it, same as
+    /// BIP370
+    pub fn locktime(&self) -> Result<u32, PsbtError> {
+        let requiring: Vec<&PsbtInput> = self
+            .inputs
+            .iter()
+            .filter(|input| {
+                input.required_height_locktime.is_some() || input.required_time_locktime.is_some()
+            })
+            .collect();
+        if requiring.is_empty() {
+            return Ok(self.fallback_locktime.unwrap_or(0));
+        }
+
+        if requiring.iter().all(|input| input.required_height_locktime.is_some()) {
+            Ok(requiring.iter().filter_map(|input| input.required_height_locktime).max().unwrap())
+        } else if requiring.iter().all(|input| input.required_time_locktime.is_some()) {
+            Ok(requiring.iter().filter_map(|input| input.required_time_locktime).max().unwrap())
+        } else {
+            Err(PsbtError::LocktimeConflict)
+        }
+    }
+
+    /// The transaction with the utxos known so far as prevouts, spent outputs which
+    /// are not known are empty. Finalized inputs have their scriptsig and witness
+    pub fn to_transaction(&self) -> Result<Transaction, PsbtError> {
+        Ok(self.transaction(self.locktime()?, true))
+    }
+
+    fn transaction(&self, locktime: u32, with_final_scripts: bool) -> Transaction {
+        let vin = self
+            .inputs
+            .iter()
+            .map(|input| {
+                let scriptsig = input
+                    .final_script_sig
+                    .clone()
+                    .filter(|_| with_final_scripts)
+                    .unwrap_or_default();
+                let witness = input
+                    .final_script_witness
+                    .clone()
+                    .filter(|_| with_final_scripts);
+                Vin {
+                    txid: input.previous_txid,
+                    vout: input.output_index,
+                    prevout: input
+                        .spent_output()
+                        .unwrap_or_else(|| Pubkey::new(ScriptBuf::new(), 0)),
+                    scriptsig_asm: scriptsig.as_script().to_asm(),
+                    scriptsig,
+                    witness,
+                    is_coinbase: false,
+                    sequence: input.sequence.unwrap_or(SEQUENCE_FINAL),
+                    inner_redeemscript_asm: None,
+                    inner_witnessscript_asm: None,
+                }
+            })
+            .collect();
+        let vout = self
+            .outputs
+            .iter()
+            .map(|output| Pubkey::new(output.script.clone(), output.amount))
+            .collect();
+
+        let mut tx = Transaction {
+            sanity_hash: None,
+            version: self.tx_version,
+            locktime,
+            vin,
+            vout,
+            weight: None,
+            sigop_cost: None,
+            tx_fee: None,
+        };
+        tx.assign_weight();
+        tx.assign_sigop_cost();
+        tx.assign_tx_fee();
+        tx.sanity_hash = Some(tx.compute_sanity_hash());
+        tx
+    }
+
+    fn input(&self, idx: usize) -> Result<&PsbtInput, PsbtError> {
+        self.inputs.get(idx).ok_or(PsbtError::InputOutOfRange {
+            idx,
+            inputs: self.inputs.len(),
+        })
+    }
+
+    /// Adds `prev_tx`, the transaction input `idx` spends from. The spent output is
+    /// also added as witness utxo when it is a witness program
+    pub fn update_with_utxo(&mut self, idx: usize, prev_tx: &Transaction) -> Result<(), PsbtError> {
+        let input = self.input(idx)?;
+        let spent = match prev_tx.vout.get(input.output_index as usize) {
+            Some(spent) if prev_tx.txid() == input.previous_txid => spent,
+            _ => return Err(PsbtError::UtxoMismatch { vin: idx }),
+        };
+        let witness_utxo = spent
+            .scriptpubkey
+            .as_script()
+            .witness_program()
+            .map(|_| Pubkey::new(spent.scriptpubkey.clone(), spent.value));
+
+        let input = &mut self.inputs[idx];
+        input.non_witness_utxo = Some(prev_tx.clone());
+        if witness_utxo.is_some() {
+            input.witness_utxo = witness_utxo;
+        }
+        Ok(())
+    }
+
+    /// Adds the output spent by input `idx`, enough to sign witness programs
+    pub fn update_with_witness_utxo(&mut self, idx: usize, utxo: Pubkey) -> Result<(), PsbtError> {
+        self.input(idx)?;
+        self.inputs[idx].witness_utxo = Some(Pubkey::new(utxo.scriptpubkey, utxo.value));
+        Ok(())
+    }
+
+    /// Signs input `idx` with the sighash type of the input, SIGHASH_ALL when it has
+    /// none or SIGHASH_DEFAULT for taproot. Same outputs as `Transaction::sign_input`,
+    /// legacy inputs need their non witness utxo and taproot inputs the utxo of every
+    /// input, both are committed to by the signature
+    pub fn sign_input(&mut self, idx: usize, secret_key: &SecretKey) -> Result<(), PsbtError> {
+        let input = self.input(idx)?;
+        if input.is_finalized() {
+            return Err(PsbtError::AlreadyFinalized { vin: idx });
+        }
+        let spent = input.spent_output().ok_or(PsbtError::MissingUtxo { vin: idx })?;
+        let template = spent.classify();
+        let sighash_type = input.sighash_type.unwrap_or(match template {
+            ScriptTemplate::P2TR => SIGHASH_DEFAULT,
+            _ => SIGHASH_ALL,
+        });
+
+        let tx = self.to_transaction()?;
+        let signature =
+            input_signature(&tx, idx, secret_key, sighash_type).map_err(PsbtError::Sign)?;
+
+        // checked once the key is known to own the input, so `sign` only reports the
+        // missing utxos of its own inputs
+        if template == ScriptTemplate::P2PKH && input.non_witness_utxo.is_none() {
+            return Err(PsbtError::MissingUtxo { vin: idx });
+        }
+        if template == ScriptTemplate::P2TR {
+            if let Some(vin) = self.inputs.iter().position(|input| input.spent_output().is_none()) {
+                return Err(PsbtError::MissingUtxo { vin });
+            }
+        }
+
+        match signature {
+            InputSignature::Ecdsa { pubkey, signature } => {
+                self.inputs[idx].partial_sigs.insert(pubkey, signature);
+            }
+            InputSignature::Schnorr(signature) => self.inputs[idx].tap_key_sig = Some(signature),
+        }
+        Ok(())
+    }
+
+    /// Signs every input `secret_key` owns and returns how many there were
+    pub fn sign(&mut self, secret_key: &SecretKey) -> Result<usize, PsbtError> {
+        let mut signed = 0;
+        for idx in 0..self.inputs.len() {
+            match self.sign_input(idx, secret_key) {
+                Ok(()) => signed += 1,
+                Err(PsbtError::Sign(SignError::KeyMismatch { .. }))
+                | Err(PsbtError::Sign(SignError::UnsupportedScript { .. }))
+                | Err(PsbtError::AlreadyFinalized { .. }) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(signed)
+    }
+
+    /// Builds the final scriptsig or witness of every input from its signatures and
+    /// returns the transaction, which the interpreter has validated. Nothing changes
+    /// when an input can not be finalized or does not validate
+    pub fn finalize(&mut self) -> Result<Transaction, PsbtError> {
+        let mut finalized = self.clone();
+        for (idx, input) in finalized.inputs.iter_mut().enumerate() {
+            if !input.is_finalized() {
+                input.finalize(idx)?;
+            }
+        }
+        let tx = finalized.extract_tx()?;
+        *self = finalized;
+        Ok(tx)
+    }
+
+    /// The signed transaction of a finalized PSBT, checked like the transactions of
+    /// the mempool and with every input validated under the standard flags, taproot
+    /// inputs by their key path signature
+    pub fn extract_tx(&self) -> Result<Transaction, PsbtError> {
+        for (vin, input) in self.inputs.iter().enumerate() {
+            if !input.is_finalized() {
+                return Err(PsbtError::NotFinalized { vin });
+            }
+            if input.spent_output().is_none() {
+                return Err(PsbtError::MissingUtxo { vin });
+            }
+        }
+
+        let tx = self.to_transaction()?;
+        tx.check_transaction().map_err(PsbtError::Check)?;
+        tx.check_tx_inputs().map_err(PsbtError::Check)?;
+        let precomputed = PrecomputedTransactionData::new(&tx);
+        let verdicts = tx.verify_inputs(VerifyFlags::STANDARD);
+        if let Some((vin, verdict)) = verdicts.into_iter().enumerate().find(|(vin, verdict)| {
+            let key_path = *verdict == InputVerdict::Unsupported(ScriptTemplate::P2TR)
+                && key_path_is_valid(&tx, *vin, &precomputed);
+            !verdict.is_valid() && !key_path
+        }) {
+            return Err(PsbtError::InvalidInput { vin, verdict });
+        }
+        Ok(tx)
+    }
+}
+
+// the interpreter can not validate taproot spends yet, a key path spend is checked
+// here against the BIP341 signature hash of the input
+fn key_path_is_valid(
+    tx: &Transaction,
+    vin: usize,
+    precomputed: &PrecomputedTransactionData,
+) -> bool {
+    let signature = match tx.vin[vin].witness.as_ref() {
+        Some(witness) if witness.0.len() == 1 => &witness.0[0],
+        _ => return false,
+    };
+    // a 65 byte signature spells out its sighash type, which can not be SIGHASH_DEFAULT
+    let (signature, sighash_type) = match signature.len() {
+        64 => (signature.as_slice(), SIGHASH_DEFAULT),
+        65 if signature[64] as u32 != SIGHASH_DEFAULT => (&signature[..64], signature[64] as u32),
+        _ => return false,
+    };
+    let program = &tx.vin[vin].prevout.scriptpubkey.as_bytes()[2..];
+    taproot_sighash(tx, vin, sighash_type, None, None, precomputed)
+        .is_ok_and(|sighash| verify_schnorr(&sighash, program, signature))
+}
+
+impl PsbtInput {
+    pub fn new(previous_txid: Txid, output_index: u32, sequence: Option<u32>) -> Self {
+        PsbtInput {
+            previous_txid,
+            output_index,
+            sequence,
+            required_time_locktime: None,
+            required_height_locktime: None,
+            non_witness_utxo: None,
+            witness_utxo: None,
+            partial_sigs: BTreeMap::new(),
+            sighash_type: None,
+            redeem_script: None,
+            witness_script: None,
+            final_script_sig: None,
+            final_script_witness: None,
+            tap_key_sig: None,
+            unknown: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.final_script_sig.is_some() || self.final_script_witness.is_some()
+    }
+
+    /// The output this input spends, the non witness utxo is preferred since its
+    /// txid was checked against the input
+    pub fn spent_output(&self) -> Option<Pubkey> {
+        let from_tx = self
+            .non_witness_utxo
+            .as_ref()
+            .and_then(|tx| tx.vout.get(self.output_index as usize));
+        from_tx
+            .or(self.witness_utxo.as_ref())
+            .map(|spent| Pubkey::new(spent.scriptpubkey.clone(), spent.value))
+    }
+
+    fn parse(mut self, pairs: &[Pair], idx: usize, version: u32) -> Result<Self, PsbtError> {
+        let (mut has_txid, mut has_index) = (false, false);
+        for pair in pairs.iter() {
+            pair.check_version(version, &[], INPUT_V2_ONLY)?;
+            match pair.key_type {
+                PSBT_IN_NON_WITNESS_UTXO => {
+                    let tx = Transaction::from_bytes(&pair.no_keydata()?.value)
+                        .map_err(|_| PsbtError::InvalidValue(pair.key.clone()))?;
+                    self.non_witness_utxo = Some(tx);
+                }
+                PSBT_IN_WITNESS_UTXO => self.witness_utxo = Some(pair.no_keydata()?.decode()?),
+                PSBT_IN_PARTIAL_SIG => {
+                    if PublicKey::parse_slice(&pair.keydata, None).is_err() {
+                        return Err(PsbtError::InvalidKey(pair.key.clone()));
+                    }
+                    self.partial_sigs.insert(pair.keydata.clone(), pair.value.clone());
+                }
+                PSBT_IN_SIGHASH_TYPE => self.sighash_type = Some(pair.no_keydata()?.decode()?),
+                PSBT_IN_REDEEM_SCRIPT => self.redeem_script = Some(pair.script()?),
+                PSBT_IN_WITNESS_SCRIPT => self.witness_script = Some(pair.script()?),
+                PSBT_IN_FINAL_SCRIPTSIG => self.final_script_sig = Some(pair.script()?),
+                PSBT_IN_FINAL_SCRIPTWITNESS => {
+                    self.final_script_witness = Some(pair.no_keydata()?.decode()?)
+                }
+                PSBT_IN_PREVIOUS_TXID => {
+                    self.previous_txid = Txid::from_byte_array(pair.no_keydata()?.decode()?);
+                    has_txid = true;
+                }
+                PSBT_IN_OUTPUT_INDEX => {
+                    self.output_index = pair.no_keydata()?.decode()?;
+                    has_index = true;
+                }
+                PSBT_IN_SEQUENCE => self.sequence = Some(pair.no_keydata()?.decode()?),
+                PSBT_IN_REQUIRED_TIME_LOCKTIME => {
+                    let locktime: u32 = pair.no_keydata()?.decode()?;
+                    if locktime < LOCKTIME_THRESHOLD {
+                        return Err(PsbtError::InvalidValue(pair.key.clone()));
+                    }
+                    self.required_time_locktime = Some(locktime);
+                }
+                PSBT_IN_REQUIRED_HEIGHT_LOCKTIME => {
+                    let locktime: u32 = pair.no_keydata()?.decode()?;
+                    if locktime == 0 || locktime >= LOCKTIME_THRESHOLD {
+                        return Err(PsbtError::InvalidValue(pair.key.clone()));
+                    }
+                    self.required_height_locktime = Some(locktime);
+                }
+                PSBT_IN_TAP_KEY_SIG => {
+                    let signature = &pair.no_keydata()?.value;
+                    if signature.len() != 64 && signature.len() != 65 {
+                        return Err(PsbtError::InvalidValue(pair.key.clone()));
+                    }
+                    self.tap_key_sig = Some(signature.clone());
+                }
+                _ => {
+                    self.unknown.insert(pair.key.clone(), pair.value.clone());
+                }
+            }
+        }
+
+        if version == 2 && !has_txid {
+            return Err(PsbtError::MissingField("previous txid"));
+        }
+        if version == 2 && !has_index {
+            return Err(PsbtError::MissingField("output index"));
+        }
+        if let Some(tx) = self.non_witness_utxo.as_ref() {
+            if tx.txid() != self.previous_txid || tx.vout.len() <= self.output_index as usize {
+                return Err(PsbtError::UtxoMismatch { vin: idx });
+            }
+        }
+        Ok(self)
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>, version: u32) {
+        if let Some(tx) = self.non_witness_utxo.as_ref() {
+            write_pair(bytes, PSBT_IN_NON_WITNESS_UTXO, &[], &serialize(tx));
+        }
+        if let Some(utxo) = self.witness_utxo.as_ref() {
+            write_pair(bytes, PSBT_IN_WITNESS_UTXO, &[], &serialize(utxo));
+        }
+        for (pubkey, signature) in self.partial_sigs.iter() {
+            write_pair(bytes, PSBT_IN_PARTIAL_SIG, pubkey, signature);
+        }
+        if let Some(sighash_type) = self.sighash_type {
+            write_pair(bytes, PSBT_IN_SIGHASH_TYPE, &[], &serialize(&sighash_type));
+        }
+        if let Some(script) = self.redeem_script.as_ref() {
+            write_pair(bytes, PSBT_IN_REDEEM_SCRIPT, &[], script.as_bytes());
+        }
+        if let Some(script) = self.witness_script.as_ref() {
+            write_pair(bytes, PSBT_IN_WITNESS_SCRIPT, &[], script.as_bytes());
+        }
+        if let Some(script) = self.final_script_sig.as_ref() {
+            write_pair(bytes, PSBT_IN_FINAL_SCRIPTSIG, &[], script.as_bytes());
+        }
+        if let Some(witness) = self.final_script_witness.as_ref() {
+            write_pair(bytes, PSBT_IN_FINAL_SCRIPTWITNESS, &[], &serialize(witness));
+        }
+        if version == 2 {
+            let txid = self.previous_txid.to_byte_array();
+            write_pair(bytes, PSBT_IN_PREVIOUS_TXID, &[], &txid);
+            write_pair(bytes, PSBT_IN_OUTPUT_INDEX, &[], &serialize(&self.output_index));
+            if let Some(sequence) = self.sequence {
+                write_pair(bytes, PSBT_IN_SEQUENCE, &[], &serialize(&sequence));
+            }
+            if let Some(locktime) = self.required_time_locktime {
+                write_pair(bytes, PSBT_IN_REQUIRED_TIME_LOCKTIME, &[], &serialize(&locktime));
+            }
+            if let Some(locktime) = self.required_height_locktime {
+                write_pair(bytes, PSBT_IN_REQUIRED_HEIGHT_LOCKTIME, &[], &serialize(&locktime));
+            }
+        }
+        if let Some(signature) = self.tap_key_sig.as_ref() {
+            write_pair(bytes, PSBT_IN_TAP_KEY_SIG, &[], signature);
+        }
+        write_unknown(bytes, &self.unknown);
+        bytes.push(0x00);
+    }
+
+    // the signature of the key hashed into the output, P2PKH keys can be uncompressed
+    fn signature_for(&self, pubkeyhash: &[u8]) -> Option<(&Vec<u8>, &Vec<u8>)> {
+        self.partial_sigs
+            .iter()
+            .find(|(pubkey, _)| pk_hash(pubkey) == pubkeyhash)
+    }
+
+    // Only the outputs the interpreter validates are finalized, the finalized
+    // transaction could not be checked otherwise
+    fn finalize(&mut self, idx: usize) -> Result<(), PsbtError> {
+        let spent = self.spent_output().ok_or(PsbtError::MissingUtxo { vin: idx })?;
+        let script = spent.scriptpubkey.as_bytes();
+
+        match spent.classify() {
+            ScriptTemplate::P2PKH => {
+                let (pubkey, signature) = self
+                    .signature_for(&script[3..23])
+                    .ok_or(PsbtError::MissingSignature { vin: idx })?;
+                self.final_script_sig = Some(p2pkh_scriptsig(signature, pubkey));
+            }
+            ScriptTemplate::P2WPKH => {
+                let (pubkey, signature) = self
+                    .signature_for(&script[2..])
+                    .filter(|(pubkey, _)| pubkey.len() == 33)
+                    .ok_or(PsbtError::MissingSignature { vin: idx })?;
+                self.final_script_witness = Some(Witness(vec![signature.clone(), pubkey.clone()]));
+            }
+            // key path spends only, a script path needs the leaf script and control block
+            ScriptTemplate::P2TR => {
+                let signature =
+                    self.tap_key_sig.as_ref().ok_or(PsbtError::MissingSignature { vin: idx })?;
+                self.final_script_witness = Some(Witness(vec![signature.clone()]));
+            }
+            template => return Err(PsbtError::CannotFinalize { vin: idx, template }),
+        }
+
+        // the finalizer keeps only the utxos and the keys it does not know
+        self.partial_sigs.clear();
+        self.sighash_type = None;
+        self.redeem_script = None;
+        self.witness_script = None;
+        self.tap_key_sig = None;
+        Ok(())
+    }
+}
+
+impl PsbtOutput {
+    pub fn new(amount: u64, script: ScriptBuf) -> Self {
+        PsbtOutput {
+            amount,
+            script,
+            redeem_script: None,
+            witness_script: None,
+            unknown: BTreeMap::new(),
+        }
+    }
+
+    fn parse(mut self, pairs: &[Pair], version: u32) -> Result<Self, PsbtError> {
+        let (mut has_amount, mut has_script) = (false, false);
+        for pair in pairs.iter() {
+            pair.check_version(version, &[], OUTPUT_V2_ONLY)?;
+            match pair.key_type {
+                PSBT_OUT_REDEEM_SCRIPT => self.redeem_script = Some(pair.script()?),
+                PSBT_OUT_WITNESS_SCRIPT => self.witness_script = Some(pair.script()?),
+                PSBT_OUT_AMOUNT => {
+                    self.amount = pair.no_keydata()?.decode()?;
+                    has_amount = true;
+                }
+                PSBT_OUT_SCRIPT => {
+                    self.script = pair.script()?;
+                    has_script = true;
+                }
+                _ => {
+                    self.unknown.insert(pair.key.clone(), pair.value.clone());
+                }
+            }
+        }
+
+        if version == 2 && !has_amount {
+            return Err(PsbtError::MissingField("output amount"));
+        }
+        if version == 2 && !has_script {
+            return Err(PsbtError::MissingField("output script"));
+        }
+        Ok(self)
+    }
+
+    fn write(&self, bytes: &mut Vec<u8>, version: u32) {
+        if let Some(script) = self.redeem_script.as_ref() {
+            write_pair(bytes, PSBT_OUT_REDEEM_SCRIPT, &[], script.as_bytes());
+        }
+        if let Some(script) = self.witness_script.as_ref() {
+            write_pair(bytes, PSBT_OUT_WITNESS_SCRIPT, &[], script.as_bytes());
+        }
+        if version == 2 {
+            write_pair(bytes, PSBT_OUT_AMOUNT, &[], &serialize(&self.amount));
+            write_pair(bytes, PSBT_OUT_SCRIPT, &[], self.script.as_bytes());
+        }
+        write_unknown(bytes, &self.unknown);
+        bytes.push(0x00);
+    }
+}
@@ -0,0 +1,173 @@
+// BIP340 signatures and the BIP341 key tweak on top of the curve arithmetic of
+// libsecp256k1, which has no schnorr support of its own
+
+use libsecp256k1::{
+    curve::{Affine, Field, Jacobian, Scalar},
+    PublicKey, SecretKey, ECMULT_CONTEXT, ECMULT_GEN_CONTEXT,
+};
+
+use crate::hash_utils::tagged_hash;
+
+#[path = "./test/schnorr_tests.rs"]
+#[cfg(test)]
+mod schnorr_test;
+
+// the point with x coordinate `x` and an even y
+fn lift_x(x: &[u8; 32]) -> Option<Affine> {
+    let mut field = Field::default();
+    let mut point = Affine::default();
+    if !field.set_b32(x) || !point.set_xo_var(&field, false) {
+        return None;
+    }
+    Some(point)
+}
+
+fn scalar_from_hash(hash: Vec<u8>) -> Scalar {
+    let mut scalar = Scalar::default();
+    // reduced mod the curve order, same as int(hash) mod n in BIP340
+    let _ = scalar.set_b32(&hash.try_into().expect("sha256 hashes are 32 bytes"));
+    scalar
+}
+
+// secret key scaled by the generator, with the coordinates normalized
+fn mul_generator(scalar: &Scalar) -> Affine {
+    let mut point = Jacobian::default();
+    ECMULT_GEN_CONTEXT.ecmult_gen(&mut point, scalar);
+    let mut point = Affine::from_gej(&point);
+    point.x.normalize_var();
+    point.y.normalize_var();
+    point
+}
+
+/// The 32 byte x only public key of `secret_key`
+pub fn xonly_public_key(secret_key: &SecretKey) -> [u8; 32] {
+    let compressed = PublicKey::from_secret_key(secret_key).serialize_compressed();
+    compressed[1..].try_into().expect("compressed keys are 33 bytes")
+}
+
+/// True when `signature` is a valid BIP340 signature of `msg` for the x only `pubkey`
+pub fn verify_schnorr(msg: &[u8], pubkey: &[u8], signature: &[u8]) -> bool {
+    let (Ok(pubkey_x), Ok(msg)) = (<[u8; 32]>::try_from(pubkey), <[u8; 32]>::try_from(msg))
+    else {
+        return false;
+    };
+    if signature.len() != 64 {
+        return false;
+    }
+
+    let Some(point) = lift_x(&pubkey_x) else {
+        return false;
+    };
+
+    let r_bytes: [u8; 32] = signature[..32].try_into().unwrap();
+    let mut r = Field::default();
+    if !r.set_b32(&r_bytes) {
+        return false;
+    }
+    let mut s = Scalar::default();
+    if bool::from(s.set_b32(&signature[32..].try_into().unwrap())) {
+        return false;
+    }
+
+    let mut challenge: Vec<u8> = r_bytes.to_vec();
+    challenge.extend_from_slice(&pubkey_x);
+    challenge.extend_from_slice(&msg);
+    let e = scalar_from_hash(tagged_hash("BIP0340/challenge", &challenge));
+
+    // R = s * G - e * P
+    let mut result = Jacobian::default();
+    ECMULT_CONTEXT.ecmult(&mut result, &Jacobian::from_ge(&point), &-e, &s);
+    if result.is_infinity() {
+        return false;
+    }
+
+    let mut result = Affine::from_gej(&result);
+    result.x.normalize_var();
+    result.y.normalize_var();
+    !result.y.is_odd() && result.x.eq_var(&r)
+}
+
+/// BIP340 signature of the 32 byte `msg`, `aux_rand` is mixed into the nonce so the
+/// nonce stays secret even when the randomness is weak
+pub fn sign_schnorr(msg: &[u8; 32], secret_key: &SecretKey, aux_rand: &[u8; 32]) -> [u8; 64] {
+    let mut d: Scalar = (*secret_key).into();
+    let pubkey = mul_generator(&d);
+    // the x only key stands for the point with an even y
+    if pubkey.y.is_odd() {
+        d = -d;
+    }
+    let pubkey_x = pubkey.x.b32();
+
+    let aux_hash = tagged_hash("BIP0340/aux", aux_rand);
+    let mut nonce_data: Vec<u8> = d
+        .b32()
+        .iter()
+        .zip(aux_hash.iter())
+        .map(|(key, aux)| key ^ aux)
+        .collect();
+    nonce_data.extend_from_slice(&pubkey_x);
+    nonce_data.extend_from_slice(msg);
+    let mut k = scalar_from_hash(tagged_hash("BIP0340/nonce", &nonce_data));
+    // only with negligible probability, the hash would have to be the curve order
+    assert!(!k.is_zero(), "schnorr nonce is zero");
+
+    let nonce_point = mul_generator(&k);
+    if nonce_point.y.is_odd() {
+        k = -k;
+    }
+    let r_bytes = nonce_point.x.b32();
+
+    let mut challenge: Vec<u8> = r_bytes.to_vec();
+    challenge.extend_from_slice(&pubkey_x);
+    challenge.extend_from_slice(msg);
+    let e = scalar_from_hash(tagged_hash("BIP0340/challenge", &challenge));
+
+    let mut signature = [0u8; 64];
+    signature[..32].copy_from_slice(&r_bytes);
+    signature[32..].copy_from_slice(&(k + e * d).b32());
+    signature
+}
+
+// BIP341 tweak of an internal key, a key path only output commits to no script tree
+fn tap_tweak(internal_key: &[u8; 32], merkle_root: Option<&[u8; 32]>) -> Scalar {
+    let mut data: Vec<u8> = internal_key.to_vec();
+    if let Some(merkle_root) = merkle_root {
+        data.extend_from_slice(merkle_root);
+    }
+    scalar_from_hash(tagged_hash("TapTweak", &data))
+}
+
+/// x only output key of a taproot output with `internal_key`, None for the keys which
+/// are not on the curve
+pub fn tweak_public_key(
+    internal_key: &[u8; 32],
+    merkle_root: Option<&[u8; 32]>,
+) -> Option<[u8; 32]> {
+    let point = lift_x(internal_key)?;
+    let tweak = tap_tweak(internal_key, merkle_root);
+
+    // Q = P + t * G
+    let mut output = Jacobian::default();
+    ECMULT_CONTEXT.ecmult(&mut output, &Jacobian::from_ge(&point), &Scalar::from_int(1), &tweak);
+    if output.is_infinity() {
+        return None;
+    }
+    let mut output = Affine::from_gej(&output);
+    output.x.normalize_var();
+    Some(output.x.b32())
+}
+
+/// Secret key of the output key `tweak_public_key` gives for the x only key of
+/// `secret_key`, signing with it spends the output by the key path
+pub fn tweak_secret_key(
+    secret_key: &SecretKey,
+    merkle_root: Option<&[u8; 32]>,
+) -> Option<SecretKey> {
+    let mut d: Scalar = (*secret_key).into();
+    let pubkey = mul_generator(&d);
+    if pubkey.y.is_odd() {
+        d = -d;
+    }
+    let tweak = tap_tweak(&pubkey.x.b32(), merkle_root);
+    SecretKey::try_from(d + tweak).ok()
+}
@@ -0,0 +1,517 @@
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{
+    encode::{Decodable, DecodeError, Encodable},
+    hex_str,
+    opcodes::{
+        all_opcodes::{
+            OP_0, OP_1, OP_CHECKMULTISIG, OP_CHECKMULTISIGVERIFY, OP_CHECKSIG,
+            OP_CHECKSIGVERIFY, OP_DUP, OP_EQUAL, OP_EQUALVERIFY, OP_HASH160, OP_PUSHBYTES,
+            OP_PUSHDATA1, OP_PUSHDATA2, OP_PUSHDATA4, OP_PUSHNUM, OP_RETURN,
+        },
+        opcode_name, Opcode,
+    },
+    str_utils::{parse_hex, HexError},
+};
+
+// keys an OP_CHECKMULTISIG is charged for when the key count is not known
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+
+#[path = "./test/script_tests.rs"]
+#[cfg(test)]
+mod script_test;
+
+/// Errors reported while parsing or executing a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptError {
+    // a push opcode asked for more bytes than the script has left
+    TruncatedPush {
+        offset: usize,
+        expected: usize,
+        available: usize,
+    },
+    // the length prefix of OP_PUSHDATA1/2/4 itself is cut off
+    TruncatedPushLength { offset: usize },
+    StackUnderflow,
+    StackOverflow,
+    // the script finished with false on top of the stack
+    EvalFalse,
+    PubkeyCount,
+    SigCount,
+    // the extra element consumed by OP_CHECKMULTISIG must be empty
+    NullDummy,
+    EqualVerify,
+    OpReturn,
+    UnknownOpcode(u8),
+    // a native witness program spent with a non empty scriptsig
+    WitnessMalleated,
+    // a witness for a script which does not expect one
+    WitnessUnexpected,
+    WitnessProgramWitnessEmpty,
+    // the witness does not have the shape the program asks for
+    WitnessProgramMismatch,
+    WitnessProgramWrongLength,
+    DiscourageUpgradableWitnessProgram,
+    // signature and key encodings of the policy flags
+    SigDer,
+    SigHighS,
+    SigHashType,
+    PubkeyType,
+    WitnessPubkeyType,
+    // a failed signature check with a non empty signature under NULLFAIL
+    SigNullFail,
+    MinimalData,
+    // more than the result left on the stack under CLEANSTACK
+    CleanStack,
+    SigPushOnly,
+}
+
+/// A single parsed script element, data pushes carry a slice into the script bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction<'a> {
+    PushBytes(&'a [u8]),
+    Op(Opcode),
+}
+
+/// Output script templates, derived from the script bytes alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptTemplate {
+    P2PK,   // <pubkey> OP_CHECKSIG
+    P2PKH,  // OP_DUP OP_HASH160 <20 bytes> OP_EQUALVERIFY OP_CHECKSIG
+    P2SH,   // OP_HASH160 <20 bytes> OP_EQUAL
+    P2WPKH, // OP_0 <20 bytes>
+    P2WSH,  // OP_0 <32 bytes>
+    P2TR,   // OP_1 <32 bytes>
+    Multisig { required: u8, keys: u8 }, // m <pubkeys> n OP_CHECKMULTISIG
+    NullData, // OP_RETURN followed by pushes only
+    Anchor,   // OP_1 <4e73>, pay to anchor
+    WitnessUnknown { version: u8, program_len: usize },
+    NonStandard,
+}
+
+impl ScriptTemplate {
+    /// The `scriptpubkey_type` label esplora gives outputs of this template
+    pub fn label(&self) -> &'static str {
+        match self {
+            ScriptTemplate::P2PK => "p2pk",
+            ScriptTemplate::P2PKH => "p2pkh",
+            ScriptTemplate::P2SH => "p2sh",
+            ScriptTemplate::P2WPKH => "v0_p2wpkh",
+            ScriptTemplate::P2WSH => "v0_p2wsh",
+            ScriptTemplate::P2TR => "v1_p2tr",
+            ScriptTemplate::NullData => "op_return",
+            ScriptTemplate::Anchor => "anchor",
+            // the mempool json has no label of its own for bare multisig
+            ScriptTemplate::Multisig { .. }
+            | ScriptTemplate::WitnessUnknown { .. }
+            | ScriptTemplate::NonStandard => "unknown",
+        }
+    }
+
+    /// Checks whether the `scriptpubkey_type` label of the mempool json agrees with
+    /// the template, esplora reports everything it does not know as "unknown"
+    pub fn matches_label(&self, label: &str) -> bool {
+        match self {
+            ScriptTemplate::P2PK => label == "p2pk",
+            ScriptTemplate::P2PKH => label == "p2pkh",
+            ScriptTemplate::P2SH => label == "p2sh",
+            ScriptTemplate::P2WPKH => label == "v0_p2wpkh",
+            ScriptTemplate::P2WSH => label == "v0_p2wsh",
+            ScriptTemplate::P2TR => label == "v1_p2tr",
+            ScriptTemplate::Multisig { .. } => label == "multisig" || label == "unknown",
+            ScriptTemplate::NullData => label == "op_return" || label == "provably_unspendable",
+            ScriptTemplate::Anchor => label == "anchor" || label == "unknown",
+            ScriptTemplate::WitnessUnknown { .. } => label == "unknown",
+            ScriptTemplate::NonStandard => {
+                label == "unknown" || label == "nonstandard" || label == "empty"
+            }
+        }
+    }
+}
+
+// bitcoin core only accepts these sizes as public keys in output templates
+fn is_pubkey_size(data: &[u8]) -> bool {
+    match data.len() {
+        33 => data[0] == 0x02 || data[0] == 0x03,
+        65 => data[0] == 0x04 || data[0] == 0x06 || data[0] == 0x07,
+        _ => false,
+    }
+}
+
+// value of OP_1 to OP_16
+fn small_int(code: u8) -> Option<u8> {
+    let opcode = Opcode {
+        code,
+        max_range: None,
+    };
+    if OP_PUSHNUM == opcode {
+        Some(code - OP_1.code + 1)
+    } else {
+        None
+    }
+}
+
+/// Borrowed view over raw script bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Script<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Script<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Script { bytes }
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Witness version and program if the script is a witness program, a version byte
+    /// followed by one direct push of 2 to 40 bytes
+    pub fn witness_program(&self) -> Option<(u8, &'a [u8])> {
+        let bytes = self.bytes;
+        if bytes.len() < 4 || bytes.len() > 42 || bytes[1] as usize + 2 != bytes.len() {
+            return None;
+        }
+        let version = if bytes[0] == OP_0.code {
+            0
+        } else {
+            small_int(bytes[0])?
+        };
+        Some((version, &bytes[2..]))
+    }
+
+    /// True when every instruction only pushes data. Like IsPushOnly in bitcoin core,
+    /// OP_1NEGATE and OP_RESERVED count as pushes too
+    pub fn is_push_only(&self) -> bool {
+        self.instructions().all(|instruction| match instruction {
+            Ok(Instruction::PushBytes(_)) => true,
+            // up to OP_16, the last of the OP_PUSHNUM range
+            Ok(Instruction::Op(opcode)) => opcode.code <= 0x60,
+            Err(_) => false,
+        })
+    }
+
+    /// Derives the output template from the script bytes, nothing else is trusted
+    pub fn classify(&self) -> ScriptTemplate {
+        let bytes = self.bytes;
+
+        if let Some((version, program)) = self.witness_program() {
+            return match (version, program.len()) {
+                (0, 20) => ScriptTemplate::P2WPKH,
+                (0, 32) => ScriptTemplate::P2WSH,
+                (1, 32) => ScriptTemplate::P2TR,
+                (1, 2) if program == [0x4e, 0x73] => ScriptTemplate::Anchor,
+                _ => ScriptTemplate::WitnessUnknown {
+                    version,
+                    program_len: program.len(),
+                },
+            };
+        }
+
+        if bytes.len() == 25
+            && bytes[0] == OP_DUP.code
+            && bytes[1] == OP_HASH160.code
+            && bytes[2] == 0x14
+            && bytes[23] == OP_EQUALVERIFY.code
+            && bytes[24] == OP_CHECKSIG.code
+        {
+            return ScriptTemplate::P2PKH;
+        }
+
+        if bytes.len() == 23
+            && bytes[0] == OP_HASH160.code
+            && bytes[1] == 0x14
+            && bytes[22] == OP_EQUAL.code
+        {
+            return ScriptTemplate::P2SH;
+        }
+
+        if !bytes.is_empty() && bytes[0] == OP_RETURN.code {
+            return if Script::new(&bytes[1..]).is_push_only() {
+                ScriptTemplate::NullData
+            } else {
+                ScriptTemplate::NonStandard
+            };
+        }
+
+        let instructions: Vec<Instruction> = match self.instructions().collect() {
+            Ok(instructions) => instructions,
+            Err(_) => return ScriptTemplate::NonStandard,
+        };
+
+        match instructions.as_slice() {
+            [Instruction::PushBytes(pubkey), Instruction::Op(op)]
+                if OP_CHECKSIG == *op && is_pubkey_size(pubkey) =>
+            {
+                ScriptTemplate::P2PK
+            }
+            [Instruction::Op(m), pubkeys @ .., Instruction::Op(n), Instruction::Op(op)]
+                if OP_CHECKMULTISIG == *op =>
+            {
+                match (small_int(m.code), small_int(n.code)) {
+                    (Some(required), Some(keys))
+                        if required <= keys
+                            && keys as usize == pubkeys.len()
+                            && pubkeys.iter().all(|pubkey| {
+                                matches!(pubkey, Instruction::PushBytes(data) if is_pubkey_size(data))
+                            }) =>
+                    {
+                        ScriptTemplate::Multisig { required, keys }
+                    }
+                    _ => ScriptTemplate::NonStandard,
+                }
+            }
+            _ => ScriptTemplate::NonStandard,
+        }
+    }
+
+    /// Data of the last push of a push only script, the redeem script of a P2SH spend
+    pub fn last_push(&self) -> Option<&'a [u8]> {
+        if !self.is_push_only() {
+            return None;
+        }
+        self.instructions().last().map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(data)) => data,
+            // OP_1 to OP_16 push a number and leave no data, same as in bitcoin core
+            _ => &[],
+        })
+    }
+
+    /// Signature operations as counted by GetSigOpCount in bitcoin core. An
+    /// OP_CHECKMULTISIG costs as many keys as the OP_1 to OP_16 in front of it when
+    /// `accurate`, and the maximum otherwise. Counting stops at a malformed push
+    pub fn sigop_count(&self, accurate: bool) -> usize {
+        let mut count = 0;
+        let mut last_opcode: Option<u8> = None;
+        for instruction in self.instructions() {
+            let opcode = match instruction {
+                Ok(Instruction::Op(opcode)) => opcode,
+                Ok(Instruction::PushBytes(_)) => {
+                    last_opcode = None;
+                    continue;
+                }
+                Err(_) => break,
+            };
+            if OP_CHECKSIG == opcode || OP_CHECKSIGVERIFY == opcode {
+                count += 1;
+            } else if OP_CHECKMULTISIG == opcode || OP_CHECKMULTISIGVERIFY == opcode {
+                count += match last_opcode.and_then(small_int) {
+                    Some(keys) if accurate => keys as usize,
+                    _ => MAX_PUBKEYS_PER_MULTISIG,
+                };
+            }
+            last_opcode = Some(opcode.code);
+        }
+        count
+    }
+
+    /// The script in the asm format of the mempool json, opcodes by name and pushes as
+    /// the push opcode followed by the data in hex
+    pub fn to_asm(&self) -> String {
+        let mut asm: Vec<String> = Vec::new();
+        let mut instructions = self.instructions();
+        while let Some(&code) = self.bytes.get(instructions.offset()) {
+            match instructions.next() {
+                // OP_0 pushes nothing and is written on its own
+                Some(Ok(Instruction::PushBytes(data))) if code != OP_0.code => {
+                    asm.push(format!("{} {}", opcode_name(code), hex_str!(data)));
+                }
+                Some(Ok(_)) => asm.push(opcode_name(code)),
+                Some(Err(_)) => {
+                    asm.push(format!("{} <push past end>", opcode_name(code)));
+                    break;
+                }
+                None => break,
+            }
+        }
+        asm.join(" ")
+    }
+
+    pub fn instructions(&self) -> Instructions<'a> {
+        Instructions {
+            data: self.bytes,
+            offset: 0,
+            failed: false,
+        }
+    }
+}
+
+/// Owned script bytes, decoded from hex once when the json is loaded
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct ScriptBuf(Vec<u8>);
+
+impl ScriptBuf {
+    pub fn new() -> Self {
+        ScriptBuf(Vec::new())
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        ScriptBuf(bytes)
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Self, HexError> {
+        Ok(ScriptBuf(parse_hex(hex)?))
+    }
+
+    pub fn as_script(&self) -> Script<'_> {
+        Script::new(&self.0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for ScriptBuf {
+    fn from(bytes: Vec<u8>) -> Self {
+        ScriptBuf(bytes)
+    }
+}
+
+impl fmt::Display for ScriptBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for val in self.0.iter() {
+            write!(f, "{:02x}", val)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for ScriptBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ScriptBuf({})", self)
+    }
+}
+
+// scripts are hex in the mempool json, malformed hex fails loading the transaction
+impl<'de> Deserialize<'de> for ScriptBuf {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        ScriptBuf::from_hex(&hex).map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for ScriptBuf {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl Encodable for ScriptBuf {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.0.consensus_encode(writer)
+    }
+}
+
+impl Decodable for ScriptBuf {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        Ok(ScriptBuf(Decodable::consensus_decode(reader)?))
+    }
+}
+
+/// Iterator over the instructions of a script, stops after the first error
+pub struct Instructions<'a> {
+    data: &'a [u8],
+    offset: usize,
+    failed: bool,
+}
+
+impl<'a> Instructions<'a> {
+    /// Byte offset of the next instruction in the script
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    // read `len` bytes as a little endian length prefix
+    fn read_len(&mut self, len: usize, op_offset: usize) -> Result<usize, ScriptError> {
+        if self.offset + len > self.data.len() {
+            return Err(ScriptError::TruncatedPushLength { offset: op_offset });
+        }
+        let value = self.data[self.offset..self.offset + len]
+            .iter()
+            .rev()
+            .fold(0usize, |acc, val| (acc << 8) | *val as usize);
+        self.offset += len;
+        Ok(value)
+    }
+
+    fn take_push(&mut self, len: usize, op_offset: usize) -> Result<Instruction<'a>, ScriptError> {
+        let available = self.data.len() - self.offset;
+        if len > available {
+            return Err(ScriptError::TruncatedPush {
+                offset: op_offset,
+                expected: len,
+                available,
+            });
+        }
+        let data = &self.data[self.offset..self.offset + len];
+        self.offset += len;
+        Ok(Instruction::PushBytes(data))
+    }
+
+    fn parse_next(&mut self) -> Result<Instruction<'a>, ScriptError> {
+        let op_offset = self.offset;
+        let opcode = Opcode {
+            code: self.data[self.offset],
+            max_range: None,
+        };
+        self.offset += 1;
+
+        if OP_0 == opcode {
+            Ok(Instruction::PushBytes(&[]))
+        } else if OP_PUSHBYTES == opcode {
+            let len = (opcode.code - OP_PUSHBYTES.code + 1) as usize;
+            self.take_push(len, op_offset)
+        } else if OP_PUSHDATA1 == opcode {
+            let len = self.read_len(1, op_offset)?;
+            self.take_push(len, op_offset)
+        } else if OP_PUSHDATA2 == opcode {
+            let len = self.read_len(2, op_offset)?;
+            self.take_push(len, op_offset)
+        } else if OP_PUSHDATA4 == opcode {
+            let len = self.read_len(4, op_offset)?;
+            self.take_push(len, op_offset)
+        } else {
+            Ok(Instruction::Op(opcode))
+        }
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>, ScriptError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.offset >= self.data.len() {
+            return None;
+        }
+        let result = self.parse_next();
+        self.failed = result.is_err();
+        Some(result)
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::HashSet,
+    sync::{OnceLock, RwLock},
+};
+
+use libsecp256k1::{verify, Message, PublicKey, Signature};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use crate::schnorr;
+
+#[path = "./test/sigcache_tests.rs"]
+#[cfg(test)]
+mod sigcache_test;
+
+// roughly what bitcoin core keeps with its default 32MiB cache
+const DEFAULT_MAX_ENTRIES: usize = 1 << 20;
+
+// ecdsa and schnorr entries are hashed apart, like the 'E' and 'S' padding of the
+// salted hashers in bitcoin core
+const ECDSA_DOMAIN: u8 = b'E';
+const SCHNORR_DOMAIN: u8 = b'S';
+
+static SIGNATURE_CACHE: OnceLock<SignatureCache> = OnceLock::new();
+
+/// Process wide cache of signatures which already passed verification, so that
+/// re-validating a transaction does not redo the elliptic curve math
+pub fn signature_cache() -> &'static SignatureCache {
+    SIGNATURE_CACHE.get_or_init(|| SignatureCache::new(DEFAULT_MAX_ENTRIES))
+}
+
+pub struct SignatureCache {
+    // entries are salted so that nobody can craft colliding keys from outside
+    salt: [u8; 32],
+    max_entries: usize,
+    entries: RwLock<HashSet<[u8; 32]>>,
+}
+
+impl SignatureCache {
+    pub fn new(max_entries: usize) -> Self {
+        SignatureCache {
+            salt: rand::thread_rng().gen(),
+            max_entries,
+            entries: RwLock::new(HashSet::new()),
+        }
+    }
+
+    // key is sha256(salt || domain || sighash || pubkey || signature), the lengths are
+    // committed as well so that moving bytes between fields changes the key
+    fn entry_key(&self, domain: u8, sighash: &[u8], pubkey: &[u8], signature: &[u8]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.salt);
+        hasher.update([domain]);
+        for field in [sighash, pubkey, signature] {
+            hasher.update((field.len() as u32).to_le_bytes());
+            hasher.update(field);
+        }
+        hasher.finalize().into()
+    }
+
+    /// Whether an ECDSA signature is cached, Schnorr ones are only looked up by
+    /// `verify_schnorr`
+    pub fn contains(&self, sighash: &[u8], pubkey: &[u8], signature: &[u8]) -> bool {
+        self.contains_key(&self.entry_key(ECDSA_DOMAIN, sighash, pubkey, signature))
+    }
+
+    pub fn insert(&self, sighash: &[u8], pubkey: &[u8], signature: &[u8]) {
+        self.insert_key(self.entry_key(ECDSA_DOMAIN, sighash, pubkey, signature));
+    }
+
+    fn contains_key(&self, key: &[u8; 32]) -> bool {
+        self.entries.read().unwrap().contains(key)
+    }
+
+    fn insert_key(&self, key: [u8; 32]) {
+        let mut entries = self.entries.write().unwrap();
+        // a signature cached again must not push out another one
+        if entries.contains(&key) {
+            return;
+        }
+        if entries.len() >= self.max_entries {
+            // evict an arbitrary entry, the set iterates in a random order anyway
+            if let Some(evicted) = entries.iter().next().copied() {
+                entries.remove(&evicted);
+            }
+        }
+        entries.insert(key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Checks a DER encoded signature (without the sighash byte) against the 32 byte
+    /// sighash, only successful verifications are remembered
+    pub fn verify_ecdsa(&self, sighash: &[u8], pubkey: &[u8], signature: &[u8]) -> bool {
+        if self.contains(sighash, pubkey, signature) {
+            return true;
+        }
+
+        let is_valid = if let (Ok(msg), Ok(sig), Ok(pk)) = (
+            Message::parse_slice(sighash),
+            Signature::parse_der_lax(signature),
+            PublicKey::parse_slice(pubkey, None),
+        ) {
+            verify(&msg, &sig, &pk)
+        } else {
+            false
+        };
+
+        if is_valid {
+            self.insert(sighash, pubkey, signature);
+        }
+        is_valid
+    }
+
+    /// Checks a 64 byte BIP340 signature against the 32 byte sighash and the x only
+    /// pubkey, cached apart from the ECDSA signatures
+    pub fn verify_schnorr(&self, sighash: &[u8], pubkey: &[u8], signature: &[u8]) -> bool {
+        let key = self.entry_key(SCHNORR_DOMAIN, sighash, pubkey, signature);
+        if self.contains_key(&key) {
+            return true;
+        }
+
+        let is_valid = schnorr::verify_schnorr(sighash, pubkey, signature);
+        if is_valid {
+            self.insert_key(key);
+        }
+        is_valid
+    }
+}
@@ -0,0 +1,353 @@
+use crate::{
+    encode::{CompactSize, Encodable},
+    hash_utils::{double_hash256, hash256, tagged_hash},
+    opcodes::all_opcodes::OP_CODESEPARATOR,
+    script::{Instruction, Script},
+    transaction::{Transaction, Vin},
+};
+
+#[path = "./test/sighash_tests.rs"]
+#[cfg(test)]
+mod sighash_test;
+
+// taproot only, commits to everything like SIGHASH_ALL but leaves the byte out of the signature
+pub const SIGHASH_DEFAULT: u32 = 0x00;
+pub const SIGHASH_ALL: u32 = 0x01;
+pub const SIGHASH_NONE: u32 = 0x02;
+pub const SIGHASH_SINGLE: u32 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+/// Reasons a taproot signature hash can not be computed, the signature is invalid then
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SighashError {
+    InputOutOfRange { idx: usize, inputs: usize },
+    InvalidSighashType(u32),
+    SingleWithoutOutput { idx: usize },
+}
+
+/// Hashes over the whole transaction which every input's signature hash commits to.
+/// Same idea as PrecomputedTransactionData in bitcoin core, the data is computed once
+/// per validation of the transaction instead of once per input, which keeps sighash
+/// cost linear. It is not kept on the transaction, so it can not outlive a change
+#[derive(Debug, Clone)]
+pub struct PrecomputedTransactionData {
+    // BIP143 (segwit v0) hashes, these are double sha256
+    pub hash_prevouts: Vec<u8>,
+    pub hash_sequence: Vec<u8>,
+    pub hash_outputs: Vec<u8>,
+
+    // BIP341 (taproot) hashes, these are single sha256
+    pub sha_prevouts: Vec<u8>,
+    pub sha_amounts: Vec<u8>,
+    pub sha_scriptpubkeys: Vec<u8>,
+    pub sha_sequences: Vec<u8>,
+    pub sha_outputs: Vec<u8>,
+}
+
+impl PrecomputedTransactionData {
+    pub fn new(tx: &Transaction) -> Self {
+        let mut prevouts_bytes: Vec<u8> = Vec::new();
+        let mut amounts_bytes: Vec<u8> = Vec::new();
+        let mut scriptpubkeys_bytes: Vec<u8> = Vec::new();
+        let mut sequence_bytes: Vec<u8> = Vec::new();
+
+        for vin in tx.vin.iter() {
+            push(&mut prevouts_bytes, &vin.outpoint());
+            push(&mut amounts_bytes, &vin.prevout.value);
+            push(&mut scriptpubkeys_bytes, &vin.prevout.scriptpubkey);
+            push(&mut sequence_bytes, &vin.sequence);
+        }
+
+        let mut outputs_bytes: Vec<u8> = Vec::new();
+        for vout in tx.vout.iter() {
+            push(&mut outputs_bytes, vout);
+        }
+
+        let sha_prevouts = hash256(&prevouts_bytes);
+        let sha_sequences = hash256(&sequence_bytes);
+        let sha_outputs = hash256(&outputs_bytes);
+
+        PrecomputedTransactionData {
+            // double sha256 is just sha256 over the single sha256
+            hash_prevouts: hash256(&sha_prevouts),
+            hash_sequence: hash256(&sha_sequences),
+            hash_outputs: hash256(&sha_outputs),
+            sha_prevouts,
+            sha_amounts: hash256(&amounts_bytes),
+            sha_scriptpubkeys: hash256(&scriptpubkeys_bytes),
+            sha_sequences,
+            sha_outputs,
+        }
+    }
+}
+
+// appends the encoding of `value`
+fn push<T: Encodable + ?Sized>(bytes: &mut Vec<u8>, value: &T) {
+    value
+        .consensus_encode(bytes)
+        .expect("writing to a Vec never fails");
+}
+
+// legacy signatures never commit to OP_CODESEPARATOR, anything after a malformed push
+// is kept as it is
+fn remove_codeseparators(script_code: &[u8]) -> Vec<u8> {
+    let mut result: Vec<u8> = Vec::new();
+    let mut instructions = Script::new(script_code).instructions();
+    let mut start = 0;
+    loop {
+        let op_start = instructions.offset();
+        match instructions.next() {
+            Some(Ok(Instruction::Op(opcode))) if opcode.code == OP_CODESEPARATOR.code => {
+                result.extend_from_slice(&script_code[start..op_start]);
+                start = instructions.offset();
+            }
+            Some(Ok(_)) => {}
+            _ => break,
+        }
+    }
+    result.extend_from_slice(&script_code[start..]);
+    result
+}
+
+/// Message a pre segwit signature commits to, the transaction with `script_code` as the
+/// scriptsig of input `idx` followed by the sighash type. None for the cases where core
+/// signs the constant 1 instead, see `legacy_sighash`
+pub fn legacy_preimage(
+    tx: &Transaction,
+    idx: usize,
+    script_code: &[u8],
+    sighash_type: u32,
+) -> Option<Vec<u8>> {
+    let base_type = sighash_type & 0x1f;
+    let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+
+    if idx >= tx.vin.len() || (base_type == SIGHASH_SINGLE && idx >= tx.vout.len()) {
+        return None;
+    }
+
+    let mut raw_bytes: Vec<u8> = Vec::new();
+    push(&mut raw_bytes, &tx.version);
+
+    // with ANYONECANPAY only the input being signed is serialized
+    let inputs: Vec<(usize, &Vin)> = if anyone_can_pay {
+        vec![(idx, &tx.vin[idx])]
+    } else {
+        tx.vin.iter().enumerate().collect()
+    };
+    push(&mut raw_bytes, &CompactSize(inputs.len() as u64));
+
+    let script_code = remove_codeseparators(script_code);
+    for (i, vin) in inputs {
+        push(&mut raw_bytes, &vin.outpoint());
+
+        // only the input being signed carries a script
+        if i == idx {
+            push(&mut raw_bytes, script_code.as_slice());
+        } else {
+            push(&mut raw_bytes, &[] as &[u8]);
+        }
+
+        // NONE and SINGLE let the other inputs change their sequence
+        if i != idx && (base_type == SIGHASH_NONE || base_type == SIGHASH_SINGLE) {
+            push(&mut raw_bytes, &0u32);
+        } else {
+            push(&mut raw_bytes, &vin.sequence);
+        }
+    }
+
+    match base_type {
+        SIGHASH_NONE => push(&mut raw_bytes, &CompactSize(0)),
+        SIGHASH_SINGLE => {
+            // outputs before the one being signed are blanked to an amount of -1
+            push(&mut raw_bytes, &CompactSize((idx + 1) as u64));
+            for _ in 0..idx {
+                push(&mut raw_bytes, &u64::MAX);
+                push(&mut raw_bytes, &[] as &[u8]);
+            }
+            push(&mut raw_bytes, &tx.vout[idx]);
+        }
+        _ => push(&mut raw_bytes, &tx.vout),
+    }
+
+    push(&mut raw_bytes, &tx.locktime);
+    push(&mut raw_bytes, &sighash_type);
+    Some(raw_bytes)
+}
+
+/// Legacy signature hash. SIGHASH_SINGLE without a matching output and an input out of
+/// range hash to the constant 1, a consensus bug kept by every implementation
+pub fn legacy_sighash(
+    tx: &Transaction,
+    idx: usize,
+    script_code: &[u8],
+    sighash_type: u32,
+) -> Vec<u8> {
+    match legacy_preimage(tx, idx, script_code, sighash_type) {
+        Some(preimage) => double_hash256(&preimage),
+        None => {
+            let mut one = vec![0u8; 32];
+            one[0] = 0x01;
+            one
+        }
+    }
+}
+
+/// BIP143 message of input `idx` spending `amount`, the input has to exist.
+/// `precomputed` is the data of `tx` as it is now
+pub fn segwit_v0_preimage(
+    tx: &Transaction,
+    idx: usize,
+    script_code: &[u8],
+    amount: u64,
+    sighash_type: u32,
+    precomputed: &PrecomputedTransactionData,
+) -> Vec<u8> {
+    let base_type = sighash_type & 0x1f;
+    let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+    let zero = vec![0u8; 32];
+
+    let hash_prevouts = if anyone_can_pay {
+        &zero
+    } else {
+        &precomputed.hash_prevouts
+    };
+    let hash_sequence =
+        if anyone_can_pay || base_type == SIGHASH_SINGLE || base_type == SIGHASH_NONE {
+            &zero
+        } else {
+            &precomputed.hash_sequence
+        };
+    let hash_outputs = if base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE {
+        precomputed.hash_outputs.clone()
+    } else if base_type == SIGHASH_SINGLE && idx < tx.vout.len() {
+        let mut output_bytes: Vec<u8> = Vec::new();
+        push(&mut output_bytes, &tx.vout[idx]);
+        double_hash256(&output_bytes)
+    } else {
+        zero.clone()
+    };
+
+    let vin = &tx.vin[idx];
+
+    // preimage = version + hash256(inputs) + hash256(sequences) + input +
+    // scriptcode + amount + sequence + hash256(outputs) + locktime + sighash type
+    let mut raw_bytes: Vec<u8> = Vec::new();
+    push(&mut raw_bytes, &tx.version);
+    raw_bytes.extend(hash_prevouts);
+    raw_bytes.extend(hash_sequence);
+    push(&mut raw_bytes, &vin.outpoint());
+    push(&mut raw_bytes, script_code);
+    push(&mut raw_bytes, &amount);
+    push(&mut raw_bytes, &vin.sequence);
+    raw_bytes.extend(hash_outputs);
+    push(&mut raw_bytes, &tx.locktime);
+    push(&mut raw_bytes, &sighash_type);
+    raw_bytes
+}
+
+pub fn segwit_v0_sighash(
+    tx: &Transaction,
+    idx: usize,
+    script_code: &[u8],
+    amount: u64,
+    sighash_type: u32,
+    precomputed: &PrecomputedTransactionData,
+) -> Vec<u8> {
+    double_hash256(&segwit_v0_preimage(
+        tx,
+        idx,
+        script_code,
+        amount,
+        sighash_type,
+        precomputed,
+    ))
+}
+
+/// Tapscript leaf being executed, only present for script path spends
+#[derive(Debug, Clone, Copy)]
+pub struct ScriptPath<'a> {
+    pub leaf_hash: &'a [u8],
+    // opcode position of the last executed OP_CODESEPARATOR, 0xffffffff when there is none
+    pub codesep_pos: u32,
+}
+
+/// BIP341 signature hash of input `idx`. The prevouts of every input are part of the
+/// message, so they have to be filled in on the transaction before `precomputed` is
+pub fn taproot_sighash(
+    tx: &Transaction,
+    idx: usize,
+    sighash_type: u32,
+    annex: Option<&[u8]>,
+    script_path: Option<ScriptPath>,
+    precomputed: &PrecomputedTransactionData,
+) -> Result<Vec<u8>, SighashError> {
+    if idx >= tx.vin.len() {
+        return Err(SighashError::InputOutOfRange {
+            idx,
+            inputs: tx.vin.len(),
+        });
+    }
+    if !matches!(sighash_type, 0x00..=0x03 | 0x81..=0x83) {
+        return Err(SighashError::InvalidSighashType(sighash_type));
+    }
+
+    let output_type = if sighash_type == SIGHASH_DEFAULT {
+        SIGHASH_ALL
+    } else {
+        sighash_type & 0x03
+    };
+    let anyone_can_pay = sighash_type & SIGHASH_ANYONECANPAY != 0;
+    let vin = &tx.vin[idx];
+
+    // epoch 0 followed by SigMsg
+    let mut msg: Vec<u8> = vec![0x00, sighash_type as u8];
+    push(&mut msg, &tx.version);
+    push(&mut msg, &tx.locktime);
+
+    if !anyone_can_pay {
+        msg.extend(&precomputed.sha_prevouts);
+        msg.extend(&precomputed.sha_amounts);
+        msg.extend(&precomputed.sha_scriptpubkeys);
+        msg.extend(&precomputed.sha_sequences);
+    }
+    if output_type == SIGHASH_ALL {
+        msg.extend(&precomputed.sha_outputs);
+    }
+
+    let ext_flag: u8 = if script_path.is_some() { 1 } else { 0 };
+    msg.push(ext_flag * 2 + annex.is_some() as u8);
+
+    if anyone_can_pay {
+        push(&mut msg, &vin.outpoint());
+        push(&mut msg, &vin.prevout);
+        push(&mut msg, &vin.sequence);
+    } else {
+        push(&mut msg, &(idx as u32));
+    }
+
+    if let Some(annex) = annex {
+        let mut annex_bytes: Vec<u8> = Vec::new();
+        push(&mut annex_bytes, annex);
+        msg.extend(hash256(&annex_bytes));
+    }
+
+    if output_type == SIGHASH_SINGLE {
+        match tx.vout.get(idx) {
+            Some(vout) => {
+                let mut output_bytes: Vec<u8> = Vec::new();
+                push(&mut output_bytes, vout);
+                msg.extend(hash256(&output_bytes));
+            }
+            None => return Err(SighashError::SingleWithoutOutput { idx }),
+        }
+    }
+
+    if let Some(script_path) = script_path {
+        msg.extend_from_slice(script_path.leaf_hash);
+        // key version 0 is the only one defined so far
+        msg.push(0x00);
+        push(&mut msg, &script_path.codesep_pos);
+    }
+
+    Ok(tagged_hash("TapSighash", &msg))
+}
@@ -1,45 +1,115 @@
-#[derive(Debug)]
-pub struct Node<T> {
-    pub data: T,
-    pub next: Option<Box<Node<T>>>,
+#[path = "./test/stack_tests.rs"]
+#[cfg(test)]
+mod stack_test;
+
+/// Pushing onto a stack which already holds `limit` elements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StackOverflow {
+    pub limit: usize,
 }
 
+/// Contiguous stack, the top of the stack is the last element of the vector. Depths
+/// are counted from the top, depth 0 is the top element
+#[derive(Debug, Clone)]
 pub struct Stack<T> {
-    pub top: Option<Node<T>>,
+    items: Vec<T>,
+    limit: usize,
 }
 
-impl<T> Node<T> {
-    fn new(data: T) -> Self {
-        Node { data, next: None }
+impl<T> Default for Stack<T> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl<T> Stack<T> {
     pub fn new() -> Self {
-        Stack { top: None }
+        Self::with_limit(usize::MAX)
+    }
+
+    pub fn with_limit(limit: usize) -> Self {
+        Stack {
+            items: Vec::new(),
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
     }
 
     pub fn is_empty(&self) -> bool {
-        self.top.is_none()
+        self.items.is_empty()
     }
 
-    pub fn push(&mut self, data: T) {
-        let mut node = Node::<T>::new(data);
-        if let Some(top) = std::mem::replace(&mut self.top, None) {
-            node.next = Some(Box::new(top));
+    pub fn push(&mut self, data: T) -> Result<(), StackOverflow> {
+        if self.items.len() >= self.limit {
+            return Err(StackOverflow { limit: self.limit });
         }
-        self.top = Some(node);
+        self.items.push(data);
+        Ok(())
     }
 
     pub fn pop(&mut self) -> Option<T> {
-        if let Some(top) = std::mem::replace(&mut self.top, None) {
-            self.top = match top.next {
-                Some(n) => Some(*n),
-                None => None,
-            };
-            Some(top.data)
+        self.items.pop()
+    }
+
+    pub fn peek(&self) -> Option<&T> {
+        self.items.last()
+    }
+
+    // converts a depth from the top into an index of the vector
+    fn index(&self, depth: usize) -> Option<usize> {
+        if depth < self.items.len() {
+            Some(self.items.len() - 1 - depth)
         } else {
             None
         }
     }
+
+    /// Element at `depth` from the top
+    pub fn peek_at(&self, depth: usize) -> Option<&T> {
+        self.index(depth).map(|idx| &self.items[idx])
+    }
+
+    pub fn peek_at_mut(&mut self, depth: usize) -> Option<&mut T> {
+        self.index(depth).map(|idx| &mut self.items[idx])
+    }
+
+    /// Inserts so that the new element ends up at `depth`, any depth past the bottom
+    /// puts it at the bottom
+    pub fn insert_at(&mut self, depth: usize, data: T) -> Result<(), StackOverflow> {
+        if self.items.len() >= self.limit {
+            return Err(StackOverflow { limit: self.limit });
+        }
+        let depth = depth.min(self.items.len());
+        let idx = self.items.len() - depth;
+        self.items.insert(idx, data);
+        Ok(())
+    }
+
+    /// Removes and returns the element at `depth`
+    pub fn remove_at(&mut self, depth: usize) -> Option<T> {
+        self.index(depth).map(|idx| self.items.remove(idx))
+    }
+
+    /// Swaps the elements at the two depths, false if either is out of range
+    pub fn swap(&mut self, first: usize, second: usize) -> bool {
+        match (self.index(first), self.index(second)) {
+            (Some(first), Some(second)) => {
+                self.items.swap(first, second);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Iterates from the bottom of the stack to the top
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.items.iter()
+    }
 }
@@ -1,43 +1,120 @@
-use std::num::ParseIntError;
+use std::{fmt, num::ParseIntError};
+
+use crate::encode::{serialize, CompactSize};
 
 pub fn get_compact_size_bytes(data: &u64) -> Vec<u8> {
-    let mut result: Vec<u8> = Vec::new();
-    if *data <= 0xFC {
-        result.push(*data as u8);
-    } else if *data <= 0xFFFF {
-        result.push(0xFD);
-        (*data as u16)
-            .to_be_bytes()
-            .iter()
-            .rev()
-            .for_each(|val| result.push(*val));
-    } else if *data <= 0xFFFFFFFF {
-        result.push(0xFE);
-        (*data as u32)
-            .to_be_bytes()
-            .iter()
-            .rev()
-            .for_each(|val| result.push(*val));
-    } else {
-        result.push(0xFF);
-        (*data)
-            .to_be_bytes()
-            .iter()
-            .rev()
-            .for_each(|val| result.push(*val));
-    }
-    result
+    serialize(&CompactSize(*data))
 }
 
 pub fn get_hex_bytes(num: &str) -> Result<Vec<u8>, ParseIntError> {
     (0..num.len()).step_by(2).map(|i| u8::from_str_radix(&num[i..i+2], 16)).collect()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::get_compact_size_bytes;
+/// Reasons a hex string can not be decoded, positions are character offsets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HexError {
+    OddLength(usize),
+    InvalidChar(usize),
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::OddLength(len) => write!(f, "hex of odd length {}", len),
+            HexError::InvalidChar(pos) => write!(f, "invalid hex character at {}", pos),
+        }
+    }
+}
+
+/// Strict version of get_hex_bytes, odd lengths and non ascii input are errors
+/// instead of panics
+pub fn parse_hex(hex: &str) -> Result<Vec<u8>, HexError> {
+    let bytes = hex.as_bytes();
+    if !bytes.len().is_multiple_of(2) {
+        return Err(HexError::OddLength(bytes.len()));
+    }
+    let nibble = |pos: usize| {
+        (bytes[pos] as char)
+            .to_digit(16)
+            .map(|val| val as u8)
+            .ok_or(HexError::InvalidChar(pos))
+    };
+    (0..bytes.len())
+        .step_by(2)
+        .map(|pos| Ok(nibble(pos)? << 4 | nibble(pos + 1)?))
+        .collect()
+}
+
+const BASE64_CHARS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Reasons a base64 string can not be decoded, positions are character offsets
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Base64Error {
+    // only padded base64 is accepted, the length is a multiple of 4
+    InvalidLength(usize),
+    InvalidChar(usize),
+}
+
+impl fmt::Display for Base64Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Base64Error::InvalidLength(len) => write!(f, "base64 of invalid length {}", len),
+            Base64Error::InvalidChar(pos) => write!(f, "invalid base64 character at {}", pos),
+        }
+    }
+}
 
+/// Padded base64 of RFC 4648, the text encoding of PSBTs
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    for chunk in data.chunks(3) {
+        let bits = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |acc, (idx, val)| acc | (*val as u32) << (16 - 8 * idx));
+        for idx in 0..4 {
+            if idx <= chunk.len() {
+                encoded.push(BASE64_CHARS[(bits >> (18 - 6 * idx) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+pub fn decode_base64(encoded: &str) -> Result<Vec<u8>, Base64Error> {
+    let chars = encoded.as_bytes();
+    if !chars.len().is_multiple_of(4) {
+        return Err(Base64Error::InvalidLength(chars.len()));
+    }
+    let mut data: Vec<u8> = Vec::new();
+    for (chunk_idx, chunk) in chars.chunks(4).enumerate() {
+        let is_last = (chunk_idx + 1) * 4 == chars.len();
+        // padding is only allowed at the end, at most two characters of it
+        let padding = chunk.iter().rev().take_while(|val| **val == b'=').count();
+        if padding > 2 || (padding > 0 && !is_last) {
+            return Err(Base64Error::InvalidChar(chunk_idx * 4 + 4 - padding));
+        }
+        let mut bits = 0u32;
+        for (idx, val) in chunk[..4 - padding].iter().enumerate() {
+            let sextet = BASE64_CHARS
+                .iter()
+                .position(|char| char == val)
+                .ok_or(Base64Error::InvalidChar(chunk_idx * 4 + idx))?;
+            bits |= (sextet as u32) << (18 - 6 * idx);
+        }
+        data.extend_from_slice(&bits.to_be_bytes()[1..4 - padding]);
+    }
+    Ok(data)
+}
 
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_base64, encode_base64, get_compact_size_bytes, parse_hex, Base64Error, HexError,
+    };
 
     #[test]
     pub fn test_compact_size_bytes() {
@@ -50,4 +127,37 @@ mod tests {
 
         println!("value {:?}/{:?}", x, y);
     }
+
+    #[test]
+    pub fn test_parse_hex() {
+        assert_eq!(parse_hex("00aBff"), Ok(vec![0x00, 0xab, 0xff]));
+        assert_eq!(parse_hex(""), Ok(vec![]));
+        assert_eq!(parse_hex("abc"), Err(HexError::OddLength(3)));
+        assert_eq!(parse_hex("0g"), Err(HexError::InvalidChar(1)));
+        assert_eq!(parse_hex("é"), Err(HexError::InvalidChar(0)));
+    }
+
+    #[test]
+    pub fn test_base64() {
+        // the vectors of RFC 4648
+        for (data, encoded) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode_base64(data.as_bytes()), encoded);
+            assert_eq!(decode_base64(encoded), Ok(data.as_bytes().to_vec()));
+        }
+        assert_eq!(encode_base64(&[0xfb, 0xff]), "+/8=");
+        assert_eq!(decode_base64("+/8="), Ok(vec![0xfb, 0xff]));
+
+        assert_eq!(decode_base64("Zm9"), Err(Base64Error::InvalidLength(3)));
+        assert_eq!(decode_base64("Zm9v*A=="), Err(Base64Error::InvalidChar(4)));
+        assert_eq!(decode_base64("Zg==Zm9v"), Err(Base64Error::InvalidChar(2)));
+        assert_eq!(decode_base64("Z==="), Err(Base64Error::InvalidChar(1)));
+    }
 }
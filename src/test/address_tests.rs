@@ -0,0 +1,202 @@
+use crate::{
+    hash_types::Txid,
+    script::ScriptBuf,
+    transaction::{OutPoint, Pubkey, Transaction},
+    utils::{prepare_coinbase_tx, COINBASE_PAYOUT_ADDRESS},
+};
+
+use super::*;
+
+// scripts and the addresses of the mempool json, then the BIP173 and BIP350 vectors
+const VECTORS: [(Network, &str, &str); 11] = [
+    (
+        Network::Bitcoin,
+        "76a91487c8847f88bacb68934f0e12434bc96a6a58435388ac",
+        "1DNxS6vmG9Z5Rzk6SVb6HvqkMAUDZr4jLn",
+    ),
+    (
+        Network::Bitcoin,
+        "a91450feb99697a4901d3fe082eca341204fb6711b9487",
+        "395H8VPYPtAoZWa2bx5SRyN2VojXrsb7j3",
+    ),
+    (
+        Network::Bitcoin,
+        "0014b9c1c402d4a435ba726f52e31c71e254af33ee0e",
+        "bc1qh8qugqk55s6m5un02t33cu0z2jhn8msw8rv8mr",
+    ),
+    (
+        Network::Bitcoin,
+        "00205ca04b274e808f26e5ccb7f3a9f8ba13580925169e55dd39fb9c34b424d114f7",
+        "bc1qtjsykf6wsz8jdewvkle6n796zdvqjfgkne2a6w0mns6tgfx3znmszdyn06",
+    ),
+    (
+        Network::Bitcoin,
+        "5120d7b0161160dc8ce46dfbf55c602ffeef5ed4ebcadebbe077918819b0aa1b73e6",
+        "bc1p67cpvytqmjxwgm0m74wxqtl7aa0df672m6a7qau33qvmp2smw0nqrgxc8d",
+    ),
+    (
+        Network::Bitcoin,
+        "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac",
+        "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+    ),
+    (
+        Network::Testnet,
+        "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+        "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+    ),
+    (
+        Network::Bitcoin,
+        "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+    ),
+    (
+        Network::Bitcoin,
+        "5210751e76e8199196d454941c45d1b3a323",
+        "bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs",
+    ),
+    (Network::Bitcoin, "6002751e", "bc1sw50qgdz25j"),
+    // pay to anchor
+    (Network::Bitcoin, "51024e73", "bc1pfeessrawgf"),
+];
+
+#[test]
+pub fn addresses_and_scripts_convert_both_ways() {
+    for (network, script_hex, address) in VECTORS {
+        let script = ScriptBuf::from_hex(script_hex).unwrap();
+        let derived = Address::from_script(script.as_script(), network).unwrap();
+        assert_eq!(derived.to_string(), address);
+
+        let parsed = Address::parse(address, network).unwrap();
+        assert_eq!(parsed, derived);
+        assert_eq!(parsed.script_pubkey(), script);
+    }
+
+    // segwit addresses can be written in upper case, e.g. for QR codes
+    let upper = VECTORS[2].2.to_ascii_uppercase();
+    assert_eq!(
+        Address::parse(&upper, Network::Bitcoin).unwrap().to_string(),
+        VECTORS[2].2
+    );
+
+    // signet shares the testnet encodings, regtest only its base58 prefixes
+    let testnet = Address::parse(VECTORS[6].2, Network::Signet).unwrap();
+    assert_eq!(testnet.network, Network::Signet);
+    let regtest = Address { network: Network::Regtest, ..testnet };
+    assert!(regtest.to_string().starts_with("bcrt1q"));
+    assert_eq!(Address::parse(&regtest.to_string(), Network::Regtest), Ok(regtest));
+
+    // OP_RETURN, a bare 1-of-1 multisig and a version 0 program of 24 bytes
+    let without_address = [
+        "6a0401020304",
+        "51210279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f8179851ae",
+        "0018000000000000000000000000000000000000000000000000",
+    ];
+    for script_hex in without_address {
+        let script = ScriptBuf::from_hex(script_hex).unwrap();
+        assert_eq!(Address::from_script(script.as_script(), Network::Bitcoin), None);
+    }
+}
+
+#[test]
+pub fn invalid_addresses() {
+    let parse = |address: &str| Address::parse(address, Network::Bitcoin).err();
+
+    // one character changed
+    assert_eq!(
+        parse("bc1qh8qugqk55s6m5un02t33cu0z2jhn8msw8rv8mq"),
+        Some(AddressError::InvalidChecksum)
+    );
+    assert_eq!(
+        parse("1DNxS6vmG9Z5Rzk6SVb6HvqkMAUDZr4jLm"),
+        Some(AddressError::InvalidChecksum)
+    );
+    assert_eq!(
+        parse("bc1qh8qugqk55s6m5un02t33cu0z2jhn8msw8rv8mR"),
+        Some(AddressError::MixedCase)
+    );
+    assert_eq!(
+        parse("bc1qh8qugqk55s6m5un02t33cu0z2jhn8msw8rv8mb"),
+        Some(AddressError::InvalidBech32Char(41))
+    );
+    assert_eq!(
+        parse("1DNxS6vmG9Z5Rzk6SVb6HvqkMAUDZr4jL0"),
+        Some(AddressError::InvalidBase58Char(33))
+    );
+    assert_eq!(parse("1111111111"), Some(AddressError::InvalidLength(6)));
+
+    // addresses of another network
+    assert_eq!(
+        parse(VECTORS[6].2),
+        Some(AddressError::WrongNetwork { expected: Network::Bitcoin })
+    );
+    let testnet_p2pkh = Address {
+        network: Network::Testnet,
+        payload: Payload::PubkeyHash([0x62; 20]),
+    };
+    assert_eq!(
+        parse(&testnet_p2pkh.to_string()),
+        Some(AddressError::WrongNetwork { expected: Network::Bitcoin })
+    );
+
+    // a taproot program with the checksum of version 0, and the other way around
+    let program = convert_bits(&[0x79; 32], 8, 5, true).unwrap();
+    for (version, checksum_const) in [(1, BECH32_CONST), (0, BECH32M_CONST)] {
+        let data = [&[version], program.as_slice()].concat();
+        assert_eq!(
+            parse(&encode_bech32("bc", &data, checksum_const)),
+            Some(AddressError::WrongChecksumVariant { version })
+        );
+    }
+
+    // version 0 lengths of neither output type, programs out of range and versions
+    // past 16
+    for (version, len) in [(0, 24), (1, 1), (2, 41), (17, 32)] {
+        let program = convert_bits(&vec![0; len], 8, 5, true).unwrap();
+        let data = [&[version], program.as_slice()].concat();
+        let checksum_const = if version == 0 { BECH32_CONST } else { BECH32M_CONST };
+        assert_eq!(
+            parse(&encode_bech32("bc", &data, checksum_const)),
+            Some(AddressError::InvalidWitnessProgram { version, len })
+        );
+    }
+}
+
+#[test]
+pub fn json_addresses_are_checked_against_scripts() {
+    let p2wpkh = ScriptBuf::from_hex(VECTORS[2].1).unwrap();
+    let p2tr = ScriptBuf::from_hex(VECTORS[4].1).unwrap();
+    let mut tx = Transaction::builder()
+        .add_input(
+            OutPoint { txid: Txid::from_byte_array([1; 32]), vout: 0 },
+            Pubkey::new(p2wpkh, 20_000),
+        )
+        .add_output(p2tr, 15_000)
+        .add_output(ScriptBuf::from_hex("6a0401020304").unwrap(), 0)
+        .build();
+
+    // built outputs get their mainnet address, OP_RETURN outputs have none
+    assert_eq!(tx.vin[0].prevout.scriptpubkey_address.as_deref(), Some(VECTORS[2].2));
+    assert_eq!(tx.vout[1].scriptpubkey_address, None);
+    assert!(tx.address_mismatches(Network::Bitcoin).is_empty());
+    assert_eq!(tx.address_mismatches(Network::Testnet).len(), 2);
+
+    tx.vout[0].scriptpubkey_address = Some(String::from(VECTORS[3].2));
+    tx.vout[1].scriptpubkey_address = Some(String::new());
+    let mismatches = tx.address_mismatches(Network::Bitcoin);
+    assert_eq!(mismatches.len(), 2);
+    assert_eq!(mismatches[0].location, "vout 0");
+    assert_eq!(mismatches[0].derived.as_deref(), Some(VECTORS[4].2));
+    assert_eq!(mismatches[1].location, "vout 1");
+    assert_eq!(mismatches[1].derived, None);
+}
+
+#[test]
+pub fn coinbase_pays_to_the_payout_address() {
+    let coinbase = prepare_coinbase_tx(&[]);
+    let payout = &coinbase.vout[0];
+    assert_eq!(
+        payout.scriptpubkey.to_string(),
+        "76a914edf10a7fac6b32e24daa5305c723f3de58db1bc888ac"
+    );
+    assert_eq!(payout.scriptpubkey_address.as_deref(), Some(COINBASE_PAYOUT_ADDRESS));
+}
@@ -0,0 +1,45 @@
+use super::*;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+pub fn block_context_from_args() {
+    assert_eq!(BlockContext::from_args(args(&[])), Ok(BlockContext::default()));
+
+    // the median time past follows the height unless it is given as well
+    let context = BlockContext::from_args(args(&["--height", "840006"])).unwrap();
+    assert_eq!(context.height, 840_006);
+    assert_eq!(context.median_time_past, HALVING_TIME);
+    let context = BlockContext::from_args(args(&[
+        "--median-time-past",
+        "1700000000",
+        "--height",
+        "800000",
+    ]))
+    .unwrap();
+    assert_eq!(
+        context,
+        BlockContext {
+            height: 800_000,
+            median_time_past: 1_700_000_000
+        }
+    );
+
+    assert_eq!(
+        BlockContext::from_args(args(&["--time", "1"])),
+        Err(ContextArgError::UnknownArgument(String::from("--time")))
+    );
+    assert_eq!(
+        BlockContext::from_args(args(&["--height"])),
+        Err(ContextArgError::MissingValue(String::from("--height")))
+    );
+    assert_eq!(
+        BlockContext::from_args(args(&["--height", "-1"])),
+        Err(ContextArgError::InvalidValue {
+            arg: String::from("--height"),
+            value: String::from("-1")
+        })
+    );
+}
@@ -0,0 +1,196 @@
+use libsecp256k1::{PublicKey, SecretKey};
+
+use crate::{
+    hash_types::Txid,
+    hash_utils::pk_hash,
+    interpreter::VerifyFlags,
+    schnorr::{tweak_public_key, verify_schnorr, xonly_public_key},
+    script::{ScriptBuf, ScriptTemplate},
+    sighash::{
+        taproot_sighash, PrecomputedTransactionData, SighashError, SIGHASH_ALL, SIGHASH_DEFAULT,
+        SIGHASH_SINGLE,
+    },
+    transaction::{InputVerdict, OutPoint, Pubkey, Transaction},
+};
+
+use super::{SignError, DEFAULT_SEQUENCE};
+
+fn secret_key(byte: u8) -> SecretKey {
+    SecretKey::parse(&[byte; 32]).unwrap()
+}
+
+fn compressed_pubkey(secret_key: &SecretKey) -> Vec<u8> {
+    PublicKey::from_secret_key(secret_key).serialize_compressed().to_vec()
+}
+
+fn p2pkh(secret_key: &SecretKey) -> ScriptBuf {
+    let hash = pk_hash(&compressed_pubkey(secret_key));
+    ScriptBuf::from_bytes([&[0x76, 0xa9, 0x14], hash.as_slice(), &[0x88, 0xac]].concat())
+}
+
+fn p2wpkh(secret_key: &SecretKey) -> ScriptBuf {
+    let hash = pk_hash(&compressed_pubkey(secret_key));
+    ScriptBuf::from_bytes([&[0x00, 0x14], hash.as_slice()].concat())
+}
+
+fn p2tr(secret_key: &SecretKey) -> ScriptBuf {
+    let output_key = tweak_public_key(&xonly_public_key(secret_key), None).unwrap();
+    ScriptBuf::from_bytes([&[0x51, 0x20], output_key.as_slice()].concat())
+}
+
+fn outpoint(byte: u8, vout: u32) -> OutPoint {
+    OutPoint {
+        txid: Txid::from_byte_array([byte; 32]),
+        vout,
+    }
+}
+
+#[test]
+pub fn prevouts_and_outputs_get_their_labels() {
+    let output = Pubkey::new(p2wpkh(&secret_key(1)), 1000);
+    assert_eq!(output.scriptpubkey_type, "v0_p2wpkh");
+    assert_eq!(
+        output.scriptpubkey_asm,
+        format!("OP_0 OP_PUSHBYTES_20 {}", &output.scriptpubkey.to_string()[4..])
+    );
+
+    let tx = Transaction::builder()
+        .locktime(834_000)
+        .add_input(outpoint(1, 0), Pubkey::new(p2pkh(&secret_key(1)), 20_000))
+        .add_input(outpoint(1, 1), Pubkey::new(p2pkh(&secret_key(1)), 20_000))
+        .sequence(0xfffffffe)
+        .add_output(p2tr(&secret_key(2)), 35_000)
+        .build();
+
+    assert_eq!(tx.version, 2);
+    assert_eq!(tx.locktime, 834_000);
+    assert_eq!(tx.vin[0].sequence, DEFAULT_SEQUENCE);
+    assert_eq!(tx.vin[1].sequence, 0xfffffffe);
+    assert_eq!(tx.vout[0].scriptpubkey_type, "v1_p2tr");
+    assert_eq!(tx.tx_fee, Some(5_000));
+    assert!(!tx.has_witness());
+}
+
+#[test]
+pub fn signed_legacy_and_segwit_inputs_are_valid() {
+    let key = secret_key(1);
+    let mut tx = Transaction::builder()
+        .add_input(outpoint(1, 0), Pubkey::new(p2pkh(&key), 50_000))
+        .add_input(outpoint(1, 1), Pubkey::new(p2wpkh(&key), 30_000))
+        .add_output(p2wpkh(&secret_key(2)), 60_000)
+        .add_output(p2pkh(&key), 15_000)
+        .build();
+    let unsigned_txid = tx.txid();
+
+    tx.sign(&[key, key], SIGHASH_ALL).unwrap();
+    assert_ne!(tx.txid(), unsigned_txid);
+    assert!(tx.has_witness());
+    assert!(tx.vin[0].scriptsig_asm.starts_with("OP_PUSHBYTES_"));
+
+    assert!(tx
+        .verify_inputs(VerifyFlags::STANDARD)
+        .iter()
+        .all(|verdict| *verdict == InputVerdict::Valid));
+    assert!(tx.validate_transacation());
+
+    // the other output is committed to, changing it invalidates both signatures
+    tx.vout[1].value -= 1;
+    assert!(tx
+        .verify_inputs(VerifyFlags::STANDARD)
+        .iter()
+        .all(|verdict| !verdict.is_valid()));
+}
+
+#[test]
+pub fn signed_taproot_input_verifies() {
+    let key = secret_key(3);
+    let mut tx = Transaction::builder()
+        .add_input(outpoint(2, 0), Pubkey::new(p2tr(&key), 10_000))
+        .add_input(outpoint(2, 1), Pubkey::new(p2tr(&key), 10_000))
+        .add_output(p2tr(&key), 19_000)
+        .build();
+
+    tx.sign_input(0, &key, SIGHASH_DEFAULT).unwrap();
+    tx.sign_input(1, &key, SIGHASH_ALL).unwrap();
+
+    for (idx, sighash_type) in [(0, SIGHASH_DEFAULT), (1, SIGHASH_ALL)] {
+        let witness = tx.vin[idx].witness.as_ref().unwrap();
+        assert_eq!(witness.len(), 1);
+        // the sighash byte is only there when it is not SIGHASH_DEFAULT
+        let signature = &witness.0[0];
+        assert_eq!(signature.len(), if idx == 0 { 64 } else { 65 });
+
+        let precomputed = PrecomputedTransactionData::new(&tx);
+        let sighash = taproot_sighash(&tx, idx, sighash_type, None, None, &precomputed).unwrap();
+        let program = &tx.vin[idx].prevout.scriptpubkey.as_bytes()[2..];
+        assert!(verify_schnorr(&sighash, program, &signature[..64]));
+    }
+}
+
+#[test]
+pub fn signing_errors() {
+    let key = secret_key(1);
+    let mut tx = Transaction::builder()
+        .add_input(outpoint(1, 0), Pubkey::new(p2wpkh(&key), 50_000))
+        .add_input(outpoint(1, 1), Pubkey::new(ScriptBuf::from_hex("51").unwrap(), 1_000))
+        .add_output(p2wpkh(&key), 40_000)
+        .build();
+
+    assert_eq!(
+        tx.sign_input(0, &secret_key(2), SIGHASH_ALL),
+        Err(SignError::KeyMismatch { vin: 0 })
+    );
+    assert_eq!(
+        tx.sign_input(0, &key, 0x04),
+        Err(SignError::Sighash(SighashError::InvalidSighashType(0x04)))
+    );
+    assert_eq!(
+        tx.sign_input(1, &key, SIGHASH_ALL),
+        Err(SignError::UnsupportedScript {
+            vin: 1,
+            template: ScriptTemplate::NonStandard
+        })
+    );
+    assert_eq!(
+        tx.sign_input(2, &key, SIGHASH_ALL),
+        Err(SignError::Sighash(SighashError::InputOutOfRange { idx: 2, inputs: 2 }))
+    );
+
+    // the output of the second input is missing, the legacy sighash would be 1
+    tx.vin[1].prevout = Pubkey::new(p2pkh(&key), 1_000);
+    assert_eq!(
+        tx.sign_input(1, &key, SIGHASH_SINGLE),
+        Err(SignError::Sighash(SighashError::SingleWithoutOutput { idx: 1 }))
+    );
+    assert!(tx.vin.iter().all(|vin| vin.scriptsig.is_empty() && vin.witness.is_none()));
+}
+
+#[test]
+pub fn signed_transactions_load_back_from_json() {
+    let key = secret_key(4);
+    let mut parent = Transaction::builder()
+        .add_input(outpoint(3, 0), Pubkey::new(p2wpkh(&key), 100_000))
+        .add_output(p2pkh(&key), 90_000)
+        .build();
+    parent.sign_input(0, &key, SIGHASH_ALL).unwrap();
+
+    // a child spending the parent's output, chains of test transactions work the same
+    let mut child = Transaction::builder()
+        .add_input(parent.outpoint(0), parent.vout[0].clone())
+        .add_output(p2tr(&key), 85_000)
+        .build();
+    child.sign_input(0, &key, SIGHASH_ALL).unwrap();
+    assert_eq!(child.vin[0].txid, parent.txid());
+
+    for tx in [parent, child] {
+        let loaded = Transaction::new(&tx.to_json()).unwrap();
+        assert_eq!(loaded.txid(), tx.txid());
+        assert_eq!(loaded.wtxid(), tx.wtxid());
+        assert_eq!(loaded.weight, tx.weight);
+        assert_eq!(loaded.tx_fee, tx.tx_fee);
+        assert_eq!(loaded.vin[0].scriptsig_asm, tx.vin[0].scriptsig_asm);
+        assert_eq!(tx.sanity_hash, Some(loaded.compute_sanity_hash()));
+        assert!(loaded.check_transaction().is_ok());
+        assert!(loaded.validate_transacation());
+    }
+}
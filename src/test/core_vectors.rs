@@ -0,0 +1,323 @@
+// Helpers to run the json test vectors of bitcoin core against this crate.
+// The vectors live in src/test/data and use core's conventions, e.g. scripts are
+// written in core's test asm and amounts in BTC.
+
+use serde_json::Value;
+
+use crate::{
+    hash_types::Txid,
+    script::{ScriptBuf, ScriptError},
+    str_utils::get_hex_bytes,
+    transaction::{InputVerdict, Pubkey, Transaction, Vin, Witness},
+};
+
+// opcodes which core's test asm knows by name, OP_0 to OP_16 are written as numbers
+const OPCODE_NAMES: [(&str, u8); 91] = [
+    ("RESERVED", 0x50),
+    ("NOP", 0x61),
+    ("VER", 0x62),
+    ("IF", 0x63),
+    ("NOTIF", 0x64),
+    ("VERIF", 0x65),
+    ("VERNOTIF", 0x66),
+    ("ELSE", 0x67),
+    ("ENDIF", 0x68),
+    ("VERIFY", 0x69),
+    ("RETURN", 0x6a),
+    ("TOALTSTACK", 0x6b),
+    ("FROMALTSTACK", 0x6c),
+    ("2DROP", 0x6d),
+    ("2DUP", 0x6e),
+    ("3DUP", 0x6f),
+    ("2OVER", 0x70),
+    ("2ROT", 0x71),
+    ("2SWAP", 0x72),
+    ("IFDUP", 0x73),
+    ("DEPTH", 0x74),
+    ("DROP", 0x75),
+    ("DUP", 0x76),
+    ("NIP", 0x77),
+    ("OVER", 0x78),
+    ("PICK", 0x79),
+    ("ROLL", 0x7a),
+    ("ROT", 0x7b),
+    ("SWAP", 0x7c),
+    ("TUCK", 0x7d),
+    ("CAT", 0x7e),
+    ("SUBSTR", 0x7f),
+    ("LEFT", 0x80),
+    ("RIGHT", 0x81),
+    ("SIZE", 0x82),
+    ("INVERT", 0x83),
+    ("AND", 0x84),
+    ("OR", 0x85),
+    ("XOR", 0x86),
+    ("EQUAL", 0x87),
+    ("EQUALVERIFY", 0x88),
+    ("RESERVED1", 0x89),
+    ("RESERVED2", 0x8a),
+    ("1ADD", 0x8b),
+    ("1SUB", 0x8c),
+    ("2MUL", 0x8d),
+    ("2DIV", 0x8e),
+    ("NEGATE", 0x8f),
+    ("ABS", 0x90),
+    ("NOT", 0x91),
+    ("0NOTEQUAL", 0x92),
+    ("ADD", 0x93),
+    ("SUB", 0x94),
+    ("MUL", 0x95),
+    ("DIV", 0x96),
+    ("MOD", 0x97),
+    ("LSHIFT", 0x98),
+    ("RSHIFT", 0x99),
+    ("BOOLAND", 0x9a),
+    ("BOOLOR", 0x9b),
+    ("NUMEQUAL", 0x9c),
+    ("NUMEQUALVERIFY", 0x9d),
+    ("NUMNOTEQUAL", 0x9e),
+    ("LESSTHAN", 0x9f),
+    ("GREATERTHAN", 0xa0),
+    ("LESSTHANOREQUAL", 0xa1),
+    ("GREATERTHANOREQUAL", 0xa2),
+    ("MIN", 0xa3),
+    ("MAX", 0xa4),
+    ("WITHIN", 0xa5),
+    ("RIPEMD160", 0xa6),
+    ("SHA1", 0xa7),
+    ("SHA256", 0xa8),
+    ("HASH160", 0xa9),
+    ("HASH256", 0xaa),
+    ("CODESEPARATOR", 0xab),
+    ("CHECKSIG", 0xac),
+    ("CHECKSIGVERIFY", 0xad),
+    ("CHECKMULTISIG", 0xae),
+    ("CHECKMULTISIGVERIFY", 0xaf),
+    ("NOP1", 0xb0),
+    ("CHECKLOCKTIMEVERIFY", 0xb1),
+    ("NOP2", 0xb1),
+    ("CHECKSEQUENCEVERIFY", 0xb2),
+    ("NOP3", 0xb2),
+    ("NOP4", 0xb3),
+    ("NOP5", 0xb4),
+    ("NOP6", 0xb5),
+    ("NOP7", 0xb6),
+    ("NOP8", 0xb7),
+    ("NOP9", 0xb8),
+];
+
+fn opcode_from_name(name: &str) -> Option<u8> {
+    let name = name.strip_prefix("OP_").unwrap_or(name);
+    match name {
+        "NOP10" => Some(0xb9),
+        "CHECKSIGADD" => Some(0xba),
+        _ => OPCODE_NAMES
+            .iter()
+            .find(|(op_name, _)| *op_name == name)
+            .map(|(_, code)| *code),
+    }
+}
+
+// minimal push of `data`, the same bytes CScript << vector would produce
+fn push_data(script: &mut Vec<u8>, data: &[u8]) {
+    if data.len() < 0x4c {
+        script.push(data.len() as u8);
+    } else if data.len() <= 0xff {
+        script.push(0x4c);
+        script.push(data.len() as u8);
+    } else if data.len() <= 0xffff {
+        script.push(0x4d);
+        script.extend((data.len() as u16).to_le_bytes());
+    } else {
+        script.push(0x4e);
+        script.extend((data.len() as u32).to_le_bytes());
+    }
+    script.extend_from_slice(data);
+}
+
+// little endian sign magnitude encoding of script numbers
+fn script_num(value: i64) -> Vec<u8> {
+    let mut result: Vec<u8> = Vec::new();
+    let negative = value < 0;
+    let mut abs = value.unsigned_abs();
+    while abs > 0 {
+        result.push((abs & 0xff) as u8);
+        abs >>= 8;
+    }
+    if let Some(last) = result.last_mut() {
+        if *last & 0x80 != 0 {
+            result.push(if negative { 0x80 } else { 0x00 });
+        } else if negative {
+            *last |= 0x80;
+        }
+    }
+    result
+}
+
+/// Parses core's test asm: numbers, 0x prefixed raw bytes, 'quoted' pushes and opcode
+/// names with or without the OP_ prefix
+pub fn parse_script_asm(asm: &str) -> Vec<u8> {
+    let mut script: Vec<u8> = Vec::new();
+    for word in asm.split_whitespace() {
+        let is_number = word
+            .strip_prefix('-')
+            .unwrap_or(word)
+            .chars()
+            .all(|c| c.is_ascii_digit());
+
+        if is_number {
+            let value: i64 = word.parse().unwrap();
+            match value {
+                0 => script.push(0x00),
+                -1 => script.push(0x4f),
+                1..=16 => script.push(0x50 + value as u8),
+                _ => push_data(&mut script, &script_num(value)),
+            }
+        } else if let Some(hex) = word.strip_prefix("0x") {
+            // raw bytes go into the script as they are
+            script.extend(get_hex_bytes(hex).unwrap());
+        } else if word.len() >= 2 && word.starts_with('\'') && word.ends_with('\'') {
+            push_data(&mut script, &word.as_bytes()[1..word.len() - 1]);
+        } else {
+            match opcode_from_name(word) {
+                Some(code) => script.push(code),
+                None => panic!("unknown opcode in test asm: {}", word),
+            }
+        }
+    }
+    script
+}
+
+/// Name of the error as printed by core's FormatScriptError
+pub fn script_error_name(err: &ScriptError) -> &'static str {
+    match err {
+        ScriptError::TruncatedPush { .. } => "BAD_OPCODE",
+        ScriptError::TruncatedPushLength { .. } => "BAD_OPCODE",
+        ScriptError::StackUnderflow => "INVALID_STACK_OPERATION",
+        ScriptError::StackOverflow => "STACK_SIZE",
+        ScriptError::EvalFalse => "EVAL_FALSE",
+        ScriptError::PubkeyCount => "PUBKEY_COUNT",
+        ScriptError::SigCount => "SIG_COUNT",
+        ScriptError::NullDummy => "SIG_NULLDUMMY",
+        ScriptError::EqualVerify => "EQUALVERIFY",
+        ScriptError::OpReturn => "OP_RETURN",
+        ScriptError::UnknownOpcode(_) => "BAD_OPCODE",
+        ScriptError::WitnessMalleated => "WITNESS_MALLEATED",
+        ScriptError::WitnessUnexpected => "WITNESS_UNEXPECTED",
+        ScriptError::WitnessProgramWitnessEmpty => "WITNESS_PROGRAM_WITNESS_EMPTY",
+        ScriptError::WitnessProgramMismatch => "WITNESS_PROGRAM_MISMATCH",
+        ScriptError::WitnessProgramWrongLength => "WITNESS_PROGRAM_WRONG_LENGTH",
+        ScriptError::DiscourageUpgradableWitnessProgram => {
+            "DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM"
+        }
+        ScriptError::SigDer => "SIG_DER",
+        ScriptError::SigHighS => "SIG_HIGH_S",
+        ScriptError::SigHashType => "SIG_HASHTYPE",
+        ScriptError::PubkeyType => "PUBKEYTYPE",
+        ScriptError::WitnessPubkeyType => "WITNESS_PUBKEYTYPE",
+        ScriptError::SigNullFail => "NULLFAIL",
+        ScriptError::MinimalData => "MINIMALDATA",
+        ScriptError::CleanStack => "CLEANSTACK",
+        ScriptError::SigPushOnly => "SIG_PUSHONLY",
+    }
+}
+
+/// "OK" for a valid input, otherwise the core name of the error
+pub fn verdict_name(verdict: &InputVerdict) -> String {
+    match verdict {
+        InputVerdict::Valid | InputVerdict::AnyoneCanSpend { .. } => String::from("OK"),
+        InputVerdict::Invalid(err) => String::from(script_error_name(err)),
+        InputVerdict::Unsupported(template) => format!("UNSUPPORTED({:?})", template),
+    }
+}
+
+/// Amounts are given in BTC in the vectors
+pub fn btc_to_sats(value: &Value) -> u64 {
+    (value.as_f64().unwrap() * 100_000_000.0).round() as u64
+}
+
+pub fn pubkey(script: &[u8], value: u64) -> Pubkey {
+    Pubkey {
+        scriptpubkey: ScriptBuf::from_bytes(script.to_vec()),
+        scriptpubkey_asm: String::from(""),
+        scriptpubkey_type: String::from(""),
+        scriptpubkey_address: None,
+        value,
+    }
+}
+
+/// Core's BuildCreditingTransaction and BuildSpendingTransaction, the returned
+/// transaction spends the only output of the crediting one
+pub fn build_spending_tx(
+    script_sig: &[u8],
+    script_pubkey: &[u8],
+    witness: Vec<Vec<u8>>,
+    amount: u64,
+) -> Transaction {
+    let crediting = Transaction {
+        sanity_hash: None,
+        version: 1,
+        locktime: 0,
+        vin: vec![Vin {
+            txid: Txid::all_zeros(),
+            vout: 0xffffffff,
+            prevout: pubkey(&[], 0),
+            // two OP_0 pushes
+            scriptsig: ScriptBuf::from_bytes(vec![0x00, 0x00]),
+            scriptsig_asm: String::from(""),
+            witness: None,
+            is_coinbase: true,
+            sequence: 0xffffffff,
+            inner_redeemscript_asm: None,
+            inner_witnessscript_asm: None,
+        }],
+        vout: vec![pubkey(script_pubkey, amount)],
+        weight: None,
+        sigop_cost: None,
+        tx_fee: None,
+    };
+
+    Transaction {
+        sanity_hash: None,
+        version: 1,
+        locktime: 0,
+        vin: vec![Vin {
+            txid: crediting.txid(),
+            vout: 0,
+            prevout: pubkey(script_pubkey, amount),
+            scriptsig: ScriptBuf::from_bytes(script_sig.to_vec()),
+            scriptsig_asm: String::from(""),
+            witness: Some(Witness(witness)),
+            is_coinbase: false,
+            sequence: 0xffffffff,
+            inner_redeemscript_asm: None,
+            inner_witnessscript_asm: None,
+        }],
+        vout: vec![pubkey(&[], amount)],
+        weight: None,
+        sigop_cost: None,
+        tx_fee: None,
+    }
+}
+
+/// Output spent by a vector
+pub struct Prevout {
+    pub txid: Txid,
+    pub vout: u32,
+    pub script_pubkey: Vec<u8>,
+    pub amount: u64,
+}
+
+/// Looks up the prevout of every input by (txid, vout), false when one is missing
+pub fn fill_prevouts(tx: &mut Transaction, prevouts: &[Prevout]) -> bool {
+    for vin in tx.vin.iter_mut() {
+        match prevouts
+            .iter()
+            .find(|prevout| prevout.txid == vin.txid && prevout.vout == vin.vout)
+        {
+            Some(prevout) => vin.prevout = pubkey(&prevout.script_pubkey, prevout.amount),
+            None => return false,
+        }
+    }
+    true
+}
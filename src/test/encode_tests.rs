@@ -0,0 +1,113 @@
+use std::io;
+
+use crate::{
+    block::BlockHeader,
+    hex_str,
+    str_utils::get_hex_bytes,
+    transaction::{Transaction, Witness},
+};
+
+use super::{
+    serialize, CompactSize, Decodable, DecodeError, Encodable, SliceReader,
+};
+
+fn decode<T: Decodable>(hex: &str) -> Result<T, DecodeError> {
+    T::consensus_decode(&mut SliceReader::new(&get_hex_bytes(hex).unwrap()))
+}
+
+#[test]
+pub fn compact_size_boundaries() {
+    let cases: [(u64, &str); 7] = [
+        (0, "00"),
+        (0xfc, "fc"),
+        (0xfd, "fdfd00"),
+        (0xffff, "fdffff"),
+        (0x10000, "fe00000100"),
+        (0x02000000, "fe00000002"),
+        (0x100000000, "ff0000000001000000"),
+    ];
+
+    for (size, hex) in cases {
+        assert_eq!(hex_str!(serialize(&CompactSize(size))), hex);
+        if size <= 0x02000000 {
+            assert_eq!(decode::<CompactSize>(hex), Ok(CompactSize(size)));
+        }
+    }
+
+    assert_eq!(
+        decode::<CompactSize>("fdfc00"),
+        Err(DecodeError::NonCanonicalCompactSize)
+    );
+    assert_eq!(
+        decode::<CompactSize>("fe01000002"),
+        Err(DecodeError::OversizedCompactSize(0x02000001))
+    );
+    assert_eq!(
+        decode::<CompactSize>("fd00"),
+        Err(DecodeError::Io(io::ErrorKind::UnexpectedEof))
+    );
+}
+
+#[test]
+pub fn integers_and_byte_strings() {
+    assert_eq!(hex_str!(serialize(&0x01020304u32)), "04030201");
+    assert_eq!(hex_str!(serialize(&-1i64)), "ffffffffffffffff");
+    assert_eq!(decode::<u16>("3412"), Ok(0x1234));
+
+    assert_eq!(hex_str!(serialize(&vec![0xab, 0xcd])), "02abcd");
+    assert_eq!(decode::<Vec<u8>>("02abcd"), Ok(vec![0xab, 0xcd]));
+    // a length running past the end of the input
+    assert_eq!(
+        decode::<Vec<u8>>("03abcd"),
+        Err(DecodeError::Io(io::ErrorKind::UnexpectedEof))
+    );
+
+    let witness = Witness(vec![vec![], vec![0x01; 3]]);
+    assert_eq!(hex_str!(serialize(&witness)), "020003010101");
+    assert_eq!(decode::<Witness>("020003010101"), Ok(witness));
+}
+
+#[test]
+pub fn slice_reader_keeps_position_on_failed_reads() {
+    let mut reader = SliceReader::new(&[0x01, 0x02, 0x03]);
+    assert_eq!(u16::consensus_decode(&mut reader), Ok(0x0201));
+    assert!(u16::consensus_decode(&mut reader).is_err());
+    assert_eq!(reader.position(), 2);
+    assert_eq!(reader.remaining(), 1);
+}
+
+#[test]
+pub fn genesis_block_header() {
+    let raw_header = "0100000000000000000000000000000000000000000000000000000000000000000000003ba3edfd7a7b12b27ac72c3e67768f617fc81bc3888a51323a9fb8aa4b1e5e4a29ab5f49ffff001d1dac2b7c";
+    let header: BlockHeader = decode(raw_header).unwrap();
+
+    assert_eq!(header.version, 1);
+    assert_eq!(header.time, 1231006505);
+    assert_eq!(header.bits, 0x1d00ffff);
+    assert_eq!(header.nonce, 2083236893);
+    assert_eq!(hex_str!(header.to_bytes()), raw_header);
+
+    assert_eq!(
+        header.block_hash().to_string(),
+        "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+    );
+}
+
+#[test]
+pub fn transaction_encoding_round_trips() {
+    let tx = Transaction::new(include_str!(
+        "../../mempool/f615cdc4463a97f08f1356b01951e90b6f36dddcf2d1febb6a2cfe9e413328c3.json"
+    ))
+    .unwrap();
+
+    // the wire format of a segwit transaction carries the witness
+    let mut bytes: Vec<u8> = Vec::new();
+    let len = tx.consensus_encode(&mut bytes).unwrap();
+    assert_eq!(len, bytes.len());
+    assert_eq!(bytes, tx.get_raw_bytes(true));
+
+    let mut reader = SliceReader::new(&bytes);
+    let decoded = Transaction::consensus_decode(&mut reader).unwrap();
+    assert_eq!(reader.remaining(), 0);
+    assert_eq!(serialize(&decoded), bytes);
+}
@@ -0,0 +1,55 @@
+use crate::{encode::serialize, hex_str, transaction::Transaction};
+
+use super::{BlockHash, HashParseError, Txid};
+
+#[test]
+pub fn display_order_is_reversed() {
+    let hex = "6ae73833e5f58616445bfe35171e89b23c5b59ef585637537f6ba34a019449ac";
+    let txid: Txid = hex.parse().unwrap();
+
+    assert_eq!(txid.to_string(), hex);
+    assert_eq!(txid.as_byte_array()[0], 0xac);
+    assert_eq!(txid.as_byte_array()[31], 0x6a);
+    // serialized in the internal order
+    assert_eq!(
+        hex_str!(serialize(&txid)),
+        "ac4994014aa36b7f53375658ef595b3cb2891e1735fe5b441686f5e53338e76a"
+    );
+    assert_eq!(
+        format!("{:?}", BlockHash::all_zeros()),
+        format!("BlockHash({})", "0".repeat(64))
+    );
+}
+
+#[test]
+pub fn rejects_malformed_hex() {
+    assert_eq!("abcd".parse::<Txid>(), Err(HashParseError::InvalidLength(4)));
+    assert_eq!(
+        "zz".repeat(32).parse::<Txid>(),
+        Err(HashParseError::InvalidHex)
+    );
+
+    // the loader reports it as a json error
+    let err = serde_json::from_str::<Txid>("\"abcd\"").unwrap_err();
+    assert!(err.to_string().contains("4 hex characters"));
+}
+
+#[test]
+pub fn ids_of_a_mempool_transaction() {
+    let tx = Transaction::new(include_str!(
+        "../../mempool/f615cdc4463a97f08f1356b01951e90b6f36dddcf2d1febb6a2cfe9e413328c3.json"
+    ))
+    .unwrap();
+
+    assert_eq!(
+        tx.txid().to_string(),
+        "351ad9aabf0bac49e7d348f9a153eb88ec9828041a0cf3e176fe6458e28d3de9"
+    );
+    assert_ne!(tx.wtxid().as_byte_array(), tx.txid().as_byte_array());
+
+    // the ids follow changes to the transaction
+    let mut changed = tx.clone();
+    changed.vout[0].value -= 1;
+    assert_ne!(changed.txid(), tx.txid());
+    assert_ne!(changed.wtxid(), tx.wtxid());
+}
@@ -0,0 +1,312 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde_json::Value;
+
+use crate::{
+    core_vectors::{btc_to_sats, build_spending_tx, parse_script_asm, verdict_name},
+    script::{Script, ScriptError},
+    str_utils::get_hex_bytes,
+    transaction::InputVerdict,
+};
+
+use super::{
+    cast_to_bool, is_low_der_signature, is_minimal_push, is_valid_signature_encoding,
+    Interpreter, NoSignatureChecker, SigVersion, SignatureChecker, VerifyFlags,
+};
+
+// cases of the vendored script_tests.json which do not agree with core yet, as
+// (scriptSig, scriptPubKey, flags). Remove a case once the interpreter gets it right,
+// the test fails for cases which start or stop failing without being listed here
+const SCRIPT_TESTS_FAILING: &[(&str, &str, &str)] = &[
+    // DEPTH, NOP, IF, VERIFY, ADD, SWAP and SIZE are not implemented
+    ("", "DEPTH 0 EQUAL", "P2SH,STRICTENC"),
+    ("  ", "DEPTH 0 EQUAL", "P2SH,STRICTENC"),
+    ("1", "NOP", "P2SH,STRICTENC"),
+    ("0", "IF 0 ELSE 1 ENDIF", "P2SH,STRICTENC"),
+    ("1", "IF 1 ELSE 0 ENDIF", "P2SH,STRICTENC"),
+    ("1", "VERIFY 1", "P2SH,STRICTENC"),
+    ("0", "VERIFY 1", "P2SH,STRICTENC"),
+    ("2 3", "ADD 5 EQUAL", "P2SH,STRICTENC"),
+    ("1 2", "SWAP 1 EQUALVERIFY 2 EQUAL", "P2SH,STRICTENC"),
+    ("'abc'", "SIZE 3 EQUALVERIFY 'abc' EQUAL", "P2SH,STRICTENC"),
+    // disabled opcodes are reported as unknown ones
+    ("'a' 'b'", "CAT", "P2SH,STRICTENC"),
+];
+
+// cases with flags the interpreter does not enforce or spends it reports as unsupported
+const SCRIPT_TESTS_SKIPPED: &[(&str, &str, &str)] = &[
+    // scriptPubKeys which are P2SH outputs
+    (
+        "'abc'",
+        "HASH160 0x14 0xbb1be98c142444d7a56aa3981c3942a978e4dc33 EQUAL",
+        "P2SH,STRICTENC",
+    ),
+    (
+        "'abd'",
+        "HASH160 0x14 0xbb1be98c142444d7a56aa3981c3942a978e4dc33 EQUAL",
+        "P2SH,STRICTENC",
+    ),
+    (
+        "0x01 0x00",
+        "HASH160 0x14 0x9f7fd096d37ed2c0e3f7f0cfc924beef4ffceb68 EQUAL",
+        "P2SH,STRICTENC",
+    ),
+    (
+        "0x01 0x51",
+        "HASH160 0x14 0xda1745e9b549bd0bfa1a569971c77eba30cd5a4b EQUAL",
+        "P2SH,STRICTENC",
+    ),
+];
+
+struct ScriptCase {
+    witness: Vec<Vec<u8>>,
+    amount: u64,
+    script_sig: String,
+    script_pubkey: String,
+    flags: String,
+    expected: String,
+}
+
+// rows with less than four strings are comments
+fn parse_case(row: &[Value]) -> Option<ScriptCase> {
+    let (witness, amount, row) = match row.first() {
+        Some(Value::Array(witness)) => {
+            let (amount, items) = witness.split_last()?;
+            let items = items
+                .iter()
+                .map(|item| get_hex_bytes(item.as_str().unwrap()).unwrap())
+                .collect();
+            (items, btc_to_sats(amount), &row[1..])
+        }
+        _ => (Vec::new(), 0, row),
+    };
+
+    if row.len() < 4 {
+        return None;
+    }
+    let field = |idx: usize| row[idx].as_str().unwrap().to_string();
+    Some(ScriptCase {
+        witness,
+        amount,
+        script_sig: field(0),
+        script_pubkey: field(1),
+        flags: field(2),
+        expected: field(3),
+    })
+}
+
+#[test]
+pub fn core_script_tests() {
+    let rows: Vec<Vec<Value>> =
+        serde_json::from_str(include_str!("./data/script_tests.json")).unwrap();
+    let cases: Vec<ScriptCase> = rows.iter().filter_map(|row| parse_case(row)).collect();
+
+    // expected result -> (passed, failed)
+    let mut matrix: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    let mut failing: BTreeSet<(&str, &str, &str)> = BTreeSet::new();
+    let mut skipped: BTreeSet<(&str, &str, &str)> = BTreeSet::new();
+
+    for case in cases.iter() {
+        let key = (
+            case.script_sig.as_str(),
+            case.script_pubkey.as_str(),
+            case.flags.as_str(),
+        );
+        let flags = match VerifyFlags::from_names(&case.flags) {
+            Some(flags) => flags,
+            None => {
+                skipped.insert(key);
+                continue;
+            }
+        };
+        let script_sig = parse_script_asm(&case.script_sig);
+        let script_pubkey = parse_script_asm(&case.script_pubkey);
+        let tx = build_spending_tx(
+            &script_sig,
+            &script_pubkey,
+            case.witness.clone(),
+            case.amount,
+        );
+
+        let verdict = tx.verify_input(0, flags);
+        if matches!(verdict, InputVerdict::Unsupported(_)) {
+            skipped.insert(key);
+            continue;
+        }
+        let result = verdict_name(&verdict);
+        let entry = matrix.entry(case.expected.clone()).or_default();
+        if result == case.expected {
+            entry.0 += 1;
+        } else {
+            entry.1 += 1;
+            failing.insert(key);
+            println!("{:?} expected {} got {}", key, case.expected, result);
+        }
+    }
+
+    println!("{:<40} {:>6} {:>6}", "expected", "pass", "fail");
+    for (expected, (passed, failed)) in matrix.iter() {
+        println!("{:<40} {:>6} {:>6}", expected, passed, failed);
+    }
+    let passed: usize = matrix.values().map(|(passed, _)| passed).sum();
+    println!("{} of {} cases pass, {} skipped", passed, cases.len(), skipped.len());
+
+    assert_eq!(failing, SCRIPT_TESTS_FAILING.iter().copied().collect());
+    assert_eq!(skipped, SCRIPT_TESTS_SKIPPED.iter().copied().collect());
+}
+
+/// Accepts exactly the listed (signature, pubkey) pairs, no keys needed
+pub struct MockSignatureChecker {
+    pub valid: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl SignatureChecker for MockSignatureChecker {
+    fn check_ecdsa_signature(
+        &self,
+        signature: &[u8],
+        pubkey: &[u8],
+        _: &[u8],
+        _: SigVersion,
+    ) -> bool {
+        self.valid.iter().any(|(valid_signature, valid_pubkey)| {
+            valid_signature == signature && valid_pubkey == pubkey
+        })
+    }
+}
+
+// runs `script_sig` and then `script_pubkey` on the same stack, without the encoding
+// rules as the mock signatures are not DER
+fn eval(script_sig: &str, script_pubkey: &str, checker: &dyn SignatureChecker) -> bool {
+    let script_sig = parse_script_asm(script_sig);
+    let script_pubkey = parse_script_asm(script_pubkey);
+    let mut interpreter = Interpreter::new(
+        Script::new(&script_pubkey),
+        checker,
+        SigVersion::Base,
+        VerifyFlags::NULLDUMMY,
+    );
+    interpreter
+        .exec_script(Script::new(&script_sig))
+        .and_then(|_| interpreter.exec_all())
+        .is_ok_and(|top| cast_to_bool(&top))
+}
+
+#[test]
+pub fn scripts_run_without_a_transaction() {
+    // hash160 of 'abc'
+    assert!(eval(
+        "'abc'",
+        "HASH160 0x14 0xbb1be98c142444d7a56aa3981c3942a978e4dc33 EQUAL",
+        &NoSignatureChecker
+    ));
+
+    // no signature is ever valid without a transaction to sign
+    assert!(!eval("'sig'", "'key' CHECKSIG", &NoSignatureChecker));
+}
+
+#[test]
+pub fn mock_checker_drives_multisig() {
+    let checker = MockSignatureChecker {
+        valid: vec![
+            (b"sig1".to_vec(), b"key1".to_vec()),
+            (b"sig3".to_vec(), b"key3".to_vec()),
+        ],
+    };
+    let script_pubkey = "2 'key1' 'key2' 'key3' 3 CHECKMULTISIG";
+
+    assert!(eval("0 'sig1' 'sig3'", script_pubkey, &checker));
+    // signatures have to follow the order of their keys
+    assert!(!eval("0 'sig3' 'sig1'", script_pubkey, &checker));
+    assert!(!eval("0 'sig1' 'sig2'", script_pubkey, &checker));
+    assert!(eval("'sig3'", "'key3' CHECKSIG", &checker));
+}
+
+// DER signature with the given R and S values and SIGHASH_ALL
+fn der_signature(r: &[u8], s: &[u8]) -> Vec<u8> {
+    let len = (r.len() + s.len() + 4) as u8;
+    [
+        &[0x30, len, 0x02, r.len() as u8],
+        r,
+        &[0x02, s.len() as u8],
+        s,
+        &[0x01],
+    ]
+    .concat()
+}
+
+#[test]
+pub fn signature_and_push_encodings() {
+    assert!(is_valid_signature_encoding(&der_signature(&[0x01], &[0x01])));
+    // R would be negative, then padded with a needless zero
+    assert!(!is_valid_signature_encoding(&der_signature(&[0x80], &[0x01])));
+    assert!(!is_valid_signature_encoding(&der_signature(&[0x00, 0x01], &[0x01])));
+    assert!(is_valid_signature_encoding(&der_signature(&[0x00, 0x80], &[0x01])));
+    // a wrong total length
+    let mut signature = der_signature(&[0x01], &[0x01]);
+    signature[1] += 1;
+    assert!(!is_valid_signature_encoding(&signature));
+
+    let mut high_s = vec![0xff; 32];
+    high_s[0] = 0x7f;
+    assert!(!is_low_der_signature(&der_signature(&[0x01], &high_s)));
+    assert!(is_low_der_signature(&der_signature(&[0x01], &[0x7f; 32])));
+
+    assert!(is_minimal_push(0x00, &[]));
+    assert!(!is_minimal_push(0x01, &[0x05]));
+    assert!(!is_minimal_push(0x01, &[0x81]));
+    assert!(is_minimal_push(0x02, &[0x05, 0x00]));
+    assert!(!is_minimal_push(0x4c, &[0x05, 0x00]));
+    assert!(is_minimal_push(0x4c, &[0x00; 76]));
+}
+
+#[test]
+pub fn policy_flags_are_enforced() {
+    let checker = MockSignatureChecker {
+        valid: vec![(der_signature(&[0x01], &[0x01]), [0x02; 33].to_vec())],
+    };
+    let run = |script_sig: &[u8], script_pubkey: &[u8], flags: VerifyFlags| {
+        let mut interpreter =
+            Interpreter::new(Script::new(script_pubkey), &checker, SigVersion::Base, flags);
+        interpreter
+            .exec_script(Script::new(script_sig))
+            .and_then(|_| interpreter.exec_all())
+    };
+    let checksig = [&[0x21], [0x02; 33].as_slice(), &[0xac]].concat();
+    let push = |data: &[u8]| [&[data.len() as u8], data].concat();
+
+    let signature = der_signature(&[0x01], &[0x01]);
+    assert!(run(&push(&signature), &checksig, VerifyFlags::STANDARD).is_ok());
+
+    // a failing signature has to be empty under NULLFAIL
+    let wrong = der_signature(&[0x02], &[0x01]);
+    assert!(run(&push(&wrong), &checksig, VerifyFlags::NONE).is_ok());
+    assert_eq!(
+        run(&push(&wrong), &checksig, VerifyFlags::NULLFAIL),
+        Err(ScriptError::SigNullFail)
+    );
+    assert!(run(&[0x00], &checksig, VerifyFlags::NULLFAIL).is_ok());
+
+    // encodings are checked before the signature
+    assert_eq!(
+        run(&push(b"sig"), &checksig, VerifyFlags::DERSIG),
+        Err(ScriptError::SigDer)
+    );
+    let mut undefined = signature.clone();
+    *undefined.last_mut().unwrap() = 0x04;
+    assert_eq!(
+        run(&push(&undefined), &checksig, VerifyFlags::STRICTENC),
+        Err(ScriptError::SigHashType)
+    );
+    let hybrid_key = [&[0x41, 0x06], [0x02; 64].as_slice(), &[0xac]].concat();
+    assert_eq!(
+        run(&push(&signature), &hybrid_key, VerifyFlags::STRICTENC),
+        Err(ScriptError::PubkeyType)
+    );
+
+    // OP_1 written as a one byte push
+    assert!(run(&[0x01, 0x01], &[0x51, 0x87], VerifyFlags::NONE).is_ok());
+    assert_eq!(
+        run(&[0x01, 0x01], &[0x51, 0x87], VerifyFlags::MINIMALDATA),
+        Err(ScriptError::MinimalData)
+    );
+}
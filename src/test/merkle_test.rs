@@ -1,4 +1,4 @@
-use std::{collections::HashMap, usize};
+use std::collections::HashMap;
 
 use hex_literal::hex;
 
@@ -18,7 +18,7 @@ use super::{do_dfs, merkleroot};
 #[test]
 pub fn test_topological_order() {
     // order of tx initially [7, 6, 5, 4, 3, 2, 1]
-    let txs = vec!["8", "7", "6", "5", "4", "3", "2", "1"];
+    let txs = ["8", "7", "6", "5", "4", "3", "2", "1"];
 
     let mut adj_list: HashMap<String, Vec<String>> = HashMap::new();
 
@@ -64,16 +64,16 @@ pub fn test_topological_order() {
     );
 
     let mut expected_stack: Stack<usize> = Stack::new();
-    expected_stack.push(0);
-    expected_stack.push(4);
-    expected_stack.push(3);
-    expected_stack.push(6);
-    expected_stack.push(2);
-    expected_stack.push(1);
-    expected_stack.push(5);
-    expected_stack.push(7);
-
-    while stack.top.is_some() {
+    expected_stack.push(0).unwrap();
+    expected_stack.push(4).unwrap();
+    expected_stack.push(3).unwrap();
+    expected_stack.push(6).unwrap();
+    expected_stack.push(2).unwrap();
+    expected_stack.push(1).unwrap();
+    expected_stack.push(5).unwrap();
+    expected_stack.push(7).unwrap();
+
+    while !stack.is_empty() {
         if let Some(top) = stack.pop() {
             assert_eq!(top, expected_stack.pop().unwrap());
         }
@@ -0,0 +1,173 @@
+use crate::{
+    script::ScriptBuf,
+    transaction::{Pubkey, Transaction, Witness},
+};
+
+use super::{Policy, PolicyError};
+
+// spends a 2 of 2 multisig p2wsh output, pays to p2tr and p2pkh
+fn mempool_tx() -> Transaction {
+    Transaction::new(include_str!(
+        "../../mempool/0136f8e20b42cf02779feef9f0f2925b5006c9b5d73df15bcbc054e6310cde27.json"
+    ))
+    .unwrap()
+}
+
+fn output(script_hex: &str, value: u64) -> Pubkey {
+    Pubkey {
+        scriptpubkey: ScriptBuf::from_hex(script_hex).unwrap(),
+        scriptpubkey_asm: String::new(),
+        scriptpubkey_type: String::new(),
+        scriptpubkey_address: None,
+        value,
+    }
+}
+
+#[test]
+pub fn mempool_transaction_is_standard() {
+    assert_eq!(Policy::default().check_standard(&mempool_tx()), Ok(()));
+}
+
+#[test]
+pub fn version_weight_and_scriptsig() {
+    let policy = Policy::default();
+
+    let mut tx = mempool_tx();
+    tx.version = 4;
+    assert_eq!(policy.check_standard(&tx), Err(PolicyError::Version(4)));
+
+    let mut tx = mempool_tx();
+    tx.weight = Some(400_001);
+    assert_eq!(policy.check_standard(&tx), Err(PolicyError::TxSize(400_001)));
+
+    let mut tx = mempool_tx();
+    tx.vin[0].scriptsig = ScriptBuf::from_hex("0176").unwrap();
+    assert_eq!(policy.check_standard(&tx), Ok(()));
+    // OP_1NEGATE and OP_RESERVED are push opcodes as well
+    tx.vin[0].scriptsig = ScriptBuf::from_hex("4f5060").unwrap();
+    assert_eq!(policy.check_standard(&tx), Ok(()));
+    tx.vin[0].scriptsig = ScriptBuf::from_hex("76").unwrap();
+    assert_eq!(
+        policy.check_standard(&tx),
+        Err(PolicyError::ScriptSigNotPushOnly { vin: 0 })
+    );
+    tx.vin[0].scriptsig = ScriptBuf::from_bytes([vec![0x4d, 0x73, 0x06], vec![0; 1651]].concat());
+    assert_eq!(
+        policy.check_standard(&tx),
+        Err(PolicyError::ScriptSigSize { vin: 0, size: 1654 })
+    );
+}
+
+#[test]
+pub fn output_types_and_datacarrier() {
+    let policy = Policy::default();
+    let pubkey = format!("21{}", "02".repeat(33));
+
+    let mut tx = mempool_tx();
+    tx.vout.push(output("6a", 0));
+    tx.vout.push(output(&format!("6a4c50{}", "00".repeat(80)), 0));
+    assert_eq!(policy.check_standard(&tx), Err(PolicyError::MultiOpReturn));
+
+    tx.vout.pop();
+    tx.vout.push(output(&format!("6a4c51{}", "00".repeat(81)), 0));
+    assert_eq!(
+        policy.check_standard(&tx),
+        Err(PolicyError::Datacarrier { vout: 3, size: 84 })
+    );
+
+    let mut tx = mempool_tx();
+    tx.vout.push(output("6a", 0));
+    let no_datacarrier = Policy {
+        max_datacarrier_bytes: None,
+        ..Policy::default()
+    };
+    assert_eq!(
+        no_datacarrier.check_standard(&tx),
+        Err(PolicyError::Datacarrier { vout: 2, size: 1 })
+    );
+
+    let mut tx = mempool_tx();
+    tx.vout.push(output("ab", 1000));
+    assert_eq!(policy.check_standard(&tx), Err(PolicyError::ScriptPubKey { vout: 2 }));
+
+    // bare multisig up to 3 keys, when permitted
+    let mut tx = mempool_tx();
+    tx.vout.push(output(&format!("51{}53ae", pubkey.repeat(3)), 1000));
+    assert_eq!(policy.check_standard(&tx), Ok(()));
+    let no_bare_multisig = Policy {
+        permit_bare_multisig: false,
+        ..Policy::default()
+    };
+    assert_eq!(
+        no_bare_multisig.check_standard(&tx),
+        Err(PolicyError::BareMultisig { vout: 2 })
+    );
+    tx.vout[2] = output(&format!("51{}54ae", pubkey.repeat(4)), 1000);
+    assert_eq!(policy.check_standard(&tx), Err(PolicyError::ScriptPubKey { vout: 2 }));
+}
+
+#[test]
+pub fn dust_thresholds() {
+    let policy = Policy::default();
+    let p2pkh = output("76a914edf10a7fac6b32e24daa5305c723f3de58db1bc888ac", 0);
+    let p2wpkh = output(&format!("0014{}", "11".repeat(20)), 0);
+    let p2tr = output(&format!("5120{}", "11".repeat(32)), 0);
+
+    assert_eq!(policy.dust_threshold(&p2pkh), 546);
+    assert_eq!(policy.dust_threshold(&p2wpkh), 294);
+    assert_eq!(policy.dust_threshold(&p2tr), 330);
+    assert_eq!(policy.dust_threshold(&output("6a", 0)), 0);
+
+    let mut tx = mempool_tx();
+    tx.vout.push(Pubkey { value: 293, ..p2wpkh });
+    assert_eq!(
+        policy.check_standard(&tx),
+        Err(PolicyError::Dust {
+            vout: 2,
+            threshold: 294
+        })
+    );
+    tx.vout[2].value = 294;
+    assert_eq!(policy.check_standard(&tx), Ok(()));
+}
+
+#[test]
+pub fn witness_limits() {
+    let policy = Policy::default();
+
+    // p2wsh stack items above 80 bytes
+    let mut tx = mempool_tx();
+    let witness = tx.vin[0].witness.as_mut().unwrap();
+    witness.0.insert(1, vec![0; 81]);
+    assert_eq!(
+        policy.check_standard(&tx),
+        Err(PolicyError::WitnessNonStandard { vin: 0 })
+    );
+
+    // a witness on an input which is not a witness program
+    let mut tx = mempool_tx();
+    tx.vin[0].prevout = output("76a914edf10a7fac6b32e24daa5305c723f3de58db1bc888ac", 10_000);
+    assert_eq!(
+        policy.check_standard(&tx),
+        Err(PolicyError::WitnessNonStandard { vin: 0 })
+    );
+
+    // taproot: key path, annex, and tapscript items
+    let mut tx = mempool_tx();
+    tx.vin[0].prevout = output(&format!("5120{}", "11".repeat(32)), 10_000);
+    tx.vin[0].witness = Some(Witness(vec![vec![0; 64]]));
+    assert_eq!(policy.check_standard(&tx), Ok(()));
+    tx.vin[0].witness = Some(Witness(vec![vec![0; 64], vec![0x50]]));
+    assert_eq!(
+        policy.check_standard(&tx),
+        Err(PolicyError::WitnessNonStandard { vin: 0 })
+    );
+    let control_block = [vec![0xc0], vec![0x22; 32]].concat();
+    tx.vin[0].witness = Some(Witness(vec![vec![0; 80], vec![0x51], control_block.clone()]));
+    assert_eq!(policy.check_standard(&tx), Ok(()));
+    tx.vin[0].witness = Some(Witness(vec![vec![0; 81], vec![0x51], control_block]));
+    assert_eq!(
+        policy.check_standard(&tx),
+        Err(PolicyError::WitnessNonStandard { vin: 0 })
+    );
+}
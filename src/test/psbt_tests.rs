@@ -0,0 +1,261 @@
+use libsecp256k1::{PublicKey, SecretKey};
+
+use crate::{
+    builder::SignError,
+    encode::DecodeError,
+    hash_types::Txid,
+    hash_utils::pk_hash,
+    schnorr::{tweak_public_key, verify_schnorr, xonly_public_key},
+    script::{ScriptBuf, ScriptTemplate},
+    sighash::{taproot_sighash, PrecomputedTransactionData, SIGHASH_DEFAULT},
+    transaction::{InputVerdict, OutPoint, Pubkey, Transaction, Witness},
+};
+
+use super::{Psbt, PsbtError};
+
+// "PSBT with one P2PKH input. Outputs are empty" of BIP174, the non witness utxo is
+// a segwit transaction
+const BIP174_P2PKH: &str = "cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA4fUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAAAAAAA";
+
+fn secret_key(byte: u8) -> SecretKey {
+    SecretKey::parse(&[byte; 32]).unwrap()
+}
+
+fn pubkey_hash(secret_key: &SecretKey) -> Vec<u8> {
+    pk_hash(&PublicKey::from_secret_key(secret_key).serialize_compressed().to_vec())
+}
+
+fn p2pkh(secret_key: &SecretKey) -> ScriptBuf {
+    let hash = pubkey_hash(secret_key);
+    ScriptBuf::from_bytes([&[0x76, 0xa9, 0x14], hash.as_slice(), &[0x88, 0xac]].concat())
+}
+
+fn p2wpkh(secret_key: &SecretKey) -> ScriptBuf {
+    ScriptBuf::from_bytes([&[0x00, 0x14], pubkey_hash(secret_key).as_slice()].concat())
+}
+
+fn p2tr(secret_key: &SecretKey) -> ScriptBuf {
+    let output_key = tweak_public_key(&xonly_public_key(secret_key), None).unwrap();
+    ScriptBuf::from_bytes([&[0x51, 0x20], output_key.as_slice()].concat())
+}
+
+fn outpoint(byte: u8, vout: u32) -> OutPoint {
+    OutPoint {
+        txid: Txid::from_byte_array([byte; 32]),
+        vout,
+    }
+}
+
+// a parent paying to P2PKH, and a child spending it along with a P2WPKH output
+fn parent_and_child(key: &SecretKey) -> (Transaction, Transaction) {
+    let mut parent = Transaction::builder()
+        .add_input(outpoint(1, 0), Pubkey::new(p2wpkh(key), 100_000))
+        .add_output(p2pkh(key), 90_000)
+        .build();
+    parent.sign_input(0, key, 0x01).unwrap();
+
+    let child = Transaction::builder()
+        .locktime(834_000)
+        .add_input(parent.outpoint(0), parent.vout[0].clone())
+        .add_input(outpoint(2, 1), Pubkey::new(p2wpkh(key), 50_000))
+        .sequence(0xfffffffe)
+        .add_output(p2wpkh(&secret_key(9)), 120_000)
+        .add_output(p2pkh(key), 15_000)
+        .build();
+    (parent, child)
+}
+
+#[test]
+pub fn bip174_vector_round_trips() {
+    let psbt = Psbt::from_base64(BIP174_P2PKH).unwrap();
+    assert_eq!(psbt.version, 0);
+    assert_eq!(psbt.inputs.len(), 1);
+    assert_eq!(psbt.outputs.len(), 2);
+
+    // the utxo is the transaction the input spends from
+    let input = &psbt.inputs[0];
+    let utxo = input.non_witness_utxo.as_ref().unwrap();
+    assert_eq!(utxo.txid(), input.previous_txid);
+    assert!(utxo.has_witness());
+    assert_eq!(input.spent_output().unwrap().classify(), ScriptTemplate::P2PKH);
+
+    assert_eq!(psbt.to_base64(), BIP174_P2PKH);
+}
+
+#[test]
+pub fn malformed_psbts() {
+    let (_, child) = parent_and_child(&secret_key(1));
+    let v0 = Psbt::from_unsigned_tx(&child, 0).unwrap().to_bytes();
+    let v2 = Psbt::from_unsigned_tx(&child, 2).unwrap().to_bytes();
+
+    assert_eq!(Psbt::from_bytes(b"psbu\xff\x00").err(), Some(PsbtError::BadMagic));
+    assert!(matches!(
+        Psbt::from_base64("cHNidP8=").err(),
+        Some(PsbtError::Decode(DecodeError::Io(_)))
+    ));
+    assert_eq!(
+        Psbt::from_bytes(&[v0.as_slice(), &[0x00]].concat()).err(),
+        Some(PsbtError::TrailingData { remaining: 1 })
+    );
+    assert_eq!(
+        Psbt::from_bytes(b"psbt\xff\x02\xfc\x01\x01\xaa\x02\xfc\x01\x01\xbb\x00").err(),
+        Some(PsbtError::DuplicateKey(vec![0xfc, 0x01]))
+    );
+    assert_eq!(
+        Psbt::from_bytes(b"psbt\xff\x00").err(),
+        Some(PsbtError::MissingField("unsigned tx"))
+    );
+    assert_eq!(
+        Psbt::from_bytes(b"psbt\xff\x01\xfb\x04\x01\x00\x00\x00\x00").err(),
+        Some(PsbtError::UnsupportedVersion(1))
+    );
+
+    // a version 2 psbt whose version field is dropped reads as version 0
+    let version_pair: &[u8] = &[0x01, 0xfb, 0x04, 0x02, 0x00, 0x00, 0x00];
+    let position = v2
+        .windows(version_pair.len())
+        .position(|window| window == version_pair)
+        .unwrap();
+    let without_version = [&v2[..position], &v2[position + version_pair.len()..]].concat();
+    assert_eq!(
+        Psbt::from_bytes(&without_version).err(),
+        Some(PsbtError::FieldNotAllowed {
+            version: 0,
+            key: vec![0x02]
+        })
+    );
+
+    // a non witness utxo which is not the spent transaction
+    let mut psbt = Psbt::from_unsigned_tx(&child, 0).unwrap();
+    psbt.inputs[1].non_witness_utxo = Some(child.clone());
+    assert_eq!(
+        Psbt::from_bytes(&psbt.to_bytes()).err(),
+        Some(PsbtError::UtxoMismatch { vin: 1 })
+    );
+}
+
+#[test]
+pub fn signs_and_finalizes_like_the_builder() {
+    let key = secret_key(1);
+    let (parent, child) = parent_and_child(&key);
+    let mut psbt = Psbt::from_unsigned_tx(&child, 0).unwrap();
+
+    // only the witness program got its utxo from the prevouts
+    assert!(psbt.inputs[0].witness_utxo.is_none());
+    assert!(psbt.inputs[1].witness_utxo.is_some());
+    assert_eq!(psbt.sign(&key), Err(PsbtError::MissingUtxo { vin: 0 }));
+    assert_eq!(
+        psbt.update_with_utxo(1, &parent),
+        Err(PsbtError::UtxoMismatch { vin: 1 })
+    );
+    psbt.update_with_utxo(0, &parent).unwrap();
+
+    // nothing is signed yet, and a failed finalize leaves the psbt as it was
+    assert_eq!(psbt.finalize().err(), Some(PsbtError::MissingSignature { vin: 0 }));
+    assert!(psbt.inputs.iter().all(|input| !input.is_finalized()));
+
+    // the psbt goes to the signer and comes back
+    let mut psbt = Psbt::from_base64(&psbt.to_base64()).unwrap();
+    assert_eq!(psbt.sign(&secret_key(2)), Ok(0));
+    assert_eq!(psbt.sign(&key), Ok(2));
+    assert_eq!(psbt.inputs[0].partial_sigs.len(), 1);
+    let psbt = Psbt::from_base64(&psbt.to_base64()).unwrap();
+
+    let mut finalized = psbt.clone();
+    let tx = finalized.finalize().unwrap();
+    assert!(finalized.inputs.iter().all(|input| input.partial_sigs.is_empty()));
+    assert!(tx.validate_transacation());
+
+    // RFC6979 nonces, the builder makes the very same signatures
+    let mut signed = child;
+    signed.sign_input(0, &key, 0x01).unwrap();
+    signed.sign_input(1, &key, 0x01).unwrap();
+    assert_eq!(tx.wtxid(), signed.wtxid());
+    assert_eq!(tx.to_json(), signed.to_json());
+
+    let extracted = Psbt::from_bytes(&finalized.to_bytes()).unwrap().extract_tx().unwrap();
+    assert_eq!(extracted.wtxid(), signed.wtxid());
+    assert_eq!(psbt.extract_tx().err(), Some(PsbtError::NotFinalized { vin: 0 }));
+
+    // signatures which no longer commit to the outputs do not finalize
+    let mut tampered = psbt.clone();
+    tampered.outputs[1].amount -= 1;
+    assert!(matches!(
+        tampered.finalize().err(),
+        Some(PsbtError::InvalidInput {
+            vin: 0,
+            verdict: InputVerdict::Invalid(_)
+        })
+    ));
+}
+
+#[test]
+pub fn version_2_fields_and_locktime() {
+    let (_, child) = parent_and_child(&secret_key(1));
+    let bytes = Psbt::from_unsigned_tx(&child, 2).unwrap().to_bytes();
+    let mut psbt = Psbt::from_bytes(&bytes).unwrap();
+    assert_eq!(psbt.version, 2);
+    assert_eq!(psbt.tx_version, 2);
+    assert_eq!(psbt.inputs[0].sequence, Some(0xfffffffd));
+    assert_eq!(psbt.inputs[1].sequence, Some(0xfffffffe));
+    assert_eq!(psbt.outputs[1].amount, 15_000);
+    assert_eq!(psbt.to_transaction().unwrap().txid(), child.txid());
+
+    // the latest required locktime wins over the fallback, heights over times
+    psbt.inputs[0].required_height_locktime = Some(834_500);
+    psbt.inputs[1].required_height_locktime = Some(834_100);
+    psbt.inputs[1].required_time_locktime = Some(1_700_000_000);
+    assert_eq!(psbt.locktime(), Ok(834_500));
+    psbt.inputs[0].required_height_locktime = None;
+    psbt.inputs[0].required_time_locktime = Some(1_710_000_000);
+    assert_eq!(psbt.locktime(), Ok(1_710_000_000));
+    psbt.inputs[1].required_time_locktime = None;
+    assert_eq!(psbt.locktime(), Err(PsbtError::LocktimeConflict));
+
+    let bytes = psbt.to_bytes();
+    let parsed = Psbt::from_bytes(&bytes).unwrap();
+    assert_eq!(parsed.inputs[0].required_time_locktime, Some(1_710_000_000));
+    assert_eq!(parsed.to_bytes(), bytes);
+}
+
+#[test]
+pub fn taproot_inputs_get_a_key_path_signature() {
+    let key = secret_key(3);
+    let tx = Transaction::builder()
+        .add_input(outpoint(4, 0), Pubkey::new(p2tr(&key), 10_000))
+        .add_output(p2tr(&key), 9_000)
+        .build();
+    let mut psbt = Psbt::from_unsigned_tx(&tx, 0).unwrap();
+
+    psbt.sign_input(0, &key).unwrap();
+    let signature = psbt.inputs[0].tap_key_sig.clone().unwrap();
+    assert_eq!(signature.len(), 64);
+    let precomputed = PrecomputedTransactionData::new(&tx);
+    let sighash = taproot_sighash(&tx, 0, SIGHASH_DEFAULT, None, None, &precomputed).unwrap();
+    assert!(verify_schnorr(&sighash, &tx.vin[0].prevout.scriptpubkey.as_bytes()[2..], &signature));
+
+    assert_eq!(
+        psbt.sign_input(0, &secret_key(4)),
+        Err(PsbtError::Sign(SignError::KeyMismatch { vin: 0 }))
+    );
+
+    // the key path is spent with the signature alone
+    let signed = psbt.finalize().unwrap();
+    assert_eq!(signed.vin[0].witness, Some(Witness(vec![signature.clone()])));
+    assert_eq!(psbt.inputs[0].tap_key_sig, None);
+    let parsed = Psbt::from_bytes(&psbt.to_bytes()).unwrap();
+    assert_eq!(parsed.extract_tx().unwrap().txid(), signed.txid());
+
+    // a corrupted signature does not finalize
+    let mut other = Psbt::from_unsigned_tx(&tx, 0).unwrap();
+    let mut forged = signature;
+    forged[0] ^= 0x01;
+    other.inputs[0].tap_key_sig = Some(forged);
+    assert_eq!(
+        other.finalize().err(),
+        Some(PsbtError::InvalidInput {
+            vin: 0,
+            verdict: InputVerdict::Unsupported(ScriptTemplate::P2TR)
+        })
+    );
+}
@@ -0,0 +1,63 @@
+use libsecp256k1::SecretKey;
+
+use crate::{hex_str, str_utils::get_hex_bytes};
+
+use super::{sign_schnorr, tweak_public_key, tweak_secret_key, verify_schnorr, xonly_public_key};
+
+fn bytes32(hex: &str) -> [u8; 32] {
+    get_hex_bytes(hex).unwrap().try_into().unwrap()
+}
+
+#[test]
+pub fn bip340_sign_vectors() {
+    // vectors 0 and 1 of BIP340
+    let secret_key = SecretKey::parse(&bytes32(
+        "0000000000000000000000000000000000000000000000000000000000000003",
+    ))
+    .unwrap();
+    assert_eq!(
+        hex_str!(xonly_public_key(&secret_key)),
+        "f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9"
+    );
+    let signature = sign_schnorr(&[0u8; 32], &secret_key, &[0u8; 32]);
+    assert_eq!(
+        hex_str!(signature),
+        "e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0"
+    );
+
+    let secret_key = SecretKey::parse(&bytes32(
+        "b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef",
+    ))
+    .unwrap();
+    let msg = bytes32("243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c89");
+    let aux_rand = bytes32("0000000000000000000000000000000000000000000000000000000000000001");
+    let signature = sign_schnorr(&msg, &secret_key, &aux_rand);
+    assert_eq!(
+        hex_str!(signature),
+        "6896bd60eeae296db48a229ff71dfe071bde413e6d43f917dc8dcf8c78de33418906d11ac976abccb20b091292bff4ea897efcb639ea871cfa95f6de339e4b0a"
+    );
+    assert!(verify_schnorr(&msg, &xonly_public_key(&secret_key), &signature));
+}
+
+#[test]
+pub fn taproot_key_tweak() {
+    // scriptPubKey vector 0 of BIP341, a key path only output
+    let internal_key = bytes32("d6889cb081036e0faefa3a35157ad71086b123b2b144b649798b494c300a961d");
+    assert_eq!(
+        hex_str!(tweak_public_key(&internal_key, None).unwrap()),
+        "53a1f6e454df1aa2776a2814a721372d6258050de330b3c6d10ee8f4e0dda343"
+    );
+
+    // the tweaked secret key belongs to the tweaked public key, whatever the parity
+    for key in [
+        "0000000000000000000000000000000000000000000000000000000000000003",
+        "b7e151628aed2a6abf7158809cf4f3c762e7160f38b4da56a784d9045190cfef",
+    ] {
+        let secret_key = SecretKey::parse(&bytes32(key)).unwrap();
+        let tweaked = tweak_secret_key(&secret_key, None).unwrap();
+        assert_eq!(
+            Some(xonly_public_key(&tweaked)),
+            tweak_public_key(&xonly_public_key(&secret_key), None)
+        );
+    }
+}
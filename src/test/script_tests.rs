@@ -0,0 +1,180 @@
+use crate::{
+    opcodes::all_opcodes::{OP_CHECKSIG, OP_DUP},
+    str_utils::get_hex_bytes,
+};
+
+use super::{Instruction, Script, ScriptError, ScriptTemplate};
+
+fn classify_hex(script_hex: &str) -> ScriptTemplate {
+    Script::new(&get_hex_bytes(script_hex).unwrap()).classify()
+}
+
+#[test]
+pub fn parse_push_and_opcodes() {
+    // OP_DUP OP_PUSHBYTES_2 abcd OP_PUSHDATA1 01 ff OP_CHECKSIG
+    let bytes = vec![0x76, 0x02, 0xab, 0xcd, 0x4c, 0x01, 0xff, 0xac];
+    let script = Script::new(&bytes);
+
+    let instructions: Vec<Instruction> = script
+        .instructions()
+        .collect::<Result<Vec<Instruction>, ScriptError>>()
+        .unwrap();
+
+    assert_eq!(
+        instructions,
+        vec![
+            Instruction::Op(OP_DUP),
+            Instruction::PushBytes(&[0xab, 0xcd]),
+            Instruction::PushBytes(&[0xff]),
+            Instruction::Op(OP_CHECKSIG),
+        ]
+    );
+}
+
+#[test]
+pub fn truncated_pushbytes_is_an_error() {
+    // OP_PUSHBYTES_4 with only two bytes left
+    let bytes = vec![0x04, 0x01, 0x02];
+    let script = Script::new(&bytes);
+
+    let mut instructions = script.instructions();
+    assert_eq!(
+        instructions.next(),
+        Some(Err(ScriptError::TruncatedPush {
+            offset: 0,
+            expected: 4,
+            available: 2
+        }))
+    );
+    // the iterator is fused after an error
+    assert_eq!(instructions.next(), None);
+}
+
+#[test]
+pub fn truncated_pushdata4_is_an_error() {
+    // OP_PUSHDATA4 asking for 0x100 bytes
+    let bytes = vec![0x76, 0x4e, 0x00, 0x01, 0x00, 0x00, 0xaa];
+    let script = Script::new(&bytes);
+
+    let result: Result<Vec<Instruction>, ScriptError> = script.instructions().collect();
+    assert_eq!(
+        result,
+        Err(ScriptError::TruncatedPush {
+            offset: 1,
+            expected: 0x100,
+            available: 1
+        })
+    );
+
+    // the length prefix itself is cut off
+    let bytes = vec![0x4d, 0x01];
+    let script = Script::new(&bytes);
+    let result: Result<Vec<Instruction>, ScriptError> = script.instructions().collect();
+    assert_eq!(result, Err(ScriptError::TruncatedPushLength { offset: 0 }));
+}
+
+#[test]
+pub fn empty_script_has_no_instructions() {
+    let script = Script::new(&[]);
+    assert!(script.is_empty());
+    assert_eq!(script.instructions().count(), 0);
+}
+
+#[test]
+pub fn classify_standard_templates() {
+    assert_eq!(
+        classify_hex("76a914aa966f56de599b4094b61aa68a2b3df9e97e9c4888ac"),
+        ScriptTemplate::P2PKH
+    );
+    assert_eq!(
+        classify_hex("a914748284390f9e263a4b766a75d0633c50426eb87587"),
+        ScriptTemplate::P2SH
+    );
+    assert_eq!(
+        classify_hex("0014aa966f56de599b4094b61aa68a2b3df9e97e9c48"),
+        ScriptTemplate::P2WPKH
+    );
+    assert_eq!(
+        classify_hex("0020701a8d401c84fb13e6baf169d59684e17abd9fa216c8cc5b9fc63d622ff8c58d"),
+        ScriptTemplate::P2WSH
+    );
+    assert_eq!(
+        classify_hex("51200f0c8db753acbd17343a39c2f3f4e35e4be6da749f9e35137ab220e7b238a667"),
+        ScriptTemplate::P2TR
+    );
+    assert_eq!(
+        classify_hex("2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac"),
+        ScriptTemplate::P2PK
+    );
+    assert_eq!(classify_hex("51024e73"), ScriptTemplate::Anchor);
+    assert_eq!(classify_hex("6a0401020304"), ScriptTemplate::NullData);
+}
+
+#[test]
+pub fn classify_multisig_and_nonstandard() {
+    // 1 of 3 bare multisig as seen in the mempool, labelled "unknown" by esplora
+    let multisig = classify_hex("512103fc4f986147d39f0c9f2d848a4fa27516d46538efb2dabbae4022c0eadef07bde2103f18aefc2bf984856f9fbfa91fd1622a53137c0d240e4504d7f546dccbbb425892102020202020202020202020202020202020202020202020202020202020202020253ae");
+    assert_eq!(
+        multisig,
+        ScriptTemplate::Multisig {
+            required: 1,
+            keys: 3
+        }
+    );
+    assert!(multisig.matches_label("unknown"));
+
+    // witness v2 and a v0 program of unexpected length
+    assert_eq!(
+        classify_hex("52020001"),
+        ScriptTemplate::WitnessUnknown {
+            version: 2,
+            program_len: 2
+        }
+    );
+    assert_eq!(
+        classify_hex("0015aa966f56de599b4094b61aa68a2b3df9e97e9c4801"),
+        ScriptTemplate::WitnessUnknown {
+            version: 0,
+            program_len: 21
+        }
+    );
+
+    // truncated push and an OP_RETURN followed by an opcode
+    assert_eq!(classify_hex("4c05aa"), ScriptTemplate::NonStandard);
+    assert_eq!(classify_hex("6a76"), ScriptTemplate::NonStandard);
+    assert!(!ScriptTemplate::P2PKH.matches_label("v0_p2wpkh"));
+}
+
+#[test]
+pub fn sigop_counting() {
+    let count = |script_hex: &str, accurate: bool| {
+        Script::new(&get_hex_bytes(script_hex).unwrap()).sigop_count(accurate)
+    };
+
+    // OP_2 <pubkey> <pubkey> <pubkey> OP_3 OP_CHECKMULTISIG
+    let multisig = format!("52{}53ae", format!("21{}", "02".repeat(33)).repeat(3));
+    assert_eq!(count(&multisig, true), 3);
+    assert_eq!(count(&multisig, false), 20);
+    // without a small integer in front the maximum is charged either way
+    assert_eq!(count("00af", true), 20);
+
+    // OP_CHECKSIGVERIFY OP_CHECKSIG, pushed data is never an opcode
+    assert_eq!(count("adac", false), 2);
+    assert_eq!(count("02acacac", false), 1);
+    // counting stops at a truncated push
+    assert_eq!(count("ac4c05ac", false), 1);
+}
+
+#[test]
+pub fn last_push_of_scriptsigs() {
+    let last_push = |script_hex: &str| {
+        Script::new(&get_hex_bytes(script_hex).unwrap())
+            .last_push()
+            .map(|data| data.to_vec())
+    };
+
+    assert_eq!(last_push("0001ab02cdef"), Some(vec![0xcd, 0xef]));
+    assert_eq!(last_push("02cdef51"), Some(vec![]));
+    assert_eq!(last_push(""), None);
+    assert_eq!(last_push("02cdefac"), None);
+}
@@ -0,0 +1,69 @@
+use libsecp256k1::{sign, Message, PublicKey, SecretKey};
+
+use crate::{
+    hash_utils::double_hash256,
+    schnorr::{sign_schnorr, xonly_public_key},
+};
+
+use super::SignatureCache;
+
+#[test]
+pub fn valid_signatures_are_cached() {
+    let seckey = SecretKey::parse(&[0x11; 32]).unwrap();
+    let pubkey = PublicKey::from_secret_key(&seckey).serialize_compressed();
+
+    let sighash = double_hash256(&b"sigcache".to_vec());
+    let (signature, _) = sign(&Message::parse_slice(&sighash).unwrap(), &seckey);
+    let der = signature.serialize_der();
+
+    let cache = SignatureCache::new(16);
+    assert!(!cache.contains(&sighash, &pubkey, der.as_ref()));
+    assert!(cache.verify_ecdsa(&sighash, &pubkey, der.as_ref()));
+    assert!(cache.contains(&sighash, &pubkey, der.as_ref()));
+
+    // a failing verification is never remembered
+    let other_sighash = double_hash256(&b"other".to_vec());
+    assert!(!cache.verify_ecdsa(&other_sighash, &pubkey, der.as_ref()));
+    assert!(!cache.contains(&other_sighash, &pubkey, der.as_ref()));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+pub fn cache_is_bounded() {
+    let cache = SignatureCache::new(2);
+    for i in 0..5u8 {
+        cache.insert(&[i; 32], &[0x02; 33], &[0x30]);
+    }
+    assert_eq!(cache.len(), 2);
+
+    // inserting a cached entry again keeps the other one
+    let cached: Vec<u8> = (0..5u8)
+        .filter(|i| cache.contains(&[*i; 32], &[0x02; 33], &[0x30]))
+        .collect();
+    assert_eq!(cached.len(), 2);
+    cache.insert(&[cached[0]; 32], &[0x02; 33], &[0x30]);
+    for i in cached {
+        assert!(cache.contains(&[i; 32], &[0x02; 33], &[0x30]));
+    }
+}
+
+#[test]
+pub fn schnorr_signatures_are_cached_apart() {
+    let seckey = SecretKey::parse(&[0x22; 32]).unwrap();
+    let pubkey = xonly_public_key(&seckey);
+    let sighash = double_hash256(&b"taproot".to_vec());
+    let signature = sign_schnorr(&sighash.clone().try_into().unwrap(), &seckey, &[0; 32]);
+
+    let cache = SignatureCache::new(16);
+    assert!(cache.verify_schnorr(&sighash, &pubkey, &signature));
+    assert_eq!(cache.len(), 1);
+    // the same bytes are not an ecdsa entry
+    assert!(!cache.contains(&sighash, &pubkey, &signature));
+    assert!(cache.verify_schnorr(&sighash, &pubkey, &signature));
+    assert_eq!(cache.len(), 1);
+
+    let mut tampered = signature;
+    tampered[63] ^= 1;
+    assert!(!cache.verify_schnorr(&sighash, &pubkey, &tampered));
+    assert_eq!(cache.len(), 1);
+}
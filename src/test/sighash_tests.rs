@@ -0,0 +1,276 @@
+use libsecp256k1::{verify, Message, PublicKey, Signature};
+use serde_json::Value;
+
+use crate::{
+    hex_str,
+    schnorr::verify_schnorr,
+    script::Instruction,
+    str_utils::get_hex_bytes,
+    transaction::Transaction,
+};
+
+use super::{
+    legacy_sighash, segwit_v0_sighash, taproot_sighash, PrecomputedTransactionData,
+    SighashError, SIGHASH_ALL, SIGHASH_SINGLE,
+};
+
+// unsigned transaction of the "native P2WPKH" example in BIP143
+pub fn bip143_native_p2wpkh_tx() -> Transaction {
+    let raw_json_tx = r#"{
+        "version": 1,
+        "locktime": 17,
+        "vin": [
+            {
+                "txid": "9f96ade4b41d5433f4eda31e1738ec2b36f6e7d1420d94a6af99801a88f7f7ff",
+                "vout": 0,
+                "prevout": {
+                    "scriptpubkey": "2103c9f4836b9a4f77fc0d81f7bcb01b7f1b35916864b9476c241ce9fc198bd25432ac",
+                    "scriptpubkey_asm": "",
+                    "scriptpubkey_type": "p2pk",
+                    "value": 625000000
+                },
+                "scriptsig": "",
+                "scriptsig_asm": "",
+                "is_coinbase": false,
+                "sequence": 4294967278
+            },
+            {
+                "txid": "8ac60eb9575db5b2d987e29f301b5b819ea83a5c6579d282d189cc04b8e151ef",
+                "vout": 1,
+                "prevout": {
+                    "scriptpubkey": "00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1",
+                    "scriptpubkey_asm": "",
+                    "scriptpubkey_type": "v0_p2wpkh",
+                    "value": 600000000
+                },
+                "scriptsig": "",
+                "scriptsig_asm": "",
+                "witness": [],
+                "is_coinbase": false,
+                "sequence": 4294967295
+            }
+        ],
+        "vout": [
+            {
+                "scriptpubkey": "76a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac",
+                "scriptpubkey_asm": "",
+                "scriptpubkey_type": "p2pkh",
+                "value": 112340000
+            },
+            {
+                "scriptpubkey": "76a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac",
+                "scriptpubkey_asm": "",
+                "scriptpubkey_type": "p2pkh",
+                "value": 223450000
+            }
+        ]
+    }"#;
+    Transaction::new(raw_json_tx).unwrap()
+}
+
+#[test]
+pub fn precomputed_bip143_hashes() {
+    let tx = bip143_native_p2wpkh_tx();
+    let precomputed = PrecomputedTransactionData::new(&tx);
+
+    assert_eq!(
+        hex_str!(precomputed.hash_prevouts),
+        "96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37"
+    );
+    assert_eq!(
+        hex_str!(precomputed.hash_sequence),
+        "52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b"
+    );
+    assert_eq!(
+        hex_str!(precomputed.hash_outputs),
+        "863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5"
+    );
+
+    // the data is built from the transaction as it is, a changed output changes it
+    let mut tx = tx;
+    tx.vout[0].value -= 1;
+    let changed = PrecomputedTransactionData::new(&tx);
+    assert_ne!(changed.hash_outputs, precomputed.hash_outputs);
+    assert_eq!(changed.hash_prevouts, precomputed.hash_prevouts);
+}
+
+
+#[test]
+pub fn core_sighash_tests() {
+    let rows: Vec<Vec<Value>> =
+        serde_json::from_str(include_str!("./data/sighash.json")).unwrap();
+
+    let mut cases = 0;
+    for row in rows.iter().filter(|row| row.len() == 5) {
+        let tx = Transaction::from_hex(row[0].as_str().unwrap()).unwrap();
+        let script_code = get_hex_bytes(row[1].as_str().unwrap()).unwrap();
+        let idx = row[2].as_u64().unwrap() as usize;
+        // hash types are signed 32 bit numbers in the vectors
+        let sighash_type = row[3].as_i64().unwrap() as u32;
+
+        let mut sighash = legacy_sighash(&tx, idx, &script_code, sighash_type);
+        sighash.reverse();
+        assert_eq!(hex_str!(sighash), row[4].as_str().unwrap(), "{:?}", row);
+        cases += 1;
+    }
+    assert!(cases > 0);
+}
+
+#[test]
+pub fn bip143_native_p2wpkh_sighash() {
+    let tx = bip143_native_p2wpkh_tx();
+    let script_code = get_hex_bytes("76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac").unwrap();
+
+    assert_eq!(
+        hex_str!(segwit_v0_sighash(
+            &tx,
+            1,
+            &script_code,
+            600000000,
+            SIGHASH_ALL,
+            &PrecomputedTransactionData::new(&tx)
+        )),
+        "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+    );
+}
+
+#[test]
+pub fn bip340_verify_vectors() {
+    // vectors 0 and 1 of BIP340
+    let pubkey = get_hex_bytes("f9308a019258c31049344f85f89d5229b531c845836f99b08601f113bce036f9").unwrap();
+    let signature = get_hex_bytes("e907831f80848d1069a5371b402410364bdf1c5f8307b0084c55f1ce2dca821525f66a4a85ea8b71e482a74f382d2ce5ebeee8fdb2172f477df4900d310536c0").unwrap();
+    assert!(verify_schnorr(&[0u8; 32], &pubkey, &signature));
+
+    let pubkey = get_hex_bytes("dff1d77f2a671c5f36183726db2341be58feae1da2deced843240f7b502ba659").unwrap();
+    let msg = get_hex_bytes("243f6a8885a308d313198a2e03707344a4093822299f31d0082efa98ec4e6c89").unwrap();
+    let mut signature = get_hex_bytes("6896bd60eeae296db48a229ff71dfe071bde413e6d43f917dc8dcf8c78de33418906d11ac976abccb20b091292bff4ea897efcb639ea871cfa95f6de339e4b0a").unwrap();
+    assert!(verify_schnorr(&msg, &pubkey, &signature));
+
+    signature[63] ^= 0x01;
+    assert!(!verify_schnorr(&msg, &pubkey, &signature));
+}
+
+// transactions from the mempool fixtures, their signatures only verify against the
+// right signature hash
+fn mempool_tx(raw_json_tx: &str) -> Transaction {
+    Transaction::new(raw_json_tx).unwrap()
+}
+
+fn witness_item(tx: &Transaction, idx: usize, item: usize) -> Vec<u8> {
+    tx.vin[idx].witness.as_ref().unwrap().0[item].clone()
+}
+
+fn assert_p2wpkh_signature(tx: &Transaction, idx: usize, sighash_type: u32) {
+    let signature = witness_item(tx, idx, 0);
+    let pubkey = witness_item(tx, idx, 1);
+    assert_eq!(*signature.last().unwrap() as u32, sighash_type);
+
+    let scriptpubkey = tx.vin[idx].prevout.scriptpubkey.as_bytes();
+    let script_code = [&[0x76, 0xa9, 0x14], &scriptpubkey[2..], &[0x88, 0xac]].concat();
+    let sighash = segwit_v0_sighash(
+        tx,
+        idx,
+        &script_code,
+        tx.vin[idx].prevout.value,
+        sighash_type,
+        &PrecomputedTransactionData::new(tx),
+    );
+
+    assert!(verify(
+        &Message::parse_slice(&sighash).unwrap(),
+        &Signature::parse_der_lax(&signature[..signature.len() - 1]).unwrap(),
+        &PublicKey::parse_slice(&pubkey, None).unwrap(),
+    ));
+}
+
+#[test]
+pub fn bip143_sighash_types_match_mempool_signatures() {
+    let tx = mempool_tx(include_str!(
+        "../../mempool/f615cdc4463a97f08f1356b01951e90b6f36dddcf2d1febb6a2cfe9e413328c3.json"
+    ));
+    assert_p2wpkh_signature(&tx, 0, SIGHASH_ALL);
+
+    // SIGHASH_ALL | ANYONECANPAY
+    let tx = mempool_tx(include_str!(
+        "../../mempool/c56e72a48bfe6b6bbc0fcc28c920368e2abb80482bb5810afb0e242f84b8a3b6.json"
+    ));
+    assert_p2wpkh_signature(&tx, 0, 0x81);
+
+    // SIGHASH_SINGLE | ANYONECANPAY
+    let tx = mempool_tx(include_str!(
+        "../../mempool/c5716cfa78d5a337c0b81bc8ce87481a5ba1ff304f907367b68a5c3d2fee7a4b.json"
+    ));
+    assert_p2wpkh_signature(&tx, 1, 0x83);
+}
+
+fn assert_taproot_signature(tx: &Transaction, idx: usize, sighash_type: u32) {
+    let signature = witness_item(tx, idx, 0);
+    let program = match tx.vin[idx].prevout.scriptpubkey.as_script().instructions().nth(1) {
+        Some(Ok(Instruction::PushBytes(program))) => program.to_vec(),
+        _ => panic!("not a taproot output"),
+    };
+
+    // the sighash byte is left out for SIGHASH_DEFAULT
+    let signed_type = signature.get(64).map(|val| *val as u32).unwrap_or(0);
+    assert_eq!(signed_type, sighash_type);
+
+    let precomputed = PrecomputedTransactionData::new(tx);
+    let sighash = taproot_sighash(tx, idx, sighash_type, None, None, &precomputed).unwrap();
+    assert!(verify_schnorr(&sighash, &program, &signature[..64]));
+}
+
+#[test]
+pub fn bip341_sighash_types_match_mempool_signatures() {
+    // SIGHASH_DEFAULT
+    let tx = mempool_tx(include_str!(
+        "../../mempool/9e05b81b06070841a44f439230b61319fc9967b78eb5cb8b1b985dddf620f523.json"
+    ));
+    assert_taproot_signature(&tx, 0, 0x00);
+
+    let tx = mempool_tx(include_str!(
+        "../../mempool/9abab76ff8d5381f46291bf5f6fd911df420d08ed3009b4756104c403ecbeb82.json"
+    ));
+    assert_taproot_signature(&tx, 0, SIGHASH_ALL);
+
+    // SIGHASH_ALL | ANYONECANPAY
+    let tx = mempool_tx(include_str!(
+        "../../mempool/5da40502e3620b0d83819e07f1047f8345d993f84236ed977119bebd1efc4719.json"
+    ));
+    assert_taproot_signature(&tx, 0, 0x81);
+
+    // SIGHASH_SINGLE | ANYONECANPAY
+    let tx = mempool_tx(include_str!(
+        "../../mempool/e8c0af3c2cc9ae68aae011e89284eb6bbcdc7dd6aec7802273cad17635bf0614.json"
+    ));
+    assert_taproot_signature(&tx, 1, 0x83);
+}
+
+#[test]
+pub fn bip341_rejects_undefined_sighash_types() {
+    let tx = bip143_native_p2wpkh_tx();
+    let precomputed = PrecomputedTransactionData::new(&tx);
+
+    assert_eq!(
+        taproot_sighash(&tx, 0, 0x04, None, None, &precomputed),
+        Err(SighashError::InvalidSighashType(0x04))
+    );
+    assert_eq!(
+        taproot_sighash(&tx, 2, SIGHASH_ALL, None, None, &precomputed),
+        Err(SighashError::InputOutOfRange { idx: 2, inputs: 2 })
+    );
+
+    // one output less than inputs, the last input has nothing to sign with SINGLE
+    let mut tx = bip143_native_p2wpkh_tx();
+    tx.vout.pop();
+    assert_eq!(
+        taproot_sighash(
+            &tx,
+            1,
+            SIGHASH_SINGLE,
+            None,
+            None,
+            &PrecomputedTransactionData::new(&tx)
+        ),
+        Err(SighashError::SingleWithoutOutput { idx: 1 })
+    );
+}
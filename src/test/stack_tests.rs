@@ -0,0 +1,54 @@
+use super::{Stack, StackOverflow};
+
+#[test]
+pub fn push_pop_and_peek() {
+    let mut stack: Stack<u32> = Stack::new();
+    assert!(stack.is_empty());
+    assert_eq!(stack.pop(), None);
+
+    for val in 1..=4 {
+        stack.push(val).unwrap();
+    }
+
+    assert_eq!(stack.len(), 4);
+    assert_eq!(stack.peek(), Some(&4));
+    assert_eq!(stack.peek_at(0), Some(&4));
+    assert_eq!(stack.peek_at(3), Some(&1));
+    assert_eq!(stack.peek_at(4), None);
+
+    *stack.peek_at_mut(1).unwrap() = 30;
+    assert_eq!(stack.pop(), Some(4));
+    assert_eq!(stack.pop(), Some(30));
+    assert_eq!(stack.iter().copied().collect::<Vec<u32>>(), vec![1, 2]);
+}
+
+#[test]
+pub fn insert_and_remove_at_depth() {
+    let mut stack: Stack<u32> = Stack::new();
+    for val in 1..=3 {
+        stack.push(val).unwrap();
+    }
+
+    // 1 2 3 -> 1 2 9 3, like OP_TUCK
+    stack.insert_at(1, 9).unwrap();
+    assert_eq!(stack.iter().copied().collect::<Vec<u32>>(), vec![1, 2, 9, 3]);
+
+    // like OP_ROLL 3
+    assert_eq!(stack.remove_at(3), Some(1));
+    assert_eq!(stack.remove_at(3), None);
+    assert_eq!(stack.iter().copied().collect::<Vec<u32>>(), vec![2, 9, 3]);
+
+    assert!(stack.swap(0, 2));
+    assert!(!stack.swap(0, 3));
+    assert_eq!(stack.iter().copied().collect::<Vec<u32>>(), vec![3, 9, 2]);
+}
+
+#[test]
+pub fn size_limit_is_enforced() {
+    let mut stack: Stack<u32> = Stack::with_limit(2);
+    stack.push(1).unwrap();
+    stack.insert_at(1, 0).unwrap();
+    assert_eq!(stack.push(2), Err(StackOverflow { limit: 2 }));
+    assert_eq!(stack.insert_at(0, 2), Err(StackOverflow { limit: 2 }));
+    assert_eq!(stack.len(), 2);
+}
@@ -1,12 +1,38 @@
-use std::str::FromStr;
-use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+use serde::{de, ser::SerializeSeq, Deserialize, Deserializer, Serialize, Serializer};
 use crate::{
-    hash_utils::double_hash256,
-    interpreter::Interpreter,
+    address::{Address, Network},
+    block::BlockContext,
+    encode::{CompactSize, Decodable, DecodeError, Encodable, SliceReader},
+    hash_types::{Txid, Wtxid},
+    hash_utils::{double_hash256, hash256},
+    hex_str,
+    interpreter::{
+        cast_to_bool, Interpreter, SigVersion, TransactionSignatureChecker, VerifyFlags,
+    },
     opcodes::all_opcodes::{OP_CHECKSIG, OP_DUP, OP_EQUALVERIFY, OP_HASH160, OP_PUSHBYTES},
-    str_utils::{get_compact_size_bytes, get_hex_bytes},
+    policy::{Policy, PolicyError},
+    script::{Script, ScriptBuf, ScriptError, ScriptTemplate},
+    sighash::PrecomputedTransactionData,
+    str_utils::{get_hex_bytes, parse_hex, HexError},
+    MAX_MONEY, MAX_WEIGHT_ALLOWED, WITNESS_SCALE_FACTOR,
 };
 
+// locktimes below this are block heights, the others unix timestamps
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+// inputs with this sequence opt out of the locktime
+pub const SEQUENCE_FINAL: u32 = 0xffffffff;
+// BIP68 relative locks: the disable bit, blocks or 512 second units, and the lock value
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000ffff;
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 9;
+
 #[path = "./test/transaction_tests.rs"]
 #[cfg(test)]
 mod transaction_test;
@@ -18,6 +44,8 @@ pub enum PubkeyType {
     P2WSH,    // SegWit transaction unlock script type, witness field is present
     P2TR,     // Pay to taproot locks bitcoin
     P2SH,     // Pay to hash
+    P2PK,     // Pay to pubkey, <pubkey> OP_CHECKSIG
+    MULTISIG, // Bare multisig, m <pubkeys> n OP_CHECKMULTISIG
 }
 
 impl FromStr for PubkeyType {
@@ -30,45 +58,480 @@ impl FromStr for PubkeyType {
             "v0_p2wpkh" => Ok(PubkeyType::P2WPKH),
             "v0_p2wsh" => Ok(PubkeyType::P2WSH),
             "v1_p2tr" => Ok(PubkeyType::P2TR),
+            "p2pk" => Ok(PubkeyType::P2PK),
+            "multisig" => Ok(PubkeyType::MULTISIG),
             _ => Err(()),
         }
     }
 }
 
-#[derive(Deserialize)]
+impl TryFrom<ScriptTemplate> for PubkeyType {
+    type Error = ();
+
+    fn try_from(template: ScriptTemplate) -> Result<Self, Self::Error> {
+        match template {
+            ScriptTemplate::P2PKH => Ok(PubkeyType::P2PKH),
+            ScriptTemplate::P2SH => Ok(PubkeyType::P2SH),
+            ScriptTemplate::P2WPKH => Ok(PubkeyType::P2WPKH),
+            ScriptTemplate::P2WSH => Ok(PubkeyType::P2WSH),
+            ScriptTemplate::P2TR => Ok(PubkeyType::P2TR),
+            ScriptTemplate::P2PK => Ok(PubkeyType::P2PK),
+            ScriptTemplate::Multisig { .. } => Ok(PubkeyType::MULTISIG),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Decision taken while validating a single input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputVerdict {
+    // the scripts were executed and left true on the stack
+    Valid,
+    // unknown witness version or length, valid by consensus without running any script
+    AnyoneCanSpend { version: u8, program_len: usize },
+    Invalid(ScriptError),
+    // a script type the interpreter cannot validate yet
+    Unsupported(ScriptTemplate),
+}
+
+impl InputVerdict {
+    pub fn is_valid(&self) -> bool {
+        matches!(self, InputVerdict::Valid | InputVerdict::AnyoneCanSpend { .. })
+    }
+
+    fn from_result(result: Result<Vec<u8>, ScriptError>) -> Self {
+        match result {
+            Ok(top) if cast_to_bool(&top) => InputVerdict::Valid,
+            Ok(_) => InputVerdict::Invalid(ScriptError::EvalFalse),
+            Err(err) => InputVerdict::Invalid(err),
+        }
+    }
+}
+
+/// Why `validate_transacation` turns a transaction down, the first rule it breaks
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxRejection {
+    Check(TxCheckError),
+    Policy(PolicyError),
+    Input { vin: usize, verdict: InputVerdict },
+}
+
+impl fmt::Display for TxRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxRejection::Check(err) => write!(f, "{}", err),
+            TxRejection::Policy(err) => write!(f, "non-standard {}", err),
+            TxRejection::Input { vin, verdict } => {
+                write!(f, "vin {} does not validate: {:?}", vin, verdict)
+            }
+        }
+    }
+}
+
+/// The transaction in the schema of the mempool json, enriched with its ids and what
+/// was computed for it. See `Transaction::enriched`
+#[derive(Serialize)]
+pub struct EnrichedTransaction<'a> {
+    pub txid: Txid,
+    pub wtxid: Wtxid,
+    #[serde(flatten)]
+    pub tx: &'a Transaction,
+    pub weight: usize,
+    pub vsize: usize,
+    pub fee: Option<u64>,
+    // satoshis per virtual byte, none without a fee
+    pub feerate: Option<f64>,
+    pub sigop_cost: usize,
+    // "valid", or the reason of the rejection
+    pub verdict: String,
+}
+
+/// A `scriptpubkey_type` label of the mempool json which does not agree with the script
+#[derive(Debug)]
+pub struct ScriptLabelMismatch {
+    // e.g. "vin 0" for a prevout or "vout 1" for an output
+    pub location: String,
+    pub label: String,
+    pub derived: ScriptTemplate,
+}
+
+/// A `scriptpubkey_address` of the mempool json which is not the address of the script,
+/// or is missing for a script which has one
+#[derive(Debug)]
+pub struct AddressMismatch {
+    // e.g. "vin 0" for a prevout or "vout 1" for an output
+    pub location: String,
+    pub address: Option<String>,
+    pub derived: Option<String>,
+}
+
+/// Reasons raw transaction bytes can not be decoded, offsets are the number of bytes
+/// read before decoding stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxDecodeError {
+    InvalidHex,
+    UnexpectedEnd { offset: usize },
+    NonCanonicalCompactSize { offset: usize },
+    OversizedCompactSize { offset: usize, size: u64 },
+    // the segwit marker is set but every witness is empty
+    SuperfluousWitness,
+    UnknownOptionalData(u8),
+    TrailingData { offset: usize, remaining: usize },
+}
+
+impl TxDecodeError {
+    fn at(err: DecodeError, offset: usize) -> Self {
+        match err {
+            DecodeError::Io(_) => TxDecodeError::UnexpectedEnd { offset },
+            DecodeError::NonCanonicalCompactSize => TxDecodeError::NonCanonicalCompactSize { offset },
+            DecodeError::OversizedCompactSize(size) => {
+                TxDecodeError::OversizedCompactSize { offset, size }
+            }
+            DecodeError::SuperfluousWitness => TxDecodeError::SuperfluousWitness,
+            DecodeError::UnknownOptionalData(flags) => TxDecodeError::UnknownOptionalData(flags),
+        }
+    }
+}
+
+/// Reasons a transaction fails the consensus checks done before any script runs, the
+/// same checks as CheckTransaction and CheckTxInputs in bitcoin core
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TxCheckError {
+    EmptyVin,
+    EmptyVout,
+    // serialized without witness, times four, is above the block weight
+    Oversize,
+    VoutValueTooLarge { vout: usize },
+    TotalOutputTooLarge,
+    DuplicateInput { vin: usize },
+    // a coinbase input in a transaction which is not a coinbase
+    NullPrevout { vin: usize },
+    CoinbaseScriptSize(usize),
+    InputValuesOutOfRange,
+    InputsBelowOutputs,
+}
+
+impl fmt::Display for TxCheckError {
+    // reject reasons as bitcoin core reports them
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxCheckError::EmptyVin => write!(f, "bad-txns-vin-empty"),
+            TxCheckError::EmptyVout => write!(f, "bad-txns-vout-empty"),
+            TxCheckError::Oversize => write!(f, "bad-txns-oversize"),
+            TxCheckError::VoutValueTooLarge { vout } => {
+                write!(f, "bad-txns-vout-toolarge (vout {})", vout)
+            }
+            TxCheckError::TotalOutputTooLarge => write!(f, "bad-txns-txouttotal-toolarge"),
+            TxCheckError::DuplicateInput { vin } => {
+                write!(f, "bad-txns-inputs-duplicate (vin {})", vin)
+            }
+            TxCheckError::NullPrevout { vin } => write!(f, "bad-txns-prevout-null (vin {})", vin),
+            TxCheckError::CoinbaseScriptSize(len) => write!(f, "bad-cb-length ({} bytes)", len),
+            TxCheckError::InputValuesOutOfRange => write!(f, "bad-txns-inputvalues-outofrange"),
+            TxCheckError::InputsBelowOutputs => write!(f, "bad-txns-in-belowout"),
+        }
+    }
+}
+
+// sum of amounts which fails once it leaves the money range instead of overflowing
+fn checked_money_sum(mut values: impl Iterator<Item = u64>) -> Option<u64> {
+    values.try_fold(0u64, |total, value| {
+        total.checked_add(value).filter(|total| *total <= MAX_MONEY)
+    })
+}
+
+/// Output being spent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OutPoint {
+    pub txid: Txid,
+    pub vout: u32,
+}
+
+impl Encodable for OutPoint {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.txid.consensus_encode(writer)? + self.vout.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for OutPoint {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        Ok(OutPoint {
+            txid: Decodable::consensus_decode(reader)?,
+            vout: Decodable::consensus_decode(reader)?,
+        })
+    }
+}
+
+impl OutPoint {
+    /// The outpoint spent by coinbase inputs
+    pub fn is_null(&self) -> bool {
+        self.vout == u32::MAX && self.txid == Txid::all_zeros()
+    }
+}
+
+/// Witness stack of an input
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Witness(pub Vec<Vec<u8>>);
+
+impl Witness {
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Vec<u8>> {
+        self.0.iter()
+    }
+}
+
+impl From<Vec<Vec<u8>>> for Witness {
+    fn from(items: Vec<Vec<u8>>) -> Self {
+        Witness(items)
+    }
+}
+
+// a list of hex items in the mempool json
+impl<'de> Deserialize<'de> for Witness {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let items = Vec::<String>::deserialize(deserializer)?;
+        items
+            .iter()
+            .map(|item| parse_hex(item))
+            .collect::<Result<Vec<Vec<u8>>, HexError>>()
+            .map(Witness)
+            .map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for Witness {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.len()))?;
+        for item in self.iter() {
+            seq.serialize_element(&hex_str!(item))?;
+        }
+        seq.end()
+    }
+}
+
+impl Encodable for Witness {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut len = CompactSize(self.0.len() as u64).consensus_encode(writer)?;
+        for item in self.0.iter() {
+            len += item.consensus_encode(writer)?;
+        }
+        Ok(len)
+    }
+}
+
+impl Decodable for Witness {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        let count = CompactSize::consensus_decode(reader)?.0;
+        let mut items: Vec<Vec<u8>> = Vec::new();
+        for _ in 0..count {
+            items.push(Decodable::consensus_decode(reader)?);
+        }
+        Ok(Witness(items))
+    }
+}
+
+fn hash_bytes(raw_tx: &Vec<u8>) -> [u8; 32] {
+    double_hash256(raw_tx)
+        .try_into()
+        .expect("sha256 hashes are 32 bytes")
+}
+
+// lists of inputs and outputs are prefixed with their count
+macro_rules! impl_vec_encodable {
+    ($($item: ty), +) => {
+        $(
+            impl Encodable for Vec<$item> {
+                fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+                    let mut len = CompactSize(self.len() as u64).consensus_encode(writer)?;
+                    for item in self.iter() {
+                        len += item.consensus_encode(writer)?;
+                    }
+                    Ok(len)
+                }
+            }
+
+            impl Decodable for Vec<$item> {
+                fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+                    let count = CompactSize::consensus_decode(reader)?.0;
+                    let mut items: Vec<$item> = Vec::new();
+                    for _ in 0..count {
+                        items.push(Decodable::consensus_decode(reader)?);
+                    }
+                    Ok(items)
+                }
+            }
+        )+
+    };
+}
+
+impl_vec_encodable!(Vin, Pubkey);
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Pubkey {
-    pub scriptpubkey: String,
+    pub scriptpubkey: ScriptBuf,
     pub scriptpubkey_asm: String,
     pub scriptpubkey_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub scriptpubkey_address: Option<String>,
     pub value: u64,
 }
 
-#[derive(Deserialize)]
+impl Pubkey {
+    /// Output of `value` locked by `scriptpubkey`, with the asm and type labels the
+    /// mempool json has for it
+    pub fn new(scriptpubkey: ScriptBuf, value: u64) -> Self {
+        let script = scriptpubkey.as_script();
+        Pubkey {
+            scriptpubkey_asm: script.to_asm(),
+            scriptpubkey_type: String::from(script.classify().label()),
+            // the mempool is a mainnet one
+            scriptpubkey_address: Address::from_script(script, Network::Bitcoin)
+                .map(|address| address.to_string()),
+            scriptpubkey,
+            value,
+        }
+    }
+
+    /// Template derived from the scriptpubkey bytes
+    pub fn classify(&self) -> ScriptTemplate {
+        self.scriptpubkey.as_script().classify()
+    }
+
+    // the script type used for validation, the json label is never consulted
+    pub fn pubkey_type(&self) -> Option<PubkeyType> {
+        PubkeyType::try_from(self.classify()).ok()
+    }
+}
+
+impl Encodable for Pubkey {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.value.consensus_encode(writer)? + self.scriptpubkey.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for Pubkey {
+    // only the amount and script are encoded, the labels are left empty
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        let value = u64::consensus_decode(reader)?;
+        Ok(Pubkey {
+            scriptpubkey: ScriptBuf::consensus_decode(reader)?,
+            scriptpubkey_asm: String::new(),
+            scriptpubkey_type: String::new(),
+            scriptpubkey_address: None,
+            value,
+        })
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Vin {
-    pub txid: String,
+    pub txid: Txid,
     pub vout: u32,
     pub prevout: Pubkey,
-    pub scriptsig: String,
+    pub scriptsig: ScriptBuf,
     pub scriptsig_asm: String,
-    pub witness: Option<Vec<String>>,
+    // the feed leaves both out for the inputs which do not have them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub witness: Option<Witness>,
     pub is_coinbase: bool,
     pub sequence: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub inner_redeemscript_asm: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inner_witnessscript_asm: Option<String>,
+}
+
+impl Vin {
+    /// The output spent by this input
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint {
+            txid: self.txid,
+            vout: self.vout,
+        }
+    }
+
+    fn witness_sigop_count(&self) -> usize {
+        let scriptpubkey = self.prevout.scriptpubkey.as_script();
+        let program = match scriptpubkey.witness_program() {
+            Some(program) => Some(program),
+            // a witness program as the redeem script
+            None if scriptpubkey.classify() == ScriptTemplate::P2SH => self
+                .scriptsig
+                .as_script()
+                .last_push()
+                .and_then(|redeem_script| Script::new(redeem_script).witness_program()),
+            None => None,
+        };
+        let witness = self.witness.as_ref().map(|witness| witness.0.as_slice());
+        match (program, witness) {
+            (Some((0, program)), _) if program.len() == 20 => 1,
+            (Some((0, program)), Some([.., witness_script])) if program.len() == 32 => {
+                Script::new(witness_script).sigop_count(true)
+            }
+            _ => 0,
+        }
+    }
+}
+
+// the witness is not part of an input's encoding, it follows all the outputs
+impl Encodable for Vin {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.outpoint().consensus_encode(writer)?
+            + self.scriptsig.consensus_encode(writer)?
+            + self.sequence.consensus_encode(writer)?)
+    }
+}
+
+impl Decodable for Vin {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        let outpoint = OutPoint::consensus_decode(reader)?;
+        let scriptsig = ScriptBuf::consensus_decode(reader)?;
+        let sequence = u32::consensus_decode(reader)?;
+
+        Ok(Vin {
+            is_coinbase: outpoint.vout == 0xffffffff && outpoint.txid == Txid::all_zeros(),
+            txid: outpoint.txid,
+            vout: outpoint.vout,
+            // the spent output is not part of the encoding
+            prevout: Pubkey {
+                scriptpubkey: ScriptBuf::new(),
+                scriptpubkey_asm: String::new(),
+                scriptpubkey_type: String::new(),
+                scriptpubkey_address: None,
+                value: 0,
+            },
+            scriptsig,
+            scriptsig_asm: String::new(),
+            witness: None,
+            sequence,
+            inner_redeemscript_asm: None,
+            inner_witnessscript_asm: None,
+        })
+    }
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct Transaction {
-    pub txid: Option<String>,
-    pub wtxid: Option<String>,
     // this is the sha256 hash of the txid (reverse order, again just a bitcoin thing)
+    #[serde(skip_serializing)]
     pub sanity_hash: Option<String>,
     pub version: u32,
     pub locktime: u32,
     pub vin: Vec<Vin>,
     pub vout: Vec<Pubkey>,
-    pub is_segwit: Option<bool>,
+    // computed on load, they are not part of the mempool json
+    #[serde(skip_serializing)]
     pub weight: Option<usize>,
+    #[serde(skip_serializing)]
+    pub sigop_cost: Option<usize>,
+    #[serde(skip_serializing)]
     pub tx_fee: Option<u64>,
 }
 
@@ -77,416 +540,674 @@ impl Transaction {
     pub fn new(raw_json_tx: &str) -> Result<Transaction, serde_json::Error> {
         let mut tx: Transaction = serde_json::from_str(raw_json_tx)?;
         tx.assign_weight();
+        tx.assign_sigop_cost();
         tx.assign_tx_fee();
         Ok(tx)
     }
 
-    // Raw transaction in bytes which can be considered for computing txid
-    pub fn get_raw_bytes(&self, include_witness: bool) -> Vec<u8> {
-        let mut raw_bytes: Vec<u8> = Vec::new();
-
-        // First we push the version bytes
-        let version_bytes: Vec<u8> = self.version.to_le_bytes().to_vec();
-        version_bytes.iter().for_each(|val| raw_bytes.push(*val));
+    /// Decodes the consensus encoding of a transaction, with or without the segwit
+    /// marker. Prevouts are not part of the encoding and are left empty, so are the
+    /// asm and type labels
+    pub fn from_bytes(bytes: &[u8]) -> Result<Transaction, TxDecodeError> {
+        let mut reader = SliceReader::new(bytes);
+        let tx = Transaction::consensus_decode(&mut reader)
+            .map_err(|err| TxDecodeError::at(err, reader.position()))?;
+        if reader.remaining() != 0 {
+            return Err(TxDecodeError::TrailingData {
+                offset: reader.position(),
+                remaining: reader.remaining(),
+            });
+        }
+        Ok(tx)
+    }
 
-        if include_witness {
-            raw_bytes.push(0x00);
-            raw_bytes.push(0x01);
+    pub fn from_hex(raw_tx: &str) -> Result<Transaction, TxDecodeError> {
+        if !raw_tx.len().is_multiple_of(2) || !raw_tx.is_ascii() {
+            return Err(TxDecodeError::InvalidHex);
         }
+        let bytes = get_hex_bytes(raw_tx).map_err(|_| TxDecodeError::InvalidHex)?;
+        Self::from_bytes(&bytes)
+    }
 
-        // The number of input bytes
-        let vin_len_bytes = get_compact_size_bytes(&(self.vin.len() as u64));
-        vin_len_bytes.iter().for_each(|val| raw_bytes.push(*val));
+    /// The transaction in the schema of the mempool json, `Transaction::new` loads it
+    /// back. Weight, fee and the other computed fields are left out, `to_enriched_json`
+    /// has them
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("transactions always serialize")
+    }
 
-        // will push the input ids
-        self.vin.iter().for_each(|vin| {
-            // will first push in txid in reverse order you know just bitcoin things
-            let txid_bytes: Vec<u8> = get_hex_bytes(vin.txid.as_ref()).unwrap();
-            txid_bytes.iter().rev().for_each(|val| raw_bytes.push(*val));
+    /// The transaction with its ids, weight, fee, sigop cost and validation verdict, to
+    /// be serialized alongside the fields of the mempool json
+    pub fn enriched(&self) -> EnrichedTransaction<'_> {
+        let weight = self.weight.unwrap_or_else(|| self.get_weight());
+        // virtual size is the weight in vbytes, rounded up
+        let vsize = weight.div_ceil(WITNESS_SCALE_FACTOR);
+        EnrichedTransaction {
+            txid: self.txid(),
+            wtxid: self.wtxid(),
+            tx: self,
+            weight,
+            vsize,
+            fee: self.tx_fee,
+            feerate: self.tx_fee.map(|fee| fee as f64 / vsize as f64),
+            sigop_cost: self.sigop_cost.unwrap_or_else(|| self.get_sigop_cost()),
+            verdict: match self.validation() {
+                Ok(()) => String::from("valid"),
+                Err(rejection) => rejection.to_string(),
+            },
+        }
+    }
 
-            // vout bytes in little endian format
-            let vout_bytes: Vec<u8> = vin.vout.to_le_bytes().to_vec();
-            vout_bytes.iter().for_each(|val| raw_bytes.push(*val));
+    pub fn to_enriched_json(&self) -> String {
+        serde_json::to_string_pretty(&self.enriched()).expect("transactions always serialize")
+    }
 
-            // get length of scriptsig size in compact_size foramt
-            let scriptsig_len_bytes: Vec<u8> =
-                get_compact_size_bytes(&((vin.scriptsig.len() / 2) as u64));
-            scriptsig_len_bytes
-                .iter()
-                .for_each(|val| raw_bytes.push(*val));
-
-            // lets push the actual signature now
-            let scriptsig_bytes: Vec<u8> = get_hex_bytes(&vin.scriptsig).unwrap();
-            scriptsig_bytes.iter().for_each(|val| raw_bytes.push(*val));
-
-            // push the sequence bytes in little endian format
-            let sequence_bytes: Vec<u8> = vin.sequence.to_le_bytes().to_vec();
-            sequence_bytes.iter().for_each(|val| raw_bytes.push(*val));
-        });
-
-        // push vout bytes in compact size format
-        let vout_len_bytes: Vec<u8> = get_compact_size_bytes(&(self.vout.len() as u64));
-        vout_len_bytes.iter().for_each(|val| raw_bytes.push(*val));
-
-        self.vout.iter().for_each(|vout| {
-            // push amout in little endian format
-            let amount_bytes: Vec<u8> = vout.value.to_le_bytes().to_vec();
-            amount_bytes.iter().for_each(|val| raw_bytes.push(*val));
-
-            // push scriptsig size in compact size format
-            let scriptsigsize_len_bytes: Vec<u8> =
-                get_compact_size_bytes(&((vout.scriptpubkey.len() / 2) as u64));
-            scriptsigsize_len_bytes
-                .iter()
-                .for_each(|val| raw_bytes.push(*val));
+    /// Name of the mempool json file of the transaction: the sha256 of the txid in
+    /// display order
+    pub fn compute_sanity_hash(&self) -> String {
+        let mut txid_bytes: Vec<u8> = self.txid().to_byte_array().to_vec();
+        txid_bytes.reverse();
+        hex_str!(hash256(&txid_bytes))
+    }
 
-            // push the actual scriptsig
-            let scripitsig_bytes: Vec<u8> = get_hex_bytes(&vout.scriptpubkey).unwrap();
-            scripitsig_bytes.iter().for_each(|val| raw_bytes.push(*val));
-        });
+    // Raw transaction in bytes which can be considered for computing txid
+    pub fn get_raw_bytes(&self, include_witness: bool) -> Vec<u8> {
+        let mut raw_bytes: Vec<u8> = Vec::new();
+        self.encode_with(&mut raw_bytes, include_witness)
+            .expect("writing to a Vec never fails");
+        raw_bytes
+    }
 
+    // the segwit marker and flag come right after the version and the witnesses of
+    // every input after the outputs
+    fn encode_with<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        include_witness: bool,
+    ) -> io::Result<usize> {
+        let mut len = self.version.consensus_encode(writer)?;
+        if include_witness {
+            len += [0x00u8, 0x01].consensus_encode(writer)?;
+        }
+        len += self.vin.consensus_encode(writer)?;
+        len += self.vout.consensus_encode(writer)?;
         if include_witness {
-            // push number of txs
-
             for vin in self.vin.iter() {
-                // iterate over all the witness field if any
-                if let Some(witness) = &vin.witness {
-                    get_compact_size_bytes(&(witness.len() as u64))
-                        .iter()
-                        .for_each(|x| raw_bytes.push(*x));
-                    witness.iter().for_each(|val| {
-                        get_compact_size_bytes(&((val.len() / 2) as u64))
-                            .iter()
-                            .for_each(|x| raw_bytes.push(*x)); //  I know that this wil be a even number
-                        get_hex_bytes(val)
-                            .unwrap()
-                            .iter()
-                            .for_each(|x| raw_bytes.push(*x));
-                    });
-                } else {
-                    raw_bytes.push(0x00);
-                }
+                // inputs without a witness get an empty one
+                len += match vin.witness.as_ref() {
+                    Some(witness) => witness.consensus_encode(writer)?,
+                    None => CompactSize(0).consensus_encode(writer)?,
+                };
             }
         }
+        len += self.locktime.consensus_encode(writer)?;
+        Ok(len)
+    }
 
-        self.locktime
-            .to_le_bytes()
-            .iter()
-            .for_each(|val| raw_bytes.push(*val));
-
-        raw_bytes
+    pub fn validate_transacation(&self) -> bool {
+        self.validation().is_ok()
     }
 
-    pub fn get_raw_tx_for_legacy_tx(&self, idx: u32) -> Vec<u8> {
-        let mut raw_bytes: Vec<u8> = Vec::new();
+    /// Consensus checks, standardness and every input script, in this order
+    pub fn validation(&self) -> Result<(), TxRejection> {
+        self.check_transaction().map_err(TxRejection::Check)?;
+        self.check_tx_inputs().map_err(TxRejection::Check)?;
+        // we only mine what the network would relay, so policy applies as well and the
+        // scripts run under the standard flags the interpreter enforces
+        Policy::default()
+            .check_standard(self)
+            .map_err(TxRejection::Policy)?;
+        match self
+            .verify_inputs(VerifyFlags::STANDARD)
+            .into_iter()
+            .enumerate()
+            .find(|(_, verdict)| !verdict.is_valid())
+        {
+            Some((vin, verdict)) => Err(TxRejection::Input { vin, verdict }),
+            None => Ok(()),
+        }
+    }
 
-        // push version bytes
-        let version_bytes: Vec<u8> = self.version.to_le_bytes().to_vec();
-        version_bytes.iter().for_each(|val| raw_bytes.push(*val));
+    /// Segwit transactions are the ones with at least one non empty witness, whatever
+    /// the spent scripts are. Only these are serialized with the marker and witnesses
+    pub fn has_witness(&self) -> bool {
+        self.vin
+            .iter()
+            .any(|vin| vin.witness.as_ref().is_some_and(|witness| !witness.is_empty()))
+    }
 
-        // number of vins in compact size format
-        let vin_len_bytes = get_compact_size_bytes(&(self.vin.len() as u64));
-        vin_len_bytes.iter().for_each(|val| raw_bytes.push(*val));
+    /// Whether the locktime allows the transaction in a block at `height` whose median
+    /// time past is `block_time`, same as IsFinalTx in bitcoin core. A locktime which
+    /// has not passed yet is ignored when every input has a final sequence
+    pub fn is_final(&self, height: u32, block_time: u32) -> bool {
+        if self.locktime == 0 {
+            return true;
+        }
+        let cutoff = if self.locktime < LOCKTIME_THRESHOLD {
+            height
+        } else {
+            block_time
+        };
+        if self.locktime < cutoff {
+            return true;
+        }
+        self.vin.iter().all(|vin| vin.sequence == SEQUENCE_FINAL)
+    }
 
-        // operation specific to each vin
-        for (i, vin) in self.vin.iter().enumerate() {
-            // push txid bytes
-            let txid_bytes = get_hex_bytes(&vin.txid);
-            if let Ok(vin) = txid_bytes {
-                vin.iter().rev().for_each(|val| raw_bytes.push(*val));
+    /// Whether the BIP68 relative locks of a version 2 or later transaction allow it in
+    /// the block of `context`, same as CalculateSequenceLocks and EvaluateSequenceLocks in
+    /// bitcoin core. `parents` has the block each spent transaction confirms in, the
+    /// locks of inputs spending other parents cannot be checked and are left to the
+    /// caller, see `unchecked_sequence_locks`
+    pub fn check_sequence_locks(
+        &self,
+        context: &BlockContext,
+        parents: &HashMap<Txid, BlockContext>,
+    ) -> bool {
+        if self.version < 2 {
+            return true;
+        }
+        // last height and median time past at which the transaction is still locked
+        let mut min_height: i64 = -1;
+        let mut min_time: i64 = -1;
+        for vin in self.vin.iter() {
+            if vin.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+                continue;
             }
-
-            // vout bytes in little endian format
-            let vout_bytes: Vec<u8> = vin.vout.to_le_bytes().to_vec();
-            vout_bytes.iter().for_each(|val| raw_bytes.push(*val));
-
-            // include the vin which we are verifying, else script will be empty
-            if idx == i as u32 {
-                // get length of scriptsig size in compact_size foramt
-                let scriptsig_len_bytes: Vec<u8> =
-                    get_compact_size_bytes(&((vin.prevout.scriptpubkey.len() / 2) as u64));
-                scriptsig_len_bytes
-                    .iter()
-                    .for_each(|val| raw_bytes.push(*val));
-                // lets push the actual script sig now
-                let scriptsig_bytes: Vec<u8> = get_hex_bytes(&vin.prevout.scriptpubkey).unwrap();
-                scriptsig_bytes.iter().for_each(|val| raw_bytes.push(*val));
+            let Some(confirmed) = parents.get(&vin.txid) else {
+                continue;
+            };
+            let value = (vin.sequence & SEQUENCE_LOCKTIME_MASK) as i64;
+            if vin.sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+                let lock = confirmed.median_time_past as i64
+                    + (value << SEQUENCE_LOCKTIME_GRANULARITY)
+                    - 1;
+                min_time = min_time.max(lock);
             } else {
-                let x = 0x00;
-                let scriptsig_len_bytes: Vec<u8> = get_compact_size_bytes(&(x as u64));
-                scriptsig_len_bytes
-                    .iter()
-                    .for_each(|val| raw_bytes.push(*val));
+                min_height = min_height.max(confirmed.height as i64 + value - 1);
             }
+        }
+        min_height < context.height as i64 && min_time < context.median_time_past as i64
+    }
 
-            // push the sequence bytes in little endian format
-            let sequence_bytes: Vec<u8> = vin.sequence.to_le_bytes().to_vec();
-            sequence_bytes.iter().for_each(|val| raw_bytes.push(*val));
+    /// Indices of the inputs with a nonzero BIP68 relative lock whose parent is not in
+    /// `parents`, `check_sequence_locks` does not know when it confirmed and skips them
+    pub fn unchecked_sequence_locks(&self, parents: &HashMap<Txid, BlockContext>) -> Vec<usize> {
+        if self.version < 2 {
+            return Vec::new();
         }
+        self.vin
+            .iter()
+            .enumerate()
+            .filter(|(_, vin)| {
+                vin.sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG == 0
+                    && vin.sequence & SEQUENCE_LOCKTIME_MASK != 0
+                    && !parents.contains_key(&vin.txid)
+            })
+            .map(|(n, _)| n)
+            .collect()
+    }
 
-        // push vout bytes in compact size format
-        let vout_len_bytes: Vec<u8> = get_compact_size_bytes(&(self.vout.len() as u64));
-        vout_len_bytes.iter().for_each(|val| raw_bytes.push(*val));
+    /// A coinbase has a single input which spends the null outpoint
+    pub fn is_coinbase(&self) -> bool {
+        self.vin.len() == 1 && self.vin[0].outpoint().is_null()
+    }
 
-        self.vout.iter().for_each(|vout| {
-            // push amout in little endian format
-            let amount_bytes: Vec<u8> = vout.value.to_le_bytes().to_vec();
-            amount_bytes.iter().for_each(|val| raw_bytes.push(*val));
+    /// Checks which need nothing but the transaction itself, they run before the
+    /// scripts so nothing after them has to deal with e.g. overflowing amounts
+    pub fn check_transaction(&self) -> Result<(), TxCheckError> {
+        if self.vin.is_empty() {
+            return Err(TxCheckError::EmptyVin);
+        }
+        if self.vout.is_empty() {
+            return Err(TxCheckError::EmptyVout);
+        }
+        if self.get_raw_bytes(false).len() * 4 > MAX_WEIGHT_ALLOWED as usize {
+            return Err(TxCheckError::Oversize);
+        }
 
-            // push scriptsig size in compact size format
-            let scriptsigsize_len_bytes: Vec<u8> =
-                get_compact_size_bytes(&((vout.scriptpubkey.len() / 2) as u64));
-            scriptsigsize_len_bytes
-                .iter()
-                .for_each(|val| raw_bytes.push(*val));
+        if let Some(vout) = self.vout.iter().position(|vout| vout.value > MAX_MONEY) {
+            return Err(TxCheckError::VoutValueTooLarge { vout });
+        }
+        if checked_money_sum(self.vout.iter().map(|vout| vout.value)).is_none() {
+            return Err(TxCheckError::TotalOutputTooLarge);
+        }
 
-            // push the actual scriptsig
-            let scripitsig_bytes: Vec<u8> = get_hex_bytes(&vout.scriptpubkey).unwrap();
-            scripitsig_bytes.iter().for_each(|val| raw_bytes.push(*val));
-        });
+        let mut outpoints: HashSet<OutPoint> = HashSet::new();
+        if let Some(vin) = self
+            .vin
+            .iter()
+            .position(|vin| !outpoints.insert(vin.outpoint()))
+        {
+            return Err(TxCheckError::DuplicateInput { vin });
+        }
 
-        self.locktime
-            .to_le_bytes()
+        if self.is_coinbase() {
+            let len = self.vin[0].scriptsig.len();
+            if !(2..=100).contains(&len) {
+                return Err(TxCheckError::CoinbaseScriptSize(len));
+            }
+        } else if let Some(vin) = self
+            .vin
             .iter()
-            .for_each(|val| raw_bytes.push(*val));
+            .position(|vin| vin.is_coinbase || vin.outpoint().is_null())
+        {
+            return Err(TxCheckError::NullPrevout { vin });
+        }
+        Ok(())
+    }
 
-        raw_bytes
+    /// Checks of the spent amounts, which the mempool json carries in the prevouts
+    pub fn check_tx_inputs(&self) -> Result<(), TxCheckError> {
+        if self.is_coinbase() {
+            return Ok(());
+        }
+        let vin_amount = checked_money_sum(self.vin.iter().map(|vin| vin.prevout.value))
+            .ok_or(TxCheckError::InputValuesOutOfRange)?;
+        let vout_amount = checked_money_sum(self.vout.iter().map(|vout| vout.value))
+            .ok_or(TxCheckError::TotalOutputTooLarge)?;
+        if vin_amount < vout_amount {
+            return Err(TxCheckError::InputsBelowOutputs);
+        }
+        Ok(())
     }
 
-    pub fn serialize_witness_transaction(&self, idx: u32) -> Vec<u8> {
-        let version_bytes: Vec<u8> = self.version.to_le_bytes().to_vec();
+    /// Validates every input and records the decision taken for each of them
+    pub fn verify_inputs(&self, flags: VerifyFlags) -> Vec<InputVerdict> {
+        // computed once for every input, like the txdata of CheckInputScripts
+        let precomputed = PrecomputedTransactionData::new(self);
+        (0..self.vin.len())
+            .map(|idx| self.verify_input_with(idx as u32, flags, &precomputed))
+            .collect()
+    }
 
-        let mut txid_vout_bytes: Vec<u8> = Vec::new();
-        let mut sequence_bytes: Vec<u8> = Vec::new();
+    pub fn verify_input(&self, idx: u32, flags: VerifyFlags) -> InputVerdict {
+        self.verify_input_with(idx, flags, &PrecomputedTransactionData::new(self))
+    }
 
-        for vin in self.vin.iter() {
-            // prepare txid+vout for all tx
-            let txid_bytes = get_hex_bytes(&vin.txid);
-            if let Ok(val) = txid_bytes {
-                val.iter().rev().for_each(|x| txid_vout_bytes.push(*x));
-            }
-            let vout_bytes = vin.vout.to_le_bytes().to_vec();
-            vout_bytes.iter().for_each(|x| txid_vout_bytes.push(*x));
+    fn verify_input_with(
+        &self,
+        idx: u32,
+        flags: VerifyFlags,
+        precomputed: &PrecomputedTransactionData,
+    ) -> InputVerdict {
+        let mut template = self.vin[idx as usize].prevout.classify();
+
+        if flags.contains(VerifyFlags::SIGPUSHONLY)
+            && !self.vin[idx as usize].scriptsig.as_script().is_push_only()
+        {
+            return InputVerdict::Invalid(ScriptError::SigPushOnly);
+        }
 
-            // prepare sequence byte array
-            vin.sequence
-                .to_le_bytes()
-                .iter()
-                .for_each(|val| sequence_bytes.push(*val));
-        }
-
-        let txid_vout_hash = double_hash256(&txid_vout_bytes);
-        let sequence_hash = double_hash256(&sequence_bytes);
-
-        let vin = self.vin.get(idx as usize);
-
-        let (cur_txid_vout_bytes, scriptcode_bytes, amount_bytes, sequence_bytes) =
-            if let Some(val) = vin {
-                let mut cur_txid_vout_bytes: Vec<u8> = Vec::new();
-                let mut scriptcode_bytes: Vec<u8> = vec![
-                    OP_DUP.code,
-                    OP_HASH160.code,
-                    OP_PUSHBYTES.code + 0x014 - 0x01,
-                ];
-                if let Ok(txid_bytes) = get_hex_bytes(&val.txid) {
-                    txid_bytes
-                        .iter()
-                        .rev()
-                        .for_each(|x| cur_txid_vout_bytes.push(*x));
-                }
+        let is_witness_program = matches!(
+            template,
+            ScriptTemplate::P2WPKH
+                | ScriptTemplate::P2WSH
+                | ScriptTemplate::P2TR
+                | ScriptTemplate::Anchor
+                | ScriptTemplate::WitnessUnknown { .. }
+        );
+
+        // before the soft forks activate their outputs are plain scripts, taproot
+        // outputs are just another unknown witness version
+        if (is_witness_program && !flags.contains(VerifyFlags::WITNESS))
+            || (template == ScriptTemplate::P2SH && !flags.contains(VerifyFlags::P2SH))
+        {
+            template = ScriptTemplate::NonStandard;
+        } else if template == ScriptTemplate::P2TR && !flags.contains(VerifyFlags::TAPROOT) {
+            template = ScriptTemplate::WitnessUnknown {
+                version: 1,
+                program_len: 32,
+            };
+        }
 
-                val.vout
-                    .to_le_bytes()
-                    .to_vec()
-                    .iter()
-                    .for_each(|x| cur_txid_vout_bytes.push(*x));
+        match template {
+            ScriptTemplate::P2WPKH => self.verify_p2wpkh_vin(idx, flags, precomputed),
+            ScriptTemplate::WitnessUnknown {
+                version,
+                program_len,
+            } => self.verify_upgradable_witness_vin(idx, version, program_len, flags),
+            ScriptTemplate::Anchor => {
+                // pay to anchor is a v1 program which policy explicitly allows spending
+                if self.vin[idx as usize].scriptsig.is_empty() {
+                    InputVerdict::AnyoneCanSpend {
+                        version: 1,
+                        program_len: 2,
+                    }
+                } else {
+                    InputVerdict::Invalid(ScriptError::WitnessMalleated)
+                }
+            }
+            ScriptTemplate::P2SH | ScriptTemplate::P2WSH | ScriptTemplate::P2TR => {
+                InputVerdict::Unsupported(template)
+            }
+            // everything else is a plain script, including the nonstandard ones
+            _ => self.verify_legacy_vin(idx, flags, precomputed),
+        }
+    }
 
-                // crerate the scriptcode
-                if let Ok(pubkeyhash) = get_hex_bytes(val.prevout.scriptpubkey.as_str()) {
-                    pubkeyhash[2..]
-                        .iter()
-                        .for_each(|val| scriptcode_bytes.push(*val));
+    // scriptsig runs first, the scriptpubkey then runs on the stack it left behind
+    fn verify_legacy_vin(
+        &self,
+        idx: u32,
+        flags: VerifyFlags,
+        precomputed: &PrecomputedTransactionData,
+    ) -> InputVerdict {
+        let vin = &self.vin[idx as usize];
+
+        if flags.contains(VerifyFlags::WITNESS)
+            && vin.witness.as_ref().is_some_and(|witness| !witness.is_empty())
+        {
+            return InputVerdict::Invalid(ScriptError::WitnessUnexpected);
+        }
 
-                    scriptcode_bytes.push(OP_EQUALVERIFY.code);
-                    scriptcode_bytes.push(OP_CHECKSIG.code)
+        let checker =
+            TransactionSignatureChecker::new(self, idx as usize, vin.prevout.value, precomputed);
+        let mut interpreter = Interpreter::new(
+            vin.prevout.scriptpubkey.as_script(),
+            &checker,
+            SigVersion::Base,
+            flags,
+        );
+        let result = interpreter
+            .exec_script(vin.scriptsig.as_script())
+            .and_then(|_| interpreter.exec_all())
+            .and_then(|top| {
+                // the result has to be the only thing left
+                if flags.contains(VerifyFlags::CLEANSTACK)
+                    && cast_to_bool(&top)
+                    && !interpreter.stack.is_empty()
+                {
+                    Err(ScriptError::CleanStack)
+                } else {
+                    Ok(top)
                 }
+            });
+        InputVerdict::from_result(result)
+    }
 
-                // amount
-                let amount_bytes = val.prevout.value.to_le_bytes();
+    fn verify_p2wpkh_vin(
+        &self,
+        idx: u32,
+        flags: VerifyFlags,
+        precomputed: &PrecomputedTransactionData,
+    ) -> InputVerdict {
+        let vin = &self.vin[idx as usize];
+
+        // native witness programs must come with an empty scriptsig
+        if !vin.scriptsig.is_empty() {
+            return InputVerdict::Invalid(ScriptError::WitnessMalleated);
+        }
 
-                // sequence
-                let sequence_bytes = val.sequence.to_le_bytes();
+        // exactly a signature and a public key
+        let witness = match vin.witness.as_ref() {
+            Some(witness) if witness.len() == 2 => witness,
+            _ => return InputVerdict::Invalid(ScriptError::WitnessProgramMismatch),
+        };
+
+        // prepare the instruction, the classifier already made sure that
+        // the pubkeyhash is of 20 bytes
+        let pubkeyhash = &vin.prevout.scriptpubkey.as_bytes()[2..];
+
+        let mut instruction = vec![
+            OP_DUP.code,
+            OP_HASH160.code,
+            OP_PUSHBYTES.code + 0x014 - 0x01,
+        ];
+
+        pubkeyhash.iter().for_each(|val| instruction.push(*val));
+
+        instruction.push(OP_EQUALVERIFY.code);
+        instruction.push(OP_CHECKSIG.code);
+
+        let checker =
+            TransactionSignatureChecker::new(self, idx as usize, vin.prevout.value, precomputed);
+        let mut interpreter = Interpreter::new(
+            Script::new(&instruction),
+            &checker,
+            SigVersion::WitnessV0,
+            flags,
+        );
+
+        for item in witness.iter() {
+            if interpreter.stack.push(item.clone()).is_err() {
+                return InputVerdict::Invalid(ScriptError::StackOverflow);
+            }
+        }
 
-                (
-                    cur_txid_vout_bytes,
-                    scriptcode_bytes,
-                    amount_bytes,
-                    sequence_bytes,
-                )
-            } else {
-                // can we do better coz this is deadcode
-                let zero_u32: u32 = 0x00;
-                let zero_u64: u64 = 0x00;
-                (
-                    vec![],
-                    vec![],
-                    zero_u64.to_le_bytes(),
-                    zero_u32.to_le_bytes(),
-                )
-            };
+        InputVerdict::from_result(interpreter.exec_all())
+    }
 
-        let mut vout_bytes: Vec<u8> = Vec::new();
+    // witness programs which no soft fork has given a meaning yet
+    fn verify_upgradable_witness_vin(
+        &self,
+        idx: u32,
+        version: u8,
+        program_len: usize,
+        flags: VerifyFlags,
+    ) -> InputVerdict {
+        if !self.vin[idx as usize].scriptsig.is_empty() {
+            return InputVerdict::Invalid(ScriptError::WitnessMalleated);
+        }
 
-        self.vout.iter().for_each(|vout| {
-            // push amout in little endian format
-            let amount_bytes: Vec<u8> = vout.value.to_le_bytes().to_vec();
-            amount_bytes.iter().for_each(|val| vout_bytes.push(*val));
+        // v0 only has meaning for 20 and 32 byte programs, anything else is invalid
+        if version == 0 {
+            return InputVerdict::Invalid(ScriptError::WitnessProgramWrongLength);
+        }
 
-            // push scriptsig size in compact size format
-            let scriptsigsize_len_bytes: Vec<u8> =
-                get_compact_size_bytes(&((vout.scriptpubkey.len() / 2) as u64));
-            scriptsigsize_len_bytes
-                .iter()
-                .for_each(|val| vout_bytes.push(*val));
+        // consensus lets anyone spend these so future soft forks can give them a
+        // meaning, policy refuses to relay them until that happens
+        if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_WITNESS_PROGRAM) {
+            return InputVerdict::Invalid(ScriptError::DiscourageUpgradableWitnessProgram);
+        }
 
-            // push the actual scriptsig
-            let scripitsig_bytes: Vec<u8> = get_hex_bytes(&vout.scriptpubkey).unwrap();
-            scripitsig_bytes
-                .iter()
-                .for_each(|val| vout_bytes.push(*val));
-        });
+        InputVerdict::AnyoneCanSpend {
+            version,
+            program_len,
+        }
+    }
 
-        let vout_hash = double_hash256(&vout_bytes);
+    /// Lists every prevout and output whose json label disagrees with its script bytes
+    pub fn script_label_mismatches(&self) -> Vec<ScriptLabelMismatch> {
+        let prevouts = self
+            .vin
+            .iter()
+            .enumerate()
+            .map(|(idx, vin)| (format!("vin {}", idx), &vin.prevout));
+        let outputs = self
+            .vout
+            .iter()
+            .enumerate()
+            .map(|(idx, vout)| (format!("vout {}", idx), vout));
+
+        prevouts
+            .chain(outputs)
+            .filter_map(|(location, pubkey)| {
+                let derived = pubkey.classify();
+                if derived.matches_label(&pubkey.scriptpubkey_type) {
+                    None
+                } else {
+                    Some(ScriptLabelMismatch {
+                        location,
+                        label: pubkey.scriptpubkey_type.clone(),
+                        derived,
+                    })
+                }
+            })
+            .collect()
+    }
 
-        let locktime_bytes = self.locktime.to_le_bytes();
+    /// Lists every prevout and output whose json address is not the one of its script
+    /// on `network`
+    pub fn address_mismatches(&self, network: Network) -> Vec<AddressMismatch> {
+        let prevouts = self
+            .vin
+            .iter()
+            .enumerate()
+            .map(|(idx, vin)| (format!("vin {}", idx), &vin.prevout));
+        let outputs = self
+            .vout
+            .iter()
+            .enumerate()
+            .map(|(idx, vout)| (format!("vout {}", idx), vout));
+
+        prevouts
+            .chain(outputs)
+            .filter_map(|(location, pubkey)| {
+                let derived = Address::from_script(pubkey.scriptpubkey.as_script(), network)
+                    .map(|address| address.to_string());
+                if derived == pubkey.scriptpubkey_address {
+                    None
+                } else {
+                    Some(AddressMismatch {
+                        location,
+                        address: pubkey.scriptpubkey_address.clone(),
+                        derived,
+                    })
+                }
+            })
+            .collect()
+    }
 
-        let mut raw_bytes: Vec<u8> = Vec::new();
+    /// Hash of the transaction without witness, hashed again on every call as the
+    /// fields can change at any time
+    pub fn txid(&self) -> Txid {
+        Txid::from_byte_array(hash_bytes(&self.get_raw_bytes(false)))
+    }
 
-        // let debug: String = scriptcode_bytes.iter().map(|x| format!("{:02x}", x)).collect();
-        // println!("{} {}", debug, debug.len());
+    /// Hash of the transaction with witness, the same bytes as the txid for
+    /// transactions without one
+    pub fn wtxid(&self) -> Wtxid {
+        if self.has_witness() {
+            Wtxid::from_byte_array(hash_bytes(&self.get_raw_bytes(true)))
+        } else {
+            Wtxid::from_byte_array(self.txid().to_byte_array())
+        }
+    }
 
-        // preimage = version + hash256(inputs) + hash256(sequences) + input +
-        // scriptcode + amount + sequence + hash256(outputs) + locktime
-        version_bytes.iter().for_each(|x| raw_bytes.push(*x));
-        txid_vout_hash.iter().for_each(|x| raw_bytes.push(*x));
-        sequence_hash.iter().for_each(|x| raw_bytes.push(*x));
-        cur_txid_vout_bytes.iter().for_each(|x| raw_bytes.push(*x));
-        get_compact_size_bytes(&(scriptcode_bytes.len() as u64))
-            .iter()
-            .for_each(|x| raw_bytes.push(*x));
-        scriptcode_bytes.iter().for_each(|x| raw_bytes.push(*x));
-        amount_bytes.iter().for_each(|x| raw_bytes.push(*x));
-        sequence_bytes.iter().for_each(|x| raw_bytes.push(*x));
-        vout_hash.iter().for_each(|x| raw_bytes.push(*x));
-        locktime_bytes.iter().for_each(|x| raw_bytes.push(*x));
+    pub fn get_weight(&self) -> usize {
+        // these are the fields that will directly go with x4 multiplier
+        let raw_tx = self.get_raw_bytes(false);
+        let raw_tx_with_witness = self.get_raw_bytes(self.has_witness());
 
-        raw_bytes
+        3 * raw_tx.len() +  raw_tx_with_witness.len()
     }
 
-    pub fn get_raw_tx_for_vin(&self, idx: u32) -> Vec<u8> {
-        let parsed_type = self
-            .vin
-            .get(idx as usize)
-            .unwrap()
-            .prevout
-            .scriptpubkey_type
-            .parse::<PubkeyType>();
-        match parsed_type.unwrap() {
-            PubkeyType::P2WSH | PubkeyType::P2WPKH => {
-                self.serialize_witness_transaction(idx)
-            }
-            _ => self.get_raw_tx_for_legacy_tx(idx),
+    pub fn assign_weight(&mut self) {
+        self.weight = Some(self.get_weight());
+    }
+
+    /// Sigops of the scriptSigs and output scripts, counted without looking at prevouts
+    pub fn legacy_sigop_count(&self) -> usize {
+        let scriptsigs = self.vin.iter().map(|vin| &vin.scriptsig);
+        let scriptpubkeys = self.vout.iter().map(|vout| &vout.scriptpubkey);
+        scriptsigs
+            .chain(scriptpubkeys)
+            .map(|script| script.as_script().sigop_count(false))
+            .sum()
+    }
+
+    /// Sigops of the redeem scripts of P2SH spends
+    pub fn p2sh_sigop_count(&self) -> usize {
+        if self.is_coinbase() {
+            return 0;
         }
+        self.vin
+            .iter()
+            .filter(|vin| vin.prevout.classify() == ScriptTemplate::P2SH)
+            .filter_map(|vin| vin.scriptsig.as_script().last_push())
+            .map(|redeem_script| Script::new(redeem_script).sigop_count(true))
+            .sum()
     }
 
-    pub fn validate_transacation(&self) -> bool {
-        let mut success = true;
-
-        for (idx, vin) in self.vin.iter().enumerate() {
-            if let Ok(parsed_enum) = vin.prevout.scriptpubkey_type.parse::<PubkeyType>() {
-                match parsed_enum {
-                    PubkeyType::P2PKH => {
-                        // prepare the instruction
-                        let mut instructions: Vec<u8> = Vec::new();
-                        if let Ok(scripitsig_bytes) = get_hex_bytes(vin.scriptsig.as_ref()) {
-                            scripitsig_bytes
-                                .iter()
-                                .for_each(|val| instructions.push(*val));
-                        }
-
-                        // push the scriptpubkey
-                        if let Ok(scriptpubkey_bytes) = get_hex_bytes(&vin.prevout.scriptpubkey) {
-                            scriptpubkey_bytes
-                                .iter()
-                                .for_each(|val| instructions.push(*val));
-                        }
-
-                        let mut interpreter = Interpreter::new(&instructions, idx as u32, self);
-                        if let Some(result) = interpreter.exec_all() {
-                            success &= result.len() == 1 && result[0] == 0x01;
-                        }
-                    }
-                    PubkeyType::P2WPKH => {
-                        // prepare the instruction
-                        let pubkeyhash =
-                            if let Ok(scriptpubkey) = get_hex_bytes(&vin.prevout.scriptpubkey) {
-                                scriptpubkey[2..].to_vec()
-                            } else {
-                                vec![]
-                            };
-
-                        // basic sanity check if the pubkeyhash is of 20 bytes
-                        assert_eq!(pubkeyhash.len(), 20);
-
-                        let mut instruction = vec![
-                            OP_DUP.code,
-                            OP_HASH160.code,
-                            OP_PUSHBYTES.code + 0x014 - 0x01,
-                        ];
-
-                        pubkeyhash.iter().for_each(|val| instruction.push(*val));
-
-                        instruction.push(OP_EQUALVERIFY.code);
-                        instruction.push(OP_CHECKSIG.code);
-
-                        let mut interpreter = Interpreter::new(&instruction, idx as u32, self);
-
-                        vin.witness.as_ref().unwrap().iter().for_each(|val| {
-                            if let Ok(bytes) = get_hex_bytes(val) {
-                                interpreter.stack.push(bytes);
-                            }
-                        });
-
-                        if let Some(result) = interpreter.exec_all() {
-                            success &= result.len() == 1 && result[0] == 0x01;
-                        }
-                    },
-                    _ => {
-                        success = false;
-                    }
-                }
-            }
+    /// Sigops of witness v0 spends, native or nested in P2SH. Other witness versions
+    /// have none
+    pub fn witness_sigop_count(&self) -> usize {
+        if self.is_coinbase() {
+            return 0;
         }
+        self.vin.iter().map(Vin::witness_sigop_count).sum()
+    }
 
-        success
+    /// Sigop cost as limited by MAX_BLOCK_SIGOPS_COST, same as GetTransactionSigOpCost
+    /// in bitcoin core
+    pub fn get_sigop_cost(&self) -> usize {
+        (self.legacy_sigop_count() + self.p2sh_sigop_count()) * WITNESS_SCALE_FACTOR
+            + self.witness_sigop_count()
     }
 
-    pub fn assign_weight(&mut self) {
-        // these are the fields that will directly go with x4 multiplier
-        let raw_tx = self.get_raw_bytes(false);
-        let raw_tx_with_witness = self.get_raw_bytes(true);
+    pub fn assign_sigop_cost(&mut self) {
+        self.sigop_cost = Some(self.get_sigop_cost());
+    }
 
-        let weight = 3 * raw_tx.len() +  raw_tx_with_witness.len();
+    // left empty when the amounts are out of range or the outputs spend more than the
+    // inputs, check_tx_inputs rejects those
+    pub fn assign_tx_fee(&mut self) {
+        let vin_amount = checked_money_sum(self.vin.iter().map(|vin| vin.prevout.value));
+        let vout_amount = checked_money_sum(self.vout.iter().map(|vout| vout.value));
 
-        self.weight = Some(weight);
+        self.tx_fee = match (vin_amount, vout_amount) {
+            (Some(vin_amount), Some(vout_amount)) => vin_amount.checked_sub(vout_amount),
+            _ => None,
+        };
     }
+}
 
-    pub fn assign_tx_fee(&mut self) {
-        let vin_amount: u64 = self.vin.iter().map(|vin| vin.prevout.value).sum();
-        let vout_amount: u64 = self.vout.iter().map(|vout| vout.value).sum();
+// the wire format, with the witnesses for segwit transactions
+impl Encodable for Transaction {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.encode_with(writer, self.has_witness())
+    }
+}
 
-        if vin_amount > vout_amount {
-            self.tx_fee = Some(vin_amount - vout_amount);
+impl Decodable for Transaction {
+    fn consensus_decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DecodeError> {
+        let version = u32::consensus_decode(reader)?;
+
+        // an empty input list followed by a non zero flag byte is the segwit marker
+        let mut vin: Vec<Vin> = Decodable::consensus_decode(reader)?;
+        let mut flags = 0;
+        let mut vout: Vec<Pubkey> = Vec::new();
+        if vin.is_empty() {
+            flags = u8::consensus_decode(reader)?;
+            if flags != 0 {
+                vin = Decodable::consensus_decode(reader)?;
+                vout = Decodable::consensus_decode(reader)?;
+            }
+        } else {
+            vout = Decodable::consensus_decode(reader)?;
         }
+
+        let has_witness = flags & 0x01 != 0;
+        if has_witness {
+            flags ^= 0x01;
+            for input in vin.iter_mut() {
+                input.witness = Some(Witness::consensus_decode(reader)?);
+            }
+            let is_empty = vin
+                .iter()
+                .all(|input| input.witness.as_ref().is_some_and(|items| items.is_empty()));
+            if is_empty {
+                return Err(DecodeError::SuperfluousWitness);
+            }
+        }
+        if flags != 0 {
+            return Err(DecodeError::UnknownOptionalData(flags));
+        }
+
+        let locktime = u32::consensus_decode(reader)?;
+
+        let mut tx = Transaction {
+            sanity_hash: None,
+            version,
+            locktime,
+            vin,
+            vout,
+            weight: None,
+            sigop_cost: None,
+            tx_fee: None,
+        };
+        tx.assign_weight();
+        Ok(tx)
     }
 }
@@ -1,14 +1,14 @@
-use std::{collections::HashSet, fs, time::{SystemTime, UNIX_EPOCH}};
+use std::{collections::{HashMap, HashSet}, fs::{self, File}, io::{self, BufWriter}, time::{SystemTime, UNIX_EPOCH}};
 
 use hex_literal::hex;
 use num_bigint::BigUint;
 extern crate rand;
 
-use rand::{thread_rng, Rng, seq::SliceRandom};
+use rand::seq::SliceRandom;
 
 
 
-use crate::{debug, debug_hex, hash_utils::double_hash256, merkle::{merkleroot, prepare_merkle_root}, opcodes::all_opcodes::{OP_PUSHBYTES, OP_RETURN}, str_utils::get_hex_bytes, transaction::{Pubkey, Transaction, Vin}, hex_str, MAX_WEIGHT_ALLOWED};
+use crate::{address::{Address, Network}, block::{BlockContext, BlockHeader}, debug, debug_hex, hash_types::{BlockHash, Txid}, hash_utils::double_hash256, merkle::{merkleroot, prepare_merkle_root}, opcodes::all_opcodes::{OP_PUSHBYTES, OP_RETURN}, script::ScriptBuf, transaction::{EnrichedTransaction, OutPoint, Pubkey, Transaction, Vin, Witness}, MAX_BLOCK_SIGOPS_COST, MAX_WEIGHT_ALLOWED};
 
 pub fn get_txs() -> Vec<Transaction> {
     let directory = "mempool/";
@@ -31,19 +31,40 @@ pub fn get_txs() -> Vec<Transaction> {
 
                 val.sanity_hash = Some(String::from(&json_path[start_index..end_index]));
 
-                // check if the current transaction is segwit
-                let pubkey_types: HashSet<String> = val
-                    .vin
-                    .iter()
-                    .map(|vin| vin.prevout.scriptpubkey_type.clone())
-                    .collect();
-                val.is_segwit =
-                    Some(pubkey_types.contains("v0_p2wpkh") || pubkey_types.contains("v0_p2wsh"));
+                if let Err(err) = val.check_transaction() {
+                    println!("{}: {}", &json_path[start_index..end_index], err);
+                    continue;
+                }
+
+                // the labels come from the feed, report the ones the scripts disagree
+                // with. Validation only looks at the script bytes, so the transaction
+                // is kept
+                for mismatch in val.script_label_mismatches().iter() {
+                    println!(
+                        "{}: {} labelled {} but script is {:?}",
+                        &json_path[start_index..end_index],
+                        mismatch.location,
+                        mismatch.label,
+                        mismatch.derived
+                    );
+                }
+
+                // same for the addresses, the script is what gets paid to
+                for mismatch in val.address_mismatches(Network::Bitcoin).iter() {
+                    println!(
+                        "{}: {} has address {:?} but script is {:?}",
+                        &json_path[start_index..end_index],
+                        mismatch.location,
+                        mismatch.address,
+                        mismatch.derived
+                    );
+                }
 
                 txs.push(val);
             }
+            // e.g. malformed hex in a script or witness, only this transaction is dropped
             Err(err) => {
-                println!("{:?}", err);
+                println!("{}: {}", path.as_ref().unwrap().path().display(), err);
             }
         }
     }
@@ -51,88 +72,126 @@ pub fn get_txs() -> Vec<Transaction> {
     txs
 }
 
+// a processed mempool as one json array of enriched transactions, for the archive and
+// the dashboards
+pub fn archive_mempool(txs: &[Transaction], path: &str) -> io::Result<()> {
+    let enriched: Vec<EnrichedTransaction> = txs.iter().map(Transaction::enriched).collect();
+    let writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(writer, &enriched).map_err(io::Error::from)
+}
 
 pub fn remove_double_spending_tx(txs: &mut [Transaction]) -> Vec<&Transaction> {
-    let mut used_tx: HashSet<String> = HashSet::new();
+    let mut used_tx: HashSet<OutPoint> = HashSet::new();
     let filtered_txs: Vec<&Transaction> = txs
         .iter()
-        .map(|tx| {
+        .filter(|tx| {
             let mut should_accept: bool = true;
 
             tx.vin.iter().for_each(|vin| {
-                let vout_str = vin.vout.to_string();
-
                 // check if txid#vout is already used in previously selected
-                // transaction
-                let key = vin.txid.clone() + "#" + vout_str.as_str();
-
-                should_accept &= used_tx.get(&key).is_none();
-
-                // push the txid#vout in the map
-                used_tx.insert(vin.txid.clone() + "#" + vout_str.as_str());
+                // transaction, and remember it otherwise
+                should_accept &= used_tx.insert(vin.outpoint());
             });
 
-            // tx.vout.iter().enumerate().for_each(|(idx, _)| {
-            //     let key = tx.txid.as_ref().unwrap().clone() + "#" + idx.to_string().as_str();
-            //     used_tx.insert(key);
-            // });
-
-            if should_accept {
-                Some(tx)
-            } else {
-                None
-            }
+            should_accept
         })
-        .flatten()
         .collect();
     filtered_txs
 }
 
+// the block each mempool transaction confirms in when it is spent by another one of
+// the mempool, i.e. the block being built
+pub fn mempool_parents(
+    txs: &[Transaction],
+    context: &BlockContext,
+) -> HashMap<Txid, BlockContext> {
+    txs.iter().map(|tx| (tx.txid(), *context)).collect()
+}
+
+// locktimes and BIP68 relative locks are checked against the block being built, the
+// transactions which are still locked are reported and left out. Relative locks on
+// parents outside `parents` cannot be checked, they are reported and the transaction
+// is kept like the mempool kept it
+pub fn remove_non_final_txs<'a>(
+    txs: &[&'a Transaction],
+    context: &BlockContext,
+    parents: &HashMap<Txid, BlockContext>,
+) -> Vec<&'a Transaction> {
+    txs.iter()
+        .filter(|tx| {
+            if !tx.is_final(context.height, context.median_time_past) {
+                println!(
+                    "{}: non-final, locktime {} at height {} and median time past {}",
+                    tx.txid(),
+                    tx.locktime,
+                    context.height,
+                    context.median_time_past
+                );
+                return false;
+            }
+            if !tx.check_sequence_locks(context, parents) {
+                println!(
+                    "{}: non-BIP68-final at height {} and median time past {}",
+                    tx.txid(),
+                    context.height,
+                    context.median_time_past
+                );
+                return false;
+            }
+            for n in tx.unchecked_sequence_locks(parents) {
+                println!(
+                    "{}: relative lock {:#x} of vin {} unchecked, parent {} not in the snapshot",
+                    tx.txid(),
+                    tx.vin[n].sequence,
+                    n,
+                    tx.vin[n].txid
+                );
+            }
+            true
+        })
+        .copied()
+        .collect()
+}
+
+// the block reward and the fees are paid to this address
+pub const COINBASE_PAYOUT_ADDRESS: &str = "1Nh7uHdvY6fNwtQtM1G5EZAFPLC33B59rB";
+
 pub fn prepare_coinbase_tx(txs: &[&Transaction]) -> Transaction {
-    let mut fees: u64 = 0;
-    for tx in txs.iter() {
-        let vin_amount: u64 = tx.vin.iter().map(|vin| vin.prevout.value).sum();
-        let vout_amount: u64 = tx.vout.iter().map(|vout| vout.value).sum();
-
-        // just making sure that I get something :) as a miner
-        assert!(vin_amount > vout_amount);
-        fees += vin_amount - vout_amount;
-    }
+    // check_tx_inputs made sure every fee exists and is within the money range
+    let fees: u64 = txs.iter().filter_map(|tx| tx.tx_fee).sum();
     debug!(fees);
 
     let mut wtxids: Vec<Vec<u8>> =
         vec![hex!("0000000000000000000000000000000000000000000000000000000000000000").to_vec()];
 
     txs.iter().for_each(|tx| {
-        if let Ok(mut wtxid_bytes) = get_hex_bytes(tx.wtxid.as_ref().unwrap()) {
-            wtxid_bytes.reverse();
-            wtxids.push(wtxid_bytes);
-        }
+        wtxids.push(tx.wtxid().as_byte_array().to_vec());
     });
 
     let wtxid_merkle_root = merkleroot(wtxids).first().unwrap().clone();
 
     let coinbase_vin = Vin {
         vout: 4294967295,
-        txid: String::from("0000000000000000000000000000000000000000000000000000000000000000"),
-        witness: Some(vec![String::from(
-            "0000000000000000000000000000000000000000000000000000000000000000",
-        )]),
+        txid: Txid::all_zeros(),
+        // the witness reserved value
+        witness: Some(Witness(vec![vec![0u8; 32]])),
         prevout: Pubkey {
             value: 0,
             scriptpubkey_asm: String::from(""),
-            scriptpubkey: String::from(""),
+            scriptpubkey: ScriptBuf::new(),
             scriptpubkey_type: String::from(""),
             scriptpubkey_address: Some(String::from("")),
         },
         // copied height  of 31e9370f45eb48f6f52ef683b0737332f09f1cead75608021185450422ec1a71
-        scriptsig: String::from(
-            "03233708184d696e656420627920416e74506f6f6c373946205b8160a4256c0000946e0100",
+        scriptsig: ScriptBuf::from_bytes(
+            hex!("03233708184d696e656420627920416e74506f6f6c373946205b8160a4256c0000946e0100")
+                .to_vec(),
         ),
         scriptsig_asm: String::from(""),
         sequence: 0xffffffff,
         is_coinbase: true,
         inner_redeemscript_asm: None,
+        inner_witnessscript_asm: None,
     };
 
     let mut scriptpub_key_lock = vec![OP_RETURN.code, OP_PUSHBYTES.code + 0x24 - 1,
@@ -143,106 +202,80 @@ pub fn prepare_coinbase_tx(txs: &[&Transaction]) -> Transaction {
     ];
 
 
-    let mut witness_lock: Vec<u8> = vec![];
-    witness_lock.extend(wtxid_merkle_root.iter());
-    witness_lock.extend(hex!["0000000000000000000000000000000000000000000000000000000000000000"].iter());
+    // commitment = hash256(witness root || witness reserved value)
+    let witness_lock: Vec<u8> = [wtxid_merkle_root.as_slice(), &[0u8; 32]].concat();
 
     let witness_lock_hash = double_hash256(&witness_lock);
 
     scriptpub_key_lock.extend(witness_lock_hash.iter());
 
-    let scriptpubkey_lock_str: String = hex_str!(scriptpub_key_lock);
-
+    let payout = Address::parse(COINBASE_PAYOUT_ADDRESS, Network::Bitcoin)
+        .expect("the payout address is a mainnet address");
     let vout: Vec<Pubkey> = vec![
-        Pubkey {
-            scriptpubkey_address: Some(String::from("")),
-            scriptpubkey_type: String::from("p2pkh"),
-            scriptpubkey: String::from("76a914edf10a7fac6b32e24daa5305c723f3de58db1bc888ac"),
-            scriptpubkey_asm: String::from(""),
-            value: fees + 1250000000,
-        },
+        Pubkey::new(payout.script_pubkey(), fees + 1250000000),
         Pubkey {
             scriptpubkey_address: Some(String::from("")),
             scriptpubkey_type: String::from("OP_RETURN"),
-            scriptpubkey: scriptpubkey_lock_str,
+            scriptpubkey: ScriptBuf::from_bytes(scriptpub_key_lock),
             scriptpubkey_asm: String::from(""),
             value: 0,
         },
     ];
 
     let mut coinbase_tx = Transaction {
-        wtxid: None,
-        txid: None,
         vout,
         vin: vec![coinbase_vin],
-        is_segwit: Some(true),
         version: 0x01,
         locktime: 0x00000000,
         sanity_hash: Some(String::from("none")),
         tx_fee: None,
         weight: None,
+        sigop_cost: None,
     };
 
     coinbase_tx.assign_tx_fee();
     coinbase_tx.assign_weight();
-
-    let coinbase_raw_tx = coinbase_tx.get_raw_bytes(false);
-    let txid = double_hash256(&coinbase_raw_tx);
-
-    let txid_str: String = txid
-        .iter()
-        .rev()
-        .fold(String::new(), |acc, val| format!("{}{:02x}", acc, *val));
-    coinbase_tx.txid = Some(txid_str);
+    coinbase_tx.assign_sigop_cost();
 
     coinbase_tx
 }
 
 
 pub fn prepare_blockheader(txs: &[&Transaction]) -> Vec<u8> {
-    let version_bytes: Vec<u8> = vec![0x00, 0x00, 0x00, 0x04];
-    let prev_block_hash: Vec<u8> =
-        hex!["0000000000000000000000000000000000000000000000000000000000000000"].to_vec();
-    let merkle_root: Vec<u8> = prepare_merkle_root(txs, false);
-    let ts_bytes: Vec<u8> =
-    (SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs() as u32).to_le_bytes().to_vec();
+    let mut header = BlockHeader {
+        version: 0x04000000,
+        prev_blockhash: BlockHash::all_zeros(),
+        merkle_root: prepare_merkle_root(txs, false)
+            .try_into()
+            .expect("merkle root is 32 bytes"),
+        time: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs() as u32,
+        bits: 0x1f00ffff,
+        nonce: 0,
+    };
     let bits: Vec<u8> =
         hex!("0000ffff00000000000000000000000000000000000000000000000000000000").to_vec();
+    let target = BigUint::from_bytes_be(bits.as_slice());
 
-    for i in 0..u32::max_value() {
-        let nonce_bytes: Vec<u8> = i.to_le_bytes().to_vec();
-
-        let mut raw_bytes: Vec<u8> = Vec::new();
+    for i in 0..u32::MAX {
+        header.nonce = i;
 
-        version_bytes.iter().for_each(|x| raw_bytes.push(*x));
-        prev_block_hash.iter().for_each(|x| raw_bytes.push(*x));
+        let block_hash = header.block_hash();
 
-        merkle_root.iter().for_each(|x| raw_bytes.push(*x));
-        ts_bytes.iter().for_each(|x| raw_bytes.push(*x));
-        hex!["1f00ffff"].iter().rev().for_each(|x| raw_bytes.push(*x));
-        nonce_bytes.iter().for_each(|x| raw_bytes.push(*x));
+        let mut hash_bytes = block_hash.to_byte_array();
+        hash_bytes.reverse();
 
-        let mut block_hash = double_hash256(&raw_bytes);
+        let block_hash_str: String = block_hash.to_string();
 
-        block_hash.reverse();
-
-        let block_hash_str: String = hex_str!(block_hash);
-
-        let block_hash = BigUint::from_bytes_be(block_hash.as_slice());
-        let target = BigUint::from_bytes_be(bits.as_slice());
+        let block_hash = BigUint::from_bytes_be(hash_bytes.as_slice());
 
         if block_hash.le(&target) {
             println!("mining successfull with hash: {}", block_hash_str);
-            debug_hex!(version_bytes);
-            debug_hex!(prev_block_hash);
-            debug_hex!(merkle_root);
-            debug_hex!(ts_bytes);
+            debug!(header);
             debug_hex!(bits);
-            debug_hex!(nonce_bytes);
-            return raw_bytes;
+            return header.to_bytes().to_vec();
         }
     }
     vec![]
@@ -263,17 +296,25 @@ pub fn pick_best_transactions<'a> (txs: &'a [&Transaction], rounds: u32) -> Vec<
         let mut transactions_to_consider: Vec<&Transaction> = Vec::new();
 
         let mut weights_filled : u32 = 0;
+        let mut sigops_filled : u32 = 0;
 
         let mut idx = 0;
 
-        while weights_filled + 1000 <= MAX_WEIGHT_ALLOWED {
+        // both the weight and the sigop cost keep some room for the coinbase
+        while weights_filled + 1000 <= MAX_WEIGHT_ALLOWED && sigops_filled + 400 <= MAX_BLOCK_SIGOPS_COST {
             if idx >= shuffled_txs.len() {
                 break;
             }
 
-            if weights_filled + shuffled_txs[idx].weight.unwrap() as u32 + 1000 <= MAX_WEIGHT_ALLOWED {
+            let weight = shuffled_txs[idx].weight.unwrap() as u32;
+            let sigop_cost = shuffled_txs[idx].sigop_cost.unwrap() as u32;
+
+            if weights_filled + weight + 1000 <= MAX_WEIGHT_ALLOWED
+                && sigops_filled + sigop_cost + 400 <= MAX_BLOCK_SIGOPS_COST
+            {
                 transactions_to_consider.push(shuffled_txs[idx]);
-                weights_filled += shuffled_txs[idx].weight.unwrap() as u32;
+                weights_filled += weight;
+                sigops_filled += sigop_cost;
             } else { 
                // do nothing consider the next tx
             }
@@ -283,9 +324,9 @@ pub fn pick_best_transactions<'a> (txs: &'a [&Transaction], rounds: u32) -> Vec<
 
         let miner_fee: u64 = transactions_to_consider.iter().map(|tx| tx.tx_fee.unwrap()).sum();
 
-        let score : f64 = weights_filled as f64 / MAX_WEIGHT_ALLOWED as f64  + miner_fee as f64 / 3000000 as f64;
+        let score : f64 = weights_filled as f64 / MAX_WEIGHT_ALLOWED as f64  + miner_fee as f64 / 3_000_000.0;
 
-        let prev_score : f64 = current_weight as f64 / MAX_WEIGHT_ALLOWED as f64 + current_fee  as f64 / 3000000 as f64;
+        let prev_score : f64 = current_weight as f64 / MAX_WEIGHT_ALLOWED as f64 + current_fee  as f64 / 3_000_000.0;
 
         if score >= prev_score {
             // debug!(score, prev_score);